@@ -6,23 +6,151 @@
  * the LICENSE file in the root of this source tree.
  */
 
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The name of the C SDK library
+const LIB_NAME: &str = "aws-greengrass-core-sdk-c";
+
 fn main() {
-    if cfg!(not(feature = "coverage")) {
-        use std::env;
-        use std::path::PathBuf;
+    // The ipc feature replaces the C SDK, so there is nothing to bind or link
+    if cfg!(any(feature = "coverage", feature = "ipc")) {
+        return;
+    }
+    println!("cargo:rerun-if-changed=wrapper.h");
+
+    let include_dirs = link_sdk();
+    // The prebuilt-bindings feature uses the checked in src/bindings/prebuilt.rs, so libclang isn't needed
+    if cfg!(not(feature = "prebuilt-bindings")) {
+        generate_bindings(&include_dirs);
+    }
+}
+
+/// Builds and installs the C SDK from the source in GGC_SDK_SRC_DIR or vendor/aws-greengrass-core-sdk-c,
+/// returning the directories to search for its header
+#[cfg(feature = "vendored")]
+fn link_sdk() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-env-changed=GGC_SDK_SRC_DIR");
+    let src = env::var_os("GGC_SDK_SRC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+                .join("vendor/aws-greengrass-core-sdk-c")
+        });
+    if !src.join("CMakeLists.txt").exists() {
+        panic!(
+            "The C SDK source was not found in {}. Clone https://github.com/aws/aws-greengrass-core-sdk-c there or set GGC_SDK_SRC_DIR",
+            src.display()
+        );
+    }
+
+    let dst = cmake::Config::new(&src).build();
+    link_search(&dst.join("lib"));
+    link_search(&dst.join("lib64"));
+    println!("cargo:rustc-link-lib={}", LIB_NAME);
+    vec![dst.join("include")]
+}
+
+/// Emits the directives to link an installed C SDK, returning the directories to search for its header.
+///
+/// The SDK is looked for, in order:
+/// 1. In GGC_SDK_DIR_<target> or GGC_SDK_DIR, which contain the lib and include directories
+/// 2. With pkg-config
+/// 3. In the default search paths of the linker, plus the paths of the cross toolchain when cross compiling
+#[cfg(not(feature = "vendored"))]
+fn link_sdk() -> Vec<PathBuf> {
+    if let Some(dir) = target_env_var("GGC_SDK_DIR") {
+        let dir = PathBuf::from(dir);
+        link_search(&dir.join("lib"));
+        println!("cargo:rustc-link-lib={}", LIB_NAME);
+        return vec![dir.join("include")];
+    }
+
+    // pkg-config refuses to cross compile unless PKG_CONFIG_ALLOW_CROSS is set
+    if let Ok(library) = pkg_config::Config::new().probe(LIB_NAME) {
+        return library.include_paths;
+    }
 
-        println!("cargo:rustc-link-lib=aws-greengrass-core-sdk-c");
-        println!("cargo:rerun-if-changed=wrapper.h");
+    let mut include_dirs = vec![];
+    for (lib_dir, include_dir) in cross_search_dirs() {
+        if lib_dir.exists() {
+            link_search(&lib_dir);
+        }
+        if include_dir.exists() {
+            include_dirs.push(include_dir);
+        }
+    }
+    println!("cargo:rustc-link-lib={}", LIB_NAME);
+    include_dirs
+}
+
+fn generate_bindings(include_dirs: &[PathBuf]) {
+    let target = env::var("TARGET").unwrap();
+    let mut builder = bindgen::Builder::default()
+        .header("wrapper.h")
+        .whitelist_function("gg_.*")
+        .whitelist_type("_?gg_.*")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks));
+    if is_cross_compiling() {
+        builder = builder.clang_arg(format!("--target={}", target));
+    }
+    for dir in include_dirs {
+        builder = builder.clang_arg(format!("-I{}", dir.display()));
+    }
+
+    let bindings = builder.generate().expect("Unable to generate c bindings");
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .expect("Unable to write bindings");
+}
 
-        let bindings = bindgen::Builder::default()
-            .header("wrapper.h")
-            .parse_callbacks(Box::new(bindgen::CargoCallbacks))
-            .generate()
-            .expect("Unable to generate c bindings");
+fn link_search(dir: &Path) {
+    println!("cargo:rustc-link-search=native={}", dir.display());
+}
+
+/// Reads the variable for the target, e.g. GGC_SDK_DIR_armv7_unknown_linux_gnueabihf, falling back to the plain variable
+#[cfg(not(feature = "vendored"))]
+fn target_env_var(name: &str) -> Option<String> {
+    let target = env::var("TARGET").unwrap().replace('-', "_");
+    let target_name = format!("{}_{}", name, target);
+    println!("cargo:rerun-if-env-changed={}", target_name);
+    println!("cargo:rerun-if-env-changed={}", name);
+    env::var(target_name).or_else(|_| env::var(name)).ok()
+}
 
-        let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-        bindings
-            .write_to_file(out_path.join("bindings.rs"))
-            .expect("Unable to write bindings");
+fn is_cross_compiling() -> bool {
+    env::var("TARGET").ok() != env::var("HOST").ok()
+}
+
+/// The lib and include directories where cross toolchains and multiarch packages install libraries for the target.
+/// Empty when not cross compiling.
+#[cfg(not(feature = "vendored"))]
+fn cross_search_dirs() -> Vec<(PathBuf, PathBuf)> {
+    if !is_cross_compiling() {
+        return vec![];
     }
+    let triple = gnu_triple(&env::var("TARGET").unwrap());
+    vec![
+        (
+            Path::new("/usr").join(&triple).join("lib"),
+            Path::new("/usr").join(&triple).join("include"),
+        ),
+        (
+            Path::new("/usr/lib").join(&triple),
+            Path::new("/usr/include").join(&triple),
+        ),
+    ]
+}
+
+/// The triple used by GNU toolchains for the rust target, e.g. arm-linux-gnueabihf for armv7-unknown-linux-gnueabihf
+#[cfg(not(feature = "vendored"))]
+fn gnu_triple(target: &str) -> String {
+    let mut parts = target.split('-').filter(|part| *part != "unknown");
+    let arch = match parts.next().unwrap_or_default() {
+        arch if arch.starts_with("arm") => "arm",
+        arch => arch,
+    };
+    let rest: Vec<&str> = parts.collect();
+    format!("{}-{}", arch, rest.join("-"))
 }
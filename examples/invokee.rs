@@ -9,8 +9,7 @@
 //! This is a simple example that will just send a message to an MQTT topic when it is run.
 //!
 //! This should be deployed in conjunction with the invoker example lambda
-use aws_greengrass_core_rust::handler::{Handler, LambdaContext};
-use aws_greengrass_core_rust::lambda::LambdaClient;
+use aws_greengrass_core_rust::handler::{LambdaContext, ResponseHandler};
 use aws_greengrass_core_rust::log as gglog;
 use aws_greengrass_core_rust::runtime::Runtime;
 use aws_greengrass_core_rust::Initializer;
@@ -18,31 +17,21 @@ use log::{error, info, LevelFilter};
 
 struct InvokeeHandler;
 
-impl Handler for InvokeeHandler {
-    fn handle(&self, ctx: LambdaContext) {
+impl ResponseHandler for InvokeeHandler {
+    type Error = String;
+
+    fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
         info!("Received context: {:?}", ctx);
-        match String::from_utf8(ctx.message) {
-            Ok(msg) => {
-                info!("Received event: {}", msg);
-                let reply = format!("{{\"original_msg\": \"{}\" }}", msg);
-                if let Err(e) = LambdaClient::default().send_response(Ok(reply.as_bytes())) {
-                    error!("Error sending response: {}", e);
-                }
-            }
-            Err(e) => {
-                let reply = format!("Could not parse message: {}", e);
-                error!("{}", reply);
-                if let Err(e) = LambdaClient::default().send_response(Err(&reply)) {
-                    error!("Error sending error response: {}", e);
-                }
-            }
-        }
+        let msg = String::from_utf8(ctx.message)
+            .map_err(|e| format!("Could not parse message: {}", e))?;
+        info!("Received event: {}", msg);
+        Ok(format!("{{\"original_msg\": \"{}\" }}", msg).into_bytes())
     }
 }
 
 pub fn main() {
     gglog::init_log(LevelFilter::Info);
-    let runtime = Runtime::default().with_handler(Some(Box::new(InvokeeHandler)));
+    let runtime = Runtime::default().with_response_handler(Some(Box::new(InvokeeHandler)));
     if let Err(e) = Initializer::default().with_runtime(runtime).init() {
         error!("Initialization failed: {}", e);
         std::process::exit(1);
@@ -12,26 +12,31 @@
 //!
 //! See the following guide for long lived functions: https://docs.aws.amazon.com/greengrass/latest/developerguide/long-lived.html
 //!
+//! Requires the async feature: `cargo build --example longlived --features async`
+//!
 //! ## Sample Request
 //! ```shell script
 //! curl -vvvv -H "Content-Type: application/json" -d '{"msg": "hello"}' http://127.0.0.1:5020/
 //! ```
-use aws_greengrass_core_rust::iotdata::IOTDataClient;
+use aws_greengrass_core_rust::iotdata::AsyncIOTDataClient;
 use aws_greengrass_core_rust::log as gglog;
 use aws_greengrass_core_rust::runtime::{Runtime, RuntimeOption};
 use aws_greengrass_core_rust::{GGResult, Initializer};
+use hyper::body::Bytes;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
 use log::{error, info, LevelFilter};
+use std::time::Duration;
 
 const SEND_TOPIC: &str = "longlived/device-sent";
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
 
 async fn serve(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
     match (req.method(), req.uri().path()) {
         // Simply echo the body back to the client.
         (&Method::POST, "/") => {
             let body = hyper::body::to_bytes(req.into_body()).await?;
-            match publish(&body).await {
+            match publish(body).await {
                 Ok(_) => {
                     let mut accepted = Response::default();
                     *accepted.status_mut() = StatusCode::ACCEPTED;
@@ -55,10 +60,14 @@ async fn serve(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
     }
 }
 
-async fn publish(bytes: &[u8]) -> GGResult<()> {
+async fn publish(bytes: Bytes) -> GGResult<()> {
     // convert to a string for logging purposes
-    info!("publishing message of {}", String::from_utf8_lossy(bytes));
-    IOTDataClient::default().publish(SEND_TOPIC, bytes)
+    info!("publishing message of {}", String::from_utf8_lossy(&bytes));
+    // The publish blocks until greengrass responds, so it is run off the executor
+    AsyncIOTDataClient::default()
+        .with_timeout(Some(PUBLISH_TIMEOUT))
+        .publish(SEND_TOPIC, bytes)
+        .await
 }
 
 #[tokio::main]
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! The JSON line formats of published messages and of the events fed to the handler.
+//!
+//! A published message is written as `{"topic": ..., "payload": ..., "timestamp": ...}`.
+//! Payloads that are not UTF-8 are written as `payload_base64` instead.
+//!
+//! An event is read as `{"topic": ..., "payload": ...}`, where the optional topic becomes the subject of the
+//! client context. A `client_context` (base64 encoded JSON) can be specified instead of the topic.
+//! Payloads that are JSON values other than strings are passed to the handler as their JSON.
+//! The handler's response is written back as `{"response": ...}`, `{"error": ...}` or `{}` if there was none.
+use crate::config::Config;
+use crate::{insert_bytes, read_bytes, Failure, Outcome};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes the message to the publish socket if one is configured, otherwise appends it to the publish file
+pub fn publish(config: &Config, topic: &str, payload: &[u8]) -> Outcome {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let mut message = json!({ "topic": topic, "timestamp": timestamp });
+    insert_bytes(&mut message, "payload", payload);
+    let line = format!("{}\n", message);
+
+    let written = match &config.publish_socket {
+        Some(socket) => {
+            UnixStream::connect(socket).and_then(|mut stream| stream.write_all(line.as_bytes()))
+        }
+        None => {
+            if let Some(parent) = config.publish_file.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&config.publish_file)
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+        }
+    };
+    written
+        .map(|_| vec![])
+        .map_err(|e| Failure::unknown(format!("Could not publish to {}: {}", topic, e)))
+}
+
+/// An event to pass to the handler
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub client_context: String,
+    pub payload: Vec<u8>,
+}
+
+impl Event {
+    pub fn parse(line: &str) -> Result<Event, String> {
+        let event: Value =
+            serde_json::from_str(line).map_err(|e| format!("Invalid event: {}", e))?;
+        if !event.is_object() {
+            return Err("Invalid event: expected a JSON object".to_owned());
+        }
+        let client_context = match (event["client_context"].as_str(), event["topic"].as_str()) {
+            (Some(client_context), _) => client_context.to_owned(),
+            (None, Some(topic)) => {
+                base64::encode(json!({ "custom": { "subject": topic } }).to_string())
+            }
+            (None, None) => String::new(),
+        };
+        Ok(Event {
+            client_context,
+            payload: read_bytes(&event, "payload")?,
+        })
+    }
+}
+
+/// The JSON line written back after the handler has handled an event
+pub fn response_line(response: Option<Result<Vec<u8>, String>>) -> String {
+    let mut line = json!({});
+    match response {
+        Some(Ok(bytes)) => insert_bytes(&mut line, "response", &bytes),
+        Some(Err(e)) => line["error"] = json!(e),
+        None => (),
+    }
+    format!("{}\n", line)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::temp_dir;
+
+    #[test]
+    fn test_publish_to_file() {
+        let dir = temp_dir("bus");
+        let config = Config {
+            publish_file: dir.join("nested").join("published.jsonl"),
+            publish_socket: None,
+            ..Config::from_env()
+        };
+        publish(&config, "my/topic", b"hello").unwrap();
+        publish(&config, "my/topic", &[0xff]).unwrap();
+
+        let written = fs::read_to_string(&config.publish_file).unwrap();
+        let lines: Vec<Value> = written
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["topic"], "my/topic");
+        assert_eq!(lines[0]["payload"], "hello");
+        assert_eq!(lines[1]["payload_base64"], "/w==");
+
+        let config = Config {
+            publish_socket: Some(dir.join("missing.sock")),
+            ..config
+        };
+        assert!(publish(&config, "my/topic", b"hello").is_err());
+    }
+
+    #[test]
+    fn test_events() {
+        let event = Event::parse(r#"{"topic": "my/topic", "payload": {"on": true}}"#).unwrap();
+        assert_eq!(event.payload, br#"{"on":true}"#);
+        let context = base64::decode(&event.client_context).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&context).unwrap(),
+            json!({"custom": {"subject": "my/topic"}})
+        );
+        assert!(Event::parse("not json").is_err());
+        assert!(Event::parse("[]").is_err());
+
+        assert_eq!(
+            response_line(Some(Ok(b"ok".to_vec()))),
+            "{\"response\":\"ok\"}\n"
+        );
+        assert_eq!(
+            response_line(Some(Err("failed".to_owned()))),
+            "{\"error\":\"failed\"}\n"
+        );
+        assert_eq!(response_line(None), "{}\n");
+    }
+}
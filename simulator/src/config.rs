@@ -0,0 +1,64 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Configuration of the simulator, read from environment variables
+use std::env;
+use std::path::PathBuf;
+
+/// The directory all other paths default to being in
+pub const STATE_DIR_ENV: &str = "GG_SIM_STATE_DIR";
+/// The function ARN passed to the handler
+pub const FUNCTION_ARN_ENV: &str = "GG_SIM_FUNCTION_ARN";
+/// A Unix socket that published messages are written to. Takes precedence over the publish file.
+pub const PUBLISH_SOCKET_ENV: &str = "GG_SIM_PUBLISH_SOCKET";
+/// A file that published messages are appended to
+pub const PUBLISH_FILE_ENV: &str = "GG_SIM_PUBLISH_FILE";
+/// The directory shadow documents are persisted in
+pub const SHADOW_DIR_ENV: &str = "GG_SIM_SHADOW_DIR";
+/// A JSON file of the secrets that can be requested
+pub const SECRETS_FILE_ENV: &str = "GG_SIM_SECRETS_FILE";
+/// The Unix socket gg_runtime_start listens on for events
+pub const EVENT_SOCKET_ENV: &str = "GG_SIM_EVENT_SOCKET";
+
+const DEFAULT_FUNCTION_ARN: &str = "arn:aws:lambda:us-east-1:000000000000:function:simulated:1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub state_dir: PathBuf,
+    pub function_arn: String,
+    pub publish_socket: Option<PathBuf>,
+    pub publish_file: PathBuf,
+    pub shadow_dir: PathBuf,
+    pub secrets_file: PathBuf,
+    pub event_socket: PathBuf,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    /// Paths that are not set default to locations in the state directory, which defaults to `$TMPDIR/greengrass-sim`.
+    pub fn from_env() -> Self {
+        let state_dir = env::var_os(STATE_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| env::temp_dir().join("greengrass-sim"));
+        let path = |name: &str, default: &str| {
+            env::var_os(name)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| state_dir.join(default))
+        };
+        Config {
+            function_arn: env::var(FUNCTION_ARN_ENV)
+                .unwrap_or_else(|_| DEFAULT_FUNCTION_ARN.to_owned()),
+            publish_socket: env::var_os(PUBLISH_SOCKET_ENV).map(PathBuf::from),
+            publish_file: path(PUBLISH_FILE_ENV, "published.jsonl"),
+            shadow_dir: path(SHADOW_DIR_ENV, "shadows"),
+            secrets_file: path(SECRETS_FILE_ENV, "secrets.json"),
+            event_socket: path(EVENT_SOCKET_ENV, "events.sock"),
+            state_dir,
+        }
+    }
+}
@@ -0,0 +1,707 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! The functions of greengrasssdk.h, exported with their C names
+#![allow(
+    non_upper_case_globals,
+    non_camel_case_types,
+    clippy::missing_safety_doc
+)]
+
+use crate::bus::{self, Event};
+use crate::config::Config;
+use crate::{secret, shadow, Failure, Outcome};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::raw::{c_char, c_void};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::ptr;
+use std::slice;
+use std::thread;
+
+pub type gg_error = u32;
+pub const gg_error_GGE_SUCCESS: gg_error = 0;
+pub const gg_error_GGE_OUT_OF_MEMORY: gg_error = 1;
+pub const gg_error_GGE_INVALID_PARAMETER: gg_error = 2;
+pub const gg_error_GGE_INVALID_STATE: gg_error = 3;
+pub const gg_error_GGE_INTERNAL_FAILURE: gg_error = 4;
+pub const gg_error_GGE_TERMINATE: gg_error = 5;
+
+pub type gg_request_status = u32;
+pub const gg_request_status_GG_REQUEST_SUCCESS: gg_request_status = 0;
+pub const gg_request_status_GG_REQUEST_HANDLED: gg_request_status = 1;
+pub const gg_request_status_GG_REQUEST_UNHANDLED: gg_request_status = 2;
+pub const gg_request_status_GG_REQUEST_UNKNOWN: gg_request_status = 3;
+pub const gg_request_status_GG_REQUEST_AGAIN: gg_request_status = 4;
+
+pub type gg_queue_full_policy_options = u32;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT:
+    gg_queue_full_policy_options = 0;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR:
+    gg_queue_full_policy_options = 1;
+
+pub type gg_log_level = u32;
+pub const gg_log_level_GG_LOG_DEBUG: gg_log_level = 1;
+pub const gg_log_level_GG_LOG_INFO: gg_log_level = 2;
+pub const gg_log_level_GG_LOG_WARN: gg_log_level = 3;
+pub const gg_log_level_GG_LOG_ERROR: gg_log_level = 4;
+pub const gg_log_level_GG_LOG_FATAL: gg_log_level = 5;
+
+pub type gg_invoke_type = u32;
+pub const gg_invoke_type_GG_INVOKE_EVENT: gg_invoke_type = 0;
+pub const gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE: gg_invoke_type = 1;
+
+pub type gg_runtime_opt = u32;
+pub const gg_runtime_opt_GG_RT_OPT_ASYNC: gg_runtime_opt = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_request_result {
+    pub request_status: gg_request_status,
+}
+
+/// The state of a request, the response is read with gg_request_read
+#[derive(Debug, Default)]
+pub struct _gg_request {
+    response: Vec<u8>,
+    read_pos: usize,
+}
+
+pub type gg_request = *mut _gg_request;
+
+#[derive(Debug)]
+pub struct _gg_publish_options {
+    queue_full_policy: gg_queue_full_policy_options,
+}
+
+pub type gg_publish_options = *mut _gg_publish_options;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_lambda_context {
+    pub function_arn: *const c_char,
+    pub client_context: *const c_char,
+}
+
+pub type gg_lambda_handler = Option<unsafe extern "C" fn(cxt: *const gg_lambda_context)>;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_invoke_options {
+    pub function_arn: *const c_char,
+    pub customer_context: *const c_char,
+    pub qualifier: *const c_char,
+    pub type_: gg_invoke_type,
+    pub payload: *const c_void,
+    pub payload_size: usize,
+}
+
+/// The event currently being handled by the handler callback
+struct Invocation {
+    payload: Vec<u8>,
+    read_pos: usize,
+    response: Option<Result<Vec<u8>, String>>,
+}
+
+thread_local! {
+    // Responses can only be written from the thread the handler callback was called on, as with the C SDK
+    static CURRENT_INVOCATION: RefCell<Option<Invocation>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_global_init(_opt: u32) -> gg_error {
+    let _ = fs::create_dir_all(Config::from_env().state_dir);
+    gg_error_GGE_SUCCESS
+}
+
+/// Entries are written to stderr.
+/// gg_log is variadic in C, which stable Rust cannot define, so the format is written as is without its arguments.
+#[no_mangle]
+pub unsafe extern "C" fn gg_log(level: gg_log_level, format: *const c_char) -> gg_error {
+    match c_string(format) {
+        Some(msg) => {
+            write_log(level, &msg);
+            gg_error_GGE_SUCCESS
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_request_init(ggreq: *mut gg_request) -> gg_error {
+    if ggreq.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    *ggreq = Box::into_raw(Box::default());
+    gg_error_GGE_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_request_close(ggreq: gg_request) -> gg_error {
+    if ggreq.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    drop(Box::from_raw(ggreq));
+    gg_error_GGE_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_request_read(
+    ggreq: gg_request,
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    match ggreq.as_mut() {
+        Some(req) => copy_out(
+            &req.response,
+            &mut req.read_pos,
+            buffer,
+            buffer_size,
+            amount_read,
+        ),
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+/// Listens on the event socket and calls the handler for each event received.
+/// Blocks forever unless the async option is specified.
+#[no_mangle]
+pub unsafe extern "C" fn gg_runtime_start(
+    handler: gg_lambda_handler,
+    opt: gg_runtime_opt,
+) -> gg_error {
+    let handler = match handler {
+        Some(handler) => handler,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let config = Config::from_env();
+    // A socket left behind by a previous run would make the bind fail
+    let _ = fs::remove_file(&config.event_socket);
+    if let Some(parent) = config.event_socket.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let listener = match UnixListener::bind(&config.event_socket) {
+        Ok(listener) => listener,
+        Err(e) => {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!(
+                    "Could not listen on {}: {}",
+                    config.event_socket.display(),
+                    e
+                ),
+            );
+            return gg_error_GGE_INTERNAL_FAILURE;
+        }
+    };
+    write_log(
+        gg_log_level_GG_LOG_INFO,
+        &format!("Listening for events on {}", config.event_socket.display()),
+    );
+
+    if opt & gg_runtime_opt_GG_RT_OPT_ASYNC != 0 {
+        let spawn_result = thread::Builder::new()
+            .name("gg-sim-runtime".to_owned())
+            .spawn(move || serve(listener, config.function_arn, handler));
+        match spawn_result {
+            Ok(_) => gg_error_GGE_SUCCESS,
+            Err(_) => gg_error_GGE_INTERNAL_FAILURE,
+        }
+    } else {
+        serve(listener, config.function_arn, handler)
+    }
+}
+
+/// Connections are served one at a time, so the handler is never called concurrently as with the C SDK
+fn serve(
+    listener: UnixListener,
+    function_arn: String,
+    handler: unsafe extern "C" fn(*const gg_lambda_context),
+) -> ! {
+    let function_arn_c = CString::new(function_arn).unwrap_or_default();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = serve_connection(stream, &function_arn_c, handler) {
+                    write_log(
+                        gg_log_level_GG_LOG_ERROR,
+                        &format!("Event connection failed: {}", e),
+                    );
+                }
+            }
+            Err(e) => write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!("Could not accept event connection: {}", e),
+            ),
+        }
+    }
+}
+
+/// Handles each line received as an event, writing a line back with the response
+fn serve_connection(
+    stream: UnixStream,
+    function_arn: &CStr,
+    handler: unsafe extern "C" fn(*const gg_lambda_context),
+) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match Event::parse(&line) {
+            Ok(event) => handle_event(event, function_arn, handler),
+            Err(e) => Some(Err(e)),
+        };
+        writer.write_all(bus::response_line(response).as_bytes())?;
+    }
+    Ok(())
+}
+
+fn handle_event(
+    event: Event,
+    function_arn: &CStr,
+    handler: unsafe extern "C" fn(*const gg_lambda_context),
+) -> Option<Result<Vec<u8>, String>> {
+    let Event {
+        client_context,
+        payload,
+    } = event;
+    let client_context_c = CString::new(client_context).unwrap_or_default();
+    let context = gg_lambda_context {
+        function_arn: function_arn.as_ptr(),
+        client_context: client_context_c.as_ptr(),
+    };
+    CURRENT_INVOCATION.with(|rc| {
+        *rc.borrow_mut() = Some(Invocation {
+            payload,
+            read_pos: 0,
+            response: None,
+        })
+    });
+
+    unsafe { handler(&context) };
+
+    CURRENT_INVOCATION
+        .with(|rc| rc.borrow_mut().take())
+        .and_then(|invocation| invocation.response)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_lambda_handler_read(
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    CURRENT_INVOCATION.with(|rc| match rc.borrow_mut().as_mut() {
+        Some(invocation) => copy_out(
+            &invocation.payload,
+            &mut invocation.read_pos,
+            buffer,
+            buffer_size,
+            amount_read,
+        ),
+        None => gg_error_GGE_INVALID_STATE,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_lambda_handler_write_response(
+    response: *const c_void,
+    response_size: usize,
+) -> gg_error {
+    respond(Ok(bytes(response, response_size).to_vec()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_lambda_handler_write_error(error_message: *const c_char) -> gg_error {
+    match c_string(error_message) {
+        Some(error_message) => respond(Err(error_message)),
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+/// Records the response for the current invocation, only one response can be written
+fn respond(response: Result<Vec<u8>, String>) -> gg_error {
+    CURRENT_INVOCATION.with(|rc| match rc.borrow_mut().as_mut() {
+        Some(invocation) if invocation.response.is_none() => {
+            invocation.response = Some(response);
+            gg_error_GGE_SUCCESS
+        }
+        _ => gg_error_GGE_INVALID_STATE,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_get_secret_value(
+    ggreq: gg_request,
+    secret_id: *const c_char,
+    version_id: *const c_char,
+    version_stage: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let secret_id = match c_string(secret_id) {
+        Some(secret_id) => secret_id,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let version_id = c_string(version_id);
+    let version_stage = c_string(version_stage);
+    let outcome = secret::get_secret_value(
+        &Config::from_env(),
+        &secret_id,
+        version_id.as_deref(),
+        version_stage.as_deref(),
+    );
+    complete(ggreq, result, outcome)
+}
+
+/// Invoking other lambdas is not simulated, an unhandled error response is returned
+#[no_mangle]
+pub unsafe extern "C" fn gg_invoke(
+    ggreq: gg_request,
+    opts: *const gg_invoke_options,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let function_arn = match opts.as_ref().and_then(|opts| c_string(opts.function_arn)) {
+        Some(function_arn) => function_arn,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let failure = Failure {
+        status: gg_request_status_GG_REQUEST_UNHANDLED,
+        code: 501,
+        message: format!(
+            "The simulator cannot invoke other lambdas: {}",
+            function_arn
+        ),
+    };
+    complete(ggreq, result, Err(failure))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_publish_options_init(opts: *mut gg_publish_options) -> gg_error {
+    if opts.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    *opts = Box::into_raw(Box::new(_gg_publish_options {
+        queue_full_policy: gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT,
+    }));
+    gg_error_GGE_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_publish_options_free(opts: gg_publish_options) -> gg_error {
+    if opts.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    drop(Box::from_raw(opts));
+    gg_error_GGE_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_publish_options_set_queue_full_policy(
+    opts: gg_publish_options,
+    policy: gg_queue_full_policy_options,
+) -> gg_error {
+    match opts.as_mut() {
+        Some(opts) => {
+            opts.queue_full_policy = policy;
+            gg_error_GGE_SUCCESS
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+/// There is a single destination for published messages, so the queue full policy has no effect
+#[no_mangle]
+pub unsafe extern "C" fn gg_publish_with_options(
+    ggreq: gg_request,
+    topic: *const c_char,
+    payload: *const c_void,
+    payload_size: usize,
+    opts: gg_publish_options,
+    result: *mut gg_request_result,
+) -> gg_error {
+    if opts.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    gg_publish(ggreq, topic, payload, payload_size, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_publish(
+    ggreq: gg_request,
+    topic: *const c_char,
+    payload: *const c_void,
+    payload_size: usize,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let topic = match c_string(topic) {
+        Some(topic) => topic,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let outcome = bus::publish(&Config::from_env(), &topic, bytes(payload, payload_size));
+    complete(ggreq, result, outcome)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_get_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    match c_string(thing_name) {
+        Some(thing_name) => {
+            let outcome = shadow::get(&Config::from_env(), &thing_name);
+            complete(ggreq, result, outcome)
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_update_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    update_payload: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    match (c_string(thing_name), c_string(update_payload)) {
+        (Some(thing_name), Some(update_payload)) => {
+            let outcome = shadow::update(&Config::from_env(), &thing_name, &update_payload);
+            complete(ggreq, result, outcome)
+        }
+        _ => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gg_delete_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    match c_string(thing_name) {
+        Some(thing_name) => {
+            let outcome = shadow::delete(&Config::from_env(), &thing_name);
+            complete(ggreq, result, outcome)
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+/// Stores the outcome of a request so it can be read with gg_request_read and sets the request status
+unsafe fn complete(
+    ggreq: gg_request,
+    result: *mut gg_request_result,
+    outcome: Outcome,
+) -> gg_error {
+    let (status, body) = match outcome {
+        Ok(body) => (gg_request_status_GG_REQUEST_SUCCESS, body),
+        Err(failure) => {
+            if failure.status == gg_request_status_GG_REQUEST_UNKNOWN {
+                write_log(gg_log_level_GG_LOG_ERROR, &failure.message);
+            }
+            (failure.status, failure.body())
+        }
+    };
+    if let Some(req) = ggreq.as_mut() {
+        req.response = body;
+        req.read_pos = 0;
+    }
+    if let Some(result) = result.as_mut() {
+        result.request_status = status;
+    }
+    gg_error_GGE_SUCCESS
+}
+
+/// Copies the unread part of the source into the buffer
+unsafe fn copy_out(
+    source: &[u8],
+    read_pos: &mut usize,
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    if buffer.is_null() || amount_read.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    let remaining = &source[(*read_pos).min(source.len())..];
+    let amount = remaining.len().min(buffer_size);
+    ptr::copy_nonoverlapping(remaining.as_ptr(), buffer as *mut u8, amount);
+    *read_pos += amount;
+    *amount_read = amount;
+    gg_error_GGE_SUCCESS
+}
+
+unsafe fn c_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+unsafe fn bytes<'a>(ptr: *const c_void, len: usize) -> &'a [u8] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr as *const u8, len)
+    }
+}
+
+fn write_log(level: gg_log_level, msg: &str) {
+    let level = match level {
+        gg_log_level_GG_LOG_DEBUG => "DEBUG",
+        gg_log_level_GG_LOG_INFO => "INFO",
+        gg_log_level_GG_LOG_WARN => "WARN",
+        gg_log_level_GG_LOG_ERROR => "ERROR",
+        gg_log_level_GG_LOG_FATAL => "FATAL",
+        _ => "NOTSET",
+    };
+    eprintln!("[{}] {}", level, msg);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::STATE_DIR_ENV;
+    use crate::test::temp_dir;
+    use serde_json::Value;
+    use std::env;
+
+    unsafe extern "C" fn echo_handler(ctx: *const gg_lambda_context) {
+        let mut buffer = [0u8; 4];
+        let mut message = vec![];
+        loop {
+            let mut read = 0;
+            let res = gg_lambda_handler_read(buffer.as_mut_ptr() as *mut c_void, 4, &mut read);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            if read == 0 {
+                break;
+            }
+            message.extend_from_slice(&buffer[..read]);
+        }
+        assert!(!CStr::from_ptr((*ctx).client_context).to_bytes().is_empty());
+        if message != b"silent" {
+            let response = [b"echo: ", message.as_slice()].concat();
+            let res = gg_lambda_handler_write_response(
+                response.as_ptr() as *const c_void,
+                response.len(),
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            // only one response can be written
+            let res = gg_lambda_handler_write_response(response.as_ptr() as *const c_void, 0);
+            assert_eq!(res, gg_error_GGE_INVALID_STATE);
+        }
+    }
+
+    unsafe fn read_all(req: gg_request) -> Vec<u8> {
+        let mut buffer = [0u8; 8];
+        let mut collected = vec![];
+        loop {
+            let mut read = 0;
+            gg_request_read(req, buffer.as_mut_ptr() as *mut c_void, 8, &mut read);
+            if read == 0 {
+                return collected;
+            }
+            collected.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    /// Environment variables are global to the process, so everything that reads them is tested here
+    #[test]
+    fn test_ffi() {
+        let dir = temp_dir("ffi");
+        env::set_var(STATE_DIR_ENV, &dir);
+
+        unsafe {
+            assert_eq!(gg_global_init(0), gg_error_GGE_SUCCESS);
+            let mut result = gg_request_result {
+                request_status: gg_request_status_GG_REQUEST_UNKNOWN,
+            };
+
+            // a publish with options
+            let topic = CString::new("my/topic").unwrap();
+            let mut opts: gg_publish_options = ptr::null_mut();
+            gg_publish_options_init(&mut opts);
+            let mut req: gg_request = ptr::null_mut();
+            gg_request_init(&mut req);
+            let res = gg_publish_with_options(
+                req,
+                topic.as_ptr(),
+                b"hello".as_ptr() as *const c_void,
+                5,
+                opts,
+                &mut result,
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_SUCCESS);
+            gg_publish_options_free(opts);
+            gg_request_close(req);
+            let published = fs::read_to_string(dir.join("published.jsonl")).unwrap();
+            assert!(published.contains(r#""payload":"hello""#));
+
+            // a shadow that doesn't exist
+            let thing = CString::new("my_thing").unwrap();
+            gg_request_init(&mut req);
+            gg_get_thing_shadow(req, thing.as_ptr(), &mut result);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_HANDLED);
+            let body: Value = serde_json::from_slice(&read_all(req)).unwrap();
+            assert_eq!(body["code"], 404);
+            gg_request_close(req);
+
+            // an update is persisted
+            let update = CString::new(r#"{"state": {"reported": {"on": true}}}"#).unwrap();
+            gg_request_init(&mut req);
+            gg_update_thing_shadow(req, thing.as_ptr(), update.as_ptr(), &mut result);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_SUCCESS);
+            gg_request_close(req);
+            assert!(dir.join("shadows").join("my_thing.json").exists());
+
+            // invoking is not supported
+            let options = gg_invoke_options {
+                function_arn: topic.as_ptr(),
+                customer_context: ptr::null(),
+                qualifier: ptr::null(),
+                type_: gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE,
+                payload: ptr::null(),
+                payload_size: 0,
+            };
+            gg_request_init(&mut req);
+            gg_invoke(req, &options, &mut result);
+            assert_eq!(
+                result.request_status,
+                gg_request_status_GG_REQUEST_UNHANDLED
+            );
+            gg_request_close(req);
+
+            // the handler loop
+            let res = gg_runtime_start(Some(echo_handler), gg_runtime_opt_GG_RT_OPT_ASYNC);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+        }
+
+        let mut stream = UnixStream::connect(dir.join("events.sock")).unwrap();
+        stream
+            .write_all(
+                b"{\"topic\": \"t\", \"payload\": \"hello\"}\n{\"topic\": \"t\", \"payload\": \"silent\"}\nnot json\n",
+            )
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut next = || lines.next().unwrap().unwrap();
+        assert_eq!(next(), r#"{"response":"echo: hello"}"#);
+        assert_eq!(next(), "{}");
+        assert!(next().starts_with(r#"{"error":"Invalid event"#));
+
+        // responses can't be written outside of the handler
+        let res = unsafe { gg_lambda_handler_write_response(ptr::null(), 0) };
+        assert_eq!(res, gg_error_GGE_INVALID_STATE);
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! A simulator of the Greengrass Core C SDK that exports the C ABI of `greengrasssdk.h`,
+//! giving Greengrass lambdas a runnable development environment without a Greengrass core.
+//!
+//! * Published messages are written as JSON lines to a Unix socket or appended to a file
+//! * Shadow documents are persisted as JSON files in a directory
+//! * Secrets are read from a JSON file
+//! * `gg_runtime_start` listens on a Unix socket and passes each JSON line it receives to the handler as an event
+//!
+//! See [`config`] for the environment variables that configure where each of these live.
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod bus;
+pub mod config;
+pub mod ffi;
+pub mod secret;
+pub mod shadow;
+
+pub use ffi::*;
+
+/// Why a request could not be fulfilled, returned as the request status and an error response body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub status: gg_request_status,
+    pub code: u16,
+    pub message: String,
+}
+
+impl Failure {
+    /// A failure handled by the simulated service, e.g. a missing shadow
+    pub fn handled(code: u16, message: &str) -> Self {
+        Failure {
+            status: gg_request_status_GG_REQUEST_HANDLED,
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    /// A failure of the simulator itself, e.g. a file that could not be written
+    pub fn unknown(message: String) -> Self {
+        Failure {
+            status: gg_request_status_GG_REQUEST_UNKNOWN,
+            code: 500,
+            message,
+        }
+    }
+
+    /// The error response body in the format the Greengrass services use
+    pub fn body(&self) -> Vec<u8> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        json!({ "code": self.code, "message": self.message, "timestamp": timestamp })
+            .to_string()
+            .into_bytes()
+    }
+}
+
+/// The response body of a request, or why it failed
+pub type Outcome = Result<Vec<u8>, Failure>;
+
+/// Adds the bytes to the object as a string if they are UTF-8, otherwise base64 encoded with a `_base64` suffix
+pub(crate) fn insert_bytes(object: &mut Value, key: &str, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => object[key] = json!(s),
+        Err(_) => object[format!("{}_base64", key)] = json!(base64::encode(bytes)),
+    }
+}
+
+/// Reads bytes written by [`insert_bytes`]. Values that are not strings are read as their JSON.
+pub(crate) fn read_bytes(object: &Value, key: &str) -> Result<Vec<u8>, String> {
+    if let Some(encoded) = object[format!("{}_base64", key)].as_str() {
+        return base64::decode(encoded).map_err(|e| format!("Invalid {}_base64: {}", key, e));
+    }
+    match &object[key] {
+        Value::Null => Ok(vec![]),
+        Value::String(s) => Ok(s.clone().into_bytes()),
+        other => Ok(other.to_string().into_bytes()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Creates an empty directory unique to this test run
+    pub fn temp_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "gg-sim-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut object = serde_json::json!({});
+        super::insert_bytes(&mut object, "payload", b"text");
+        super::insert_bytes(&mut object, "binary", &[0xff, 0x00]);
+        assert_eq!(object["payload"], "text");
+        assert_eq!(object["binary_base64"], "/wA=");
+        assert_eq!(super::read_bytes(&object, "payload").unwrap(), b"text");
+        assert_eq!(
+            super::read_bytes(&object, "binary").unwrap(),
+            vec![0xff, 0x00]
+        );
+        assert!(super::read_bytes(&object, "missing").unwrap().is_empty());
+
+        let object = serde_json::json!({"payload": {"foo": 1}});
+        assert_eq!(
+            super::read_bytes(&object, "payload").unwrap(),
+            br#"{"foo":1}"#
+        );
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Secrets read from the secrets file, a JSON object of secret id to secret.
+//!
+//! A secret is either a string, used as the secret string, or an object with the fields of a
+//! Secrets Manager `GetSecretValue` response (`SecretString`, `SecretBinary`, `VersionId`, `VersionStages`, ...).
+//! Missing fields are filled in. The file is read on every request so it can be edited while the lambda runs.
+//!
+//! ```json
+//! {
+//!     "my-password": "hunter2",
+//!     "my-api-key": { "SecretString": "abc123", "VersionId": "2", "VersionStages": ["AWSCURRENT"] }
+//! }
+//! ```
+use crate::config::Config;
+use crate::{Failure, Outcome};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::ErrorKind;
+
+pub fn get_secret_value(
+    config: &Config,
+    secret_id: &str,
+    version_id: Option<&str>,
+    version_stage: Option<&str>,
+) -> Outcome {
+    let secrets = match fs::read(&config.secrets_file) {
+        Ok(raw) => serde_json::from_slice::<Map<String, Value>>(&raw)
+            .map_err(|e| Failure::unknown(format!("Invalid secrets file: {}", e)))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => Map::new(),
+        Err(e) => {
+            return Err(Failure::unknown(format!(
+                "Could not read secrets file: {}",
+                e
+            )))
+        }
+    };
+
+    secrets
+        .iter()
+        .map(|(id, secret)| with_defaults(id, secret))
+        .find(|secret| secret["ARN"] == secret_id || secret["Name"] == secret_id)
+        .filter(|secret| version_id.is_none_or(|v| secret["VersionId"] == v))
+        .filter(|secret| {
+            version_stage.is_none_or(|v| {
+                secret["VersionStages"]
+                    .as_array()
+                    .is_some_and(|stages| stages.iter().any(|s| s == v))
+            })
+        })
+        .map(|secret| secret.to_string().into_bytes())
+        .ok_or_else(|| Failure::handled(404, "Secrets Manager can't find the specified secret."))
+}
+
+/// The secret as a complete GetSecretValue response
+fn with_defaults(id: &str, secret: &Value) -> Value {
+    let mut complete = json!({
+        "ARN": format!("arn:aws:secretsmanager:us-east-1:000000000000:secret:{}", id),
+        "Name": id,
+        "VersionId": "1",
+        "VersionStages": ["AWSCURRENT"],
+        "CreatedDate": 0,
+    });
+    match secret {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                complete[key] = value.clone();
+            }
+        }
+        other => complete["SecretString"] = other.clone(),
+    }
+    complete
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::temp_dir;
+
+    #[test]
+    fn test_get_secret_value() {
+        let dir = temp_dir("secret");
+        let config = Config {
+            secrets_file: dir.join("secrets.json"),
+            ..Config::from_env()
+        };
+        let not_found = get_secret_value(&config, "my-password", None, None);
+        assert_eq!(not_found.unwrap_err().code, 404);
+
+        fs::write(
+            &config.secrets_file,
+            r#"{"my-password": "hunter2", "my-api-key": {"SecretString": "abc123", "VersionId": "2"}}"#,
+        )
+        .unwrap();
+        let secret: Value = serde_json::from_slice(
+            &get_secret_value(&config, "my-password", None, Some("AWSCURRENT")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(secret["SecretString"], "hunter2");
+        assert_eq!(secret["Name"], "my-password");
+
+        let arn = "arn:aws:secretsmanager:us-east-1:000000000000:secret:my-api-key";
+        let secret: Value =
+            serde_json::from_slice(&get_secret_value(&config, arn, Some("2"), None).unwrap())
+                .unwrap();
+        assert_eq!(secret["SecretString"], "abc123");
+        assert!(get_secret_value(&config, "my-api-key", Some("1"), None).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Shadow documents persisted as `<thing name>.json` in the shadow directory.
+//!
+//! Updates are merged into the desired and reported state, bump the version and are published to the
+//! `$aws/things/<thing>/shadow/update/accepted` and `.../update/delta` topics like the Greengrass shadow service.
+use crate::bus;
+use crate::config::Config;
+use crate::{Failure, Outcome};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Serializes the read-modify-write of shadow files within the process
+static SHADOW_LOCK: Mutex<()> = Mutex::new(());
+
+/// The persisted state of a shadow
+#[derive(Debug, Default)]
+struct Shadow {
+    desired: Map<String, Value>,
+    reported: Map<String, Value>,
+    version: u64,
+}
+
+impl Shadow {
+    fn from_json(value: Value) -> Self {
+        let section = |name: &str| value[name].as_object().cloned().unwrap_or_default();
+        Shadow {
+            desired: section("desired"),
+            reported: section("reported"),
+            version: value["version"].as_u64().unwrap_or_default(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "desired": self.desired, "reported": self.reported, "version": self.version })
+    }
+
+    /// The document as returned by gg_get_thing_shadow
+    fn document(&self) -> Value {
+        let mut state = Map::new();
+        if !self.desired.is_empty() {
+            state.insert("desired".to_owned(), json!(self.desired));
+        }
+        if !self.reported.is_empty() {
+            state.insert("reported".to_owned(), json!(self.reported));
+        }
+        let delta = delta(&self.desired, &self.reported);
+        if !delta.is_empty() {
+            state.insert("delta".to_owned(), json!(delta));
+        }
+        json!({ "state": state, "version": self.version, "timestamp": timestamp() })
+    }
+}
+
+pub fn get(config: &Config, thing_name: &str) -> Outcome {
+    let _guard = SHADOW_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let shadow = load(config, thing_name)?.ok_or_else(|| not_found(thing_name))?;
+    Ok(shadow.document().to_string().into_bytes())
+}
+
+pub fn update(config: &Config, thing_name: &str, document: &str) -> Outcome {
+    let document: Value = serde_json::from_str(document)
+        .map_err(|_| Failure::handled(400, "Payload contains invalid json"))?;
+    let state = document["state"]
+        .as_object()
+        .ok_or_else(|| Failure::handled(400, "Missing required node: state"))?;
+    let is_invalid = |name: &str| {
+        state
+            .get(name)
+            .is_some_and(|s| !s.is_object() && !s.is_null())
+    };
+    if is_invalid("desired") || is_invalid("reported") {
+        return Err(Failure::handled(400, "State node must be an object"));
+    }
+
+    let (accepted, delta) = {
+        let _guard = SHADOW_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut shadow = load(config, thing_name)?.unwrap_or_default();
+        if let Some(version) = document["version"].as_u64() {
+            if version != shadow.version {
+                return Err(Failure::handled(409, "Version conflict"));
+            }
+        }
+        for (name, section) in [
+            ("desired", &mut shadow.desired),
+            ("reported", &mut shadow.reported),
+        ] {
+            match state.get(name) {
+                Some(Value::Object(patch)) => merge(section, patch),
+                Some(_) => section.clear(),
+                None => (),
+            }
+        }
+        shadow.version += 1;
+        save(config, thing_name, &shadow)?;
+        let accepted =
+            json!({ "state": state, "version": shadow.version, "timestamp": timestamp() });
+        let delta = delta(&shadow.desired, &shadow.reported);
+        let delta = if delta.is_empty() {
+            None
+        } else {
+            Some(json!({ "state": delta, "version": shadow.version, "timestamp": timestamp() }))
+        };
+        (accepted, delta)
+    };
+
+    // The update has been accepted whether or not the notifications could be published
+    let topic = format!("$aws/things/{}/shadow/update", thing_name);
+    let _ = bus::publish(
+        config,
+        &format!("{}/accepted", topic),
+        accepted.to_string().as_bytes(),
+    );
+    if let Some(delta) = delta {
+        let _ = bus::publish(
+            config,
+            &format!("{}/delta", topic),
+            delta.to_string().as_bytes(),
+        );
+    }
+    Ok(accepted.to_string().into_bytes())
+}
+
+pub fn delete(config: &Config, thing_name: &str) -> Outcome {
+    let _guard = SHADOW_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let shadow = load(config, thing_name)?.ok_or_else(|| not_found(thing_name))?;
+    fs::remove_file(path(config, thing_name)?)
+        .map_err(|e| Failure::unknown(format!("Could not delete shadow {}: {}", thing_name, e)))?;
+    let accepted = json!({ "version": shadow.version, "timestamp": timestamp() });
+    Ok(accepted.to_string().into_bytes())
+}
+
+/// The thing name is used as a file name, so names that could escape the shadow directory are rejected
+fn path(config: &Config, thing_name: &str) -> Result<PathBuf, Failure> {
+    if thing_name.is_empty()
+        || thing_name.starts_with('.')
+        || thing_name.contains(['/', '\\'])
+    {
+        return Err(Failure::handled(400, "Invalid thing name"));
+    }
+    Ok(config.shadow_dir.join(format!("{}.json", thing_name)))
+}
+
+fn load(config: &Config, thing_name: &str) -> Result<Option<Shadow>, Failure> {
+    let raw = match fs::read(path(config, thing_name)?) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Failure::unknown(format!(
+                "Could not read shadow {}: {}",
+                thing_name, e
+            )))
+        }
+    };
+    serde_json::from_slice(&raw)
+        .map(|value| Some(Shadow::from_json(value)))
+        .map_err(|e| Failure::unknown(format!("Invalid shadow file for {}: {}", thing_name, e)))
+}
+
+/// Writes to a temporary file first so that readers never see a partially written shadow
+fn save(config: &Config, thing_name: &str, shadow: &Shadow) -> Result<(), Failure> {
+    let path = path(config, thing_name)?;
+    let temp = path.with_extension("json.tmp");
+    fs::create_dir_all(&config.shadow_dir)
+        .and_then(|_| fs::write(&temp, shadow.to_json().to_string()))
+        .and_then(|_| fs::rename(&temp, &path))
+        .map_err(|e| Failure::unknown(format!("Could not write shadow {}: {}", thing_name, e)))
+}
+
+fn not_found(thing_name: &str) -> Failure {
+    Failure::handled(
+        404,
+        &format!("No shadow exists with name: '{}'", thing_name),
+    )
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Merges the patch into the section. Null values remove keys.
+fn merge(section: &mut Map<String, Value>, patch: &Map<String, Value>) {
+    for (key, value) in patch {
+        match value {
+            Value::Null => {
+                section.remove(key);
+            }
+            Value::Object(nested) => {
+                let entry = section
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if !entry.is_object() {
+                    *entry = Value::Object(Map::new());
+                }
+                if let Value::Object(entry) = entry {
+                    merge(entry, nested);
+                }
+            }
+            _ => {
+                section.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The desired values that differ from the reported values
+fn delta(desired: &Map<String, Value>, reported: &Map<String, Value>) -> Map<String, Value> {
+    let mut delta = Map::new();
+    for (key, value) in desired {
+        match (value, reported.get(key)) {
+            (Value::Object(desired), Some(Value::Object(reported))) => {
+                let nested = self::delta(desired, reported);
+                if !nested.is_empty() {
+                    delta.insert(key.clone(), Value::Object(nested));
+                }
+            }
+            (value, Some(reported)) if value == reported => (),
+            (value, _) => {
+                delta.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    delta
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::temp_dir;
+
+    #[test]
+    fn test_shadow_lifecycle() {
+        let dir = temp_dir("shadow");
+        let config = Config {
+            shadow_dir: dir.join("shadows"),
+            publish_file: dir.join("published.jsonl"),
+            publish_socket: None,
+            ..Config::from_env()
+        };
+        assert_eq!(get(&config, "thing").unwrap_err().code, 404);
+        assert_eq!(get(&config, "../thing").unwrap_err().code, 400);
+        assert_eq!(update(&config, "thing", "{}").unwrap_err().code, 400);
+
+        update(
+            &config,
+            "thing",
+            r#"{"state": {"desired": {"on": true, "level": 5}}}"#,
+        )
+        .unwrap();
+        update(
+            &config,
+            "thing",
+            r#"{"state": {"reported": {"on": false, "level": 5}}}"#,
+        )
+        .unwrap();
+        let document: Value = serde_json::from_slice(&get(&config, "thing").unwrap()).unwrap();
+        assert_eq!(document["version"], 2);
+        assert_eq!(document["state"]["delta"], json!({"on": true}));
+        assert!(dir.join("shadows").join("thing.json").exists());
+
+        let conflict = update(
+            &config,
+            "thing",
+            r#"{"state": {"reported": {"on": true}}, "version": 1}"#,
+        );
+        assert_eq!(conflict.unwrap_err().code, 409);
+
+        let published = fs::read_to_string(&config.publish_file).unwrap();
+        let topics: Vec<String> = published
+            .lines()
+            .map(|l| {
+                serde_json::from_str::<Value>(l).unwrap()["topic"]
+                    .as_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+        assert_eq!(
+            topics,
+            vec![
+                "$aws/things/thing/shadow/update/accepted",
+                "$aws/things/thing/shadow/update/delta",
+                "$aws/things/thing/shadow/update/accepted",
+                "$aws/things/thing/shadow/update/delta",
+            ]
+        );
+
+        delete(&config, "thing").unwrap();
+        assert_eq!(get(&config, "thing").unwrap_err().code, 404);
+    }
+}
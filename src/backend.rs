@@ -0,0 +1,563 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides the [`Backend`] trait that the clients use to talk to Greengrass.
+//!
+//! [`CBackend`] wraps the Greengrass Core C SDK and is used by default. Other implementations can be
+//! injected into the clients with `with_backend`, for example to run integration tests without a core.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::backend::{Backend, CBackend};
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//! use std::sync::Arc;
+//!
+//! let backend: Arc<dyn Backend> = Arc::new(CBackend);
+//! let client = IOTDataClient::default().with_backend(backend);
+//! ```
+use crate::bindings::*;
+use crate::error::GGError;
+use crate::ffi::{self, PublishOptionsHandle};
+use crate::iotdata::PublishOptions;
+use crate::lambda::InvokeType;
+use crate::request::GGRequestStatus;
+use crate::GGResult;
+use lazy_static::lazy_static;
+use log::Level;
+use std::ffi::CString;
+use std::sync::Arc;
+
+lazy_static! {
+    static ref DEFAULT_BACKEND: Arc<dyn Backend> = Arc::new(CBackend);
+}
+
+/// Returns the backend used by clients that were not given one, the C SDK
+pub fn default_backend() -> Arc<dyn Backend> {
+    Arc::clone(&DEFAULT_BACKEND)
+}
+
+/// Identifies a request created by [`Backend::request_init`].
+/// The value is only meaningful to the backend that created it, and only until the request is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestHandle(pub usize);
+
+/// Identifies an invocation that has been detached from the handler callback with [`Backend::handler_detach`],
+/// so that it can be handled on another thread.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvocationHandle(pub String);
+
+/// The arguments for invoking a lambda
+#[derive(Debug, Clone)]
+pub struct InvokeArgs<'a> {
+    /// The full ARN of the lambda
+    pub function_arn: &'a str,
+    /// The base64 encoded JSON customer context
+    pub customer_context: &'a str,
+    /// Version number of the lambda function
+    pub qualifier: &'a str,
+    pub invoke_type: InvokeType,
+    pub payload: Option<&'a [u8]>,
+}
+
+/// The operations the clients need from Greengrass.
+///
+/// Operations that take a [`RequestHandle`] return the status of the request. The body of the response,
+/// or of the error response if the status is not [`GGRequestStatus::Success`], is read with [`Backend::request_read`].
+///
+/// # Safety
+///
+/// The operations that take a [`RequestHandle`] are unsafe, as a backend may trust the handle, e.g. the C SDK
+/// dereferences it. The handle must have been returned by [`Backend::request_init`] of the same backend
+/// and the request must not have been closed with [`Backend::request_close`].
+pub trait Backend: Send + Sync {
+    /// Creates a new request
+    fn request_init(&self) -> GGResult<RequestHandle>;
+
+    /// Reads the next chunk of the response body into the buffer, returning the number of bytes read.
+    /// Zero is returned once the body has been read.
+    ///
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn request_read(&self, request: RequestHandle, buffer: &mut [u8]) -> GGResult<usize>;
+
+    /// Releases the request
+    ///
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn request_close(&self, request: RequestHandle) -> GGResult<()>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn publish(
+        &self,
+        request: RequestHandle,
+        topic: &str,
+        payload: &[u8],
+        options: Option<&PublishOptions>,
+    ) -> GGResult<GGRequestStatus>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn invoke(&self, request: RequestHandle, args: &InvokeArgs)
+        -> GGResult<GGRequestStatus>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn get_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn update_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+        document: &str,
+    ) -> GGResult<GGRequestStatus>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn delete_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus>;
+
+    /// # Safety
+    /// `request` must be an open request of this backend, see [`Backend`].
+    unsafe fn get_secret_value(
+        &self,
+        request: RequestHandle,
+        secret_id: &str,
+        version_id: Option<&str>,
+        version_stage: Option<&str>,
+    ) -> GGResult<GGRequestStatus>;
+
+    /// Writes a message to the Greengrass log
+    fn log(&self, level: Level, message: &str) -> GGResult<()>;
+
+    /// Reads the next chunk of the event being handled into the buffer, returning the number of bytes read.
+    /// Zero is returned once the event has been read.
+    fn handler_read(&self, buffer: &mut [u8]) -> GGResult<usize>;
+
+    /// Writes the response to the event being handled
+    fn handler_write_response(&self, response: &[u8]) -> GGResult<()>;
+
+    /// Writes an error response to the event being handled
+    fn handler_write_error(&self, message: &str) -> GGResult<()>;
+
+    /// Detaches the event being handled from the handler callback, so that it isn't completed when the callback
+    /// returns. It can then be handled on another thread with [`Backend::handler_attach`] and must be completed
+    /// with [`Backend::handler_complete`].
+    ///
+    /// Returns None if events can only be handled on the thread the callback was called on, which is the default.
+    fn handler_detach(&self) -> Option<InvocationHandle> {
+        None
+    }
+
+    /// Makes the detached invocation the event being handled by this thread, or clears it when None
+    fn handler_attach(&self, _invocation: Option<&InvocationHandle>) {}
+
+    /// Completes the detached invocation. An empty response is written if no response was written,
+    /// as Greengrass waits for a response to every invocation.
+    fn handler_complete(&self, _invocation: &InvocationHandle) {}
+}
+
+/// The backend that calls the Greengrass Core C SDK
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CBackend;
+
+impl CBackend {
+    fn request_c(request: RequestHandle) -> gg_request {
+        request.0 as gg_request
+    }
+
+    fn c_string(s: &str) -> GGResult<CString> {
+        CString::new(s).map_err(GGError::from)
+    }
+
+    fn publish_options_c(options: &PublishOptions) -> GGResult<PublishOptionsHandle> {
+        let mut options_c = PublishOptionsHandle::new()?;
+        options_c.set_queue_full_policy(options.queue_full_policy.to_queue_full_c())?;
+        Ok(options_c)
+    }
+}
+
+// The C SDK trusts the requests passed to it, which the callers guarantee are open requests created here
+impl Backend for CBackend {
+    fn request_init(&self) -> GGResult<RequestHandle> {
+        ffi::request_init().map(|req| RequestHandle(req as usize))
+    }
+
+    unsafe fn request_read(&self, request: RequestHandle, buffer: &mut [u8]) -> GGResult<usize> {
+        unsafe { ffi::request_read(Self::request_c(request), buffer) }
+    }
+
+    unsafe fn request_close(&self, request: RequestHandle) -> GGResult<()> {
+        unsafe { ffi::request_close(Self::request_c(request)) }
+    }
+
+    unsafe fn publish(
+        &self,
+        request: RequestHandle,
+        topic: &str,
+        payload: &[u8],
+        options: Option<&PublishOptions>,
+    ) -> GGResult<GGRequestStatus> {
+        let topic_c = Self::c_string(topic)?;
+        let options_c = options.map(Self::publish_options_c).transpose()?;
+        unsafe {
+            ffi::publish(
+                Self::request_c(request),
+                &topic_c,
+                payload,
+                options_c.as_ref(),
+            )
+        }
+    }
+
+    unsafe fn invoke(
+        &self,
+        request: RequestHandle,
+        args: &InvokeArgs,
+    ) -> GGResult<GGRequestStatus> {
+        let function_arn_c = Self::c_string(args.function_arn)?;
+        let customer_context_c = Self::c_string(args.customer_context)?;
+        let qualifier_c = Self::c_string(args.qualifier)?;
+        unsafe {
+            ffi::invoke(
+                Self::request_c(request),
+                &function_arn_c,
+                &customer_context_c,
+                &qualifier_c,
+                args.invoke_type.as_c_invoke_type(),
+                args.payload,
+            )
+        }
+    }
+
+    unsafe fn get_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus> {
+        let thing_name_c = Self::c_string(thing_name)?;
+        unsafe { ffi::get_thing_shadow(Self::request_c(request), &thing_name_c) }
+    }
+
+    unsafe fn update_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+        document: &str,
+    ) -> GGResult<GGRequestStatus> {
+        let thing_name_c = Self::c_string(thing_name)?;
+        let document_c = Self::c_string(document)?;
+        unsafe { ffi::update_thing_shadow(Self::request_c(request), &thing_name_c, &document_c) }
+    }
+
+    unsafe fn delete_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus> {
+        let thing_name_c = Self::c_string(thing_name)?;
+        unsafe { ffi::delete_thing_shadow(Self::request_c(request), &thing_name_c) }
+    }
+
+    unsafe fn get_secret_value(
+        &self,
+        request: RequestHandle,
+        secret_id: &str,
+        version_id: Option<&str>,
+        version_stage: Option<&str>,
+    ) -> GGResult<GGRequestStatus> {
+        let secret_id_c = Self::c_string(secret_id)?;
+        let version_id_c = version_id.map(Self::c_string).transpose()?;
+        let version_stage_c = version_stage.map(Self::c_string).transpose()?;
+        unsafe {
+            ffi::get_secret_value(
+                Self::request_c(request),
+                &secret_id_c,
+                version_id_c.as_deref(),
+                version_stage_c.as_deref(),
+            )
+        }
+    }
+
+    fn log(&self, level: Level, message: &str) -> GGResult<()> {
+        let message_c = Self::c_string(message)?;
+        let level_c = match level {
+            Level::Info => gg_log_level_GG_LOG_INFO,
+            Level::Warn => gg_log_level_GG_LOG_WARN,
+            Level::Error => gg_log_level_GG_LOG_ERROR,
+            _ => gg_log_level_GG_LOG_DEBUG,
+        };
+        ffi::log(level_c, &message_c)
+    }
+
+    fn handler_read(&self, buffer: &mut [u8]) -> GGResult<usize> {
+        ffi::handler_read(buffer)
+    }
+
+    fn handler_write_response(&self, response: &[u8]) -> GGResult<()> {
+        ffi::handler_write_response(response)
+    }
+
+    fn handler_write_error(&self, message: &str) -> GGResult<()> {
+        ffi::handler_write_error(&Self::c_string(message)?)
+    }
+
+    #[cfg(all(not(test), not(feature = "coverage"), feature = "ipc"))]
+    fn handler_detach(&self) -> Option<InvocationHandle> {
+        crate::ipc::ffi::detach_invocation().map(InvocationHandle)
+    }
+
+    #[cfg(all(not(test), not(feature = "coverage"), feature = "ipc"))]
+    fn handler_attach(&self, invocation: Option<&InvocationHandle>) {
+        crate::ipc::ffi::attach_invocation(invocation.map(|i| i.0.as_str()))
+    }
+
+    #[cfg(all(not(test), not(feature = "coverage"), feature = "ipc"))]
+    fn handler_complete(&self, invocation: &InvocationHandle) {
+        crate::ipc::ffi::complete_invocation(&invocation.0)
+    }
+}
+
+#[cfg(all(test, not(feature = "mock")))]
+mod test {
+    use super::*;
+    use crate::iotdata::IOTDataClient;
+    use crate::lambda::{InvokeOptions, LambdaClient};
+    use crate::shadow::ShadowClient;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Records every call and responds to every request with the same body
+    #[derive(Default)]
+    struct RecordingBackend {
+        calls: Mutex<Vec<String>>,
+        body: Mutex<Vec<u8>>,
+    }
+
+    impl RecordingBackend {
+        fn record(&self, call: String) -> GGResult<GGRequestStatus> {
+            self.calls.lock().unwrap().push(call);
+            Ok(GGRequestStatus::Success)
+        }
+    }
+
+    impl Backend for RecordingBackend {
+        fn request_init(&self) -> GGResult<RequestHandle> {
+            Ok(RequestHandle(7))
+        }
+
+        unsafe fn request_read(&self, _: RequestHandle, buffer: &mut [u8]) -> GGResult<usize> {
+            let mut body = self.body.lock().unwrap();
+            let read = buffer.len().min(body.len());
+            buffer[..read].copy_from_slice(&body[..read]);
+            body.drain(..read);
+            Ok(read)
+        }
+
+        unsafe fn request_close(&self, request: RequestHandle) -> GGResult<()> {
+            self.record(format!("close {}", request.0)).map(|_| ())
+        }
+
+        unsafe fn publish(
+            &self,
+            _: RequestHandle,
+            topic: &str,
+            payload: &[u8],
+            _: Option<&PublishOptions>,
+        ) -> GGResult<GGRequestStatus> {
+            self.record(format!(
+                "publish {} {}",
+                topic,
+                String::from_utf8_lossy(payload)
+            ))
+        }
+
+        unsafe fn invoke(&self, _: RequestHandle, args: &InvokeArgs) -> GGResult<GGRequestStatus> {
+            self.record(format!(
+                "invoke {} {:?}",
+                args.function_arn, args.invoke_type
+            ))
+        }
+
+        unsafe fn get_thing_shadow(
+            &self,
+            _: RequestHandle,
+            thing_name: &str,
+        ) -> GGResult<GGRequestStatus> {
+            self.record(format!("get_thing_shadow {}", thing_name))
+        }
+
+        unsafe fn update_thing_shadow(
+            &self,
+            _: RequestHandle,
+            thing_name: &str,
+            _: &str,
+        ) -> GGResult<GGRequestStatus> {
+            self.record(format!("update_thing_shadow {}", thing_name))
+        }
+
+        unsafe fn delete_thing_shadow(
+            &self,
+            _: RequestHandle,
+            thing_name: &str,
+        ) -> GGResult<GGRequestStatus> {
+            self.record(format!("delete_thing_shadow {}", thing_name))
+        }
+
+        unsafe fn get_secret_value(
+            &self,
+            _: RequestHandle,
+            secret_id: &str,
+            _: Option<&str>,
+            _: Option<&str>,
+        ) -> GGResult<GGRequestStatus> {
+            self.record(format!("get_secret_value {}", secret_id))
+        }
+
+        fn log(&self, _: Level, message: &str) -> GGResult<()> {
+            self.record(format!("log {}", message)).map(|_| ())
+        }
+
+        fn handler_read(&self, _: &mut [u8]) -> GGResult<usize> {
+            Ok(0)
+        }
+
+        fn handler_write_response(&self, response: &[u8]) -> GGResult<()> {
+            self.record(format!("response {}", String::from_utf8_lossy(response)))
+                .map(|_| ())
+        }
+
+        fn handler_write_error(&self, message: &str) -> GGResult<()> {
+            self.record(format!("error {}", message)).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_injected_backend() {
+        let backend = Arc::new(RecordingBackend::default());
+        *backend.body.lock().unwrap() = br#"{"state":{}}"#.to_vec();
+
+        IOTDataClient::default()
+            .with_backend(backend.clone())
+            .publish("my/topic", "hello")
+            .unwrap();
+        let shadow = ShadowClient::default()
+            .with_backend(backend.clone())
+            .get_thing_shadow::<Value>("my_thing")
+            .unwrap();
+        assert_eq!(shadow, Some(json!({"state": {}})));
+        let lambda = LambdaClient::default().with_backend(backend.clone());
+        lambda
+            .invoke_async(
+                InvokeOptions::new("my_func_arn".to_owned(), json!({}), "1".to_owned()),
+                Some("payload"),
+            )
+            .unwrap();
+        lambda.send_response(Err("failed")).unwrap();
+
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![
+                "publish my/topic hello",
+                "close 7",
+                "get_thing_shadow my_thing",
+                "close 7",
+                "invoke my_func_arn InvokeEvent",
+                "close 7",
+                "error failed",
+            ]
+        );
+    }
+
+    /// Starts a runtime with a recording backend and the handler, and returns the calls made
+    /// once the handler has handled an empty message
+    fn handle_with_runtime(handler: Box<crate::runtime::ShareableHandler>) -> Vec<String> {
+        use crate::bindings::test::send_to_handler;
+        use crate::handler::LambdaContext;
+        use crate::runtime::test::runtime_lock;
+        use crate::runtime::Runtime;
+        use crate::Initializer;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let _lock = runtime_lock();
+        let backend = Arc::new(RecordingBackend::default());
+        let runtime = Runtime::default()
+            .with_backend(backend.clone())
+            .with_handler(Some(handler));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        send_to_handler(LambdaContext::new(
+            "my_func_arn".to_owned(),
+            String::new(),
+            vec![],
+        ));
+        let start = Instant::now();
+        while backend.calls.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        Initializer::default().init().unwrap();
+        let calls = backend.calls.lock().unwrap().clone();
+        calls
+    }
+
+    #[test]
+    fn test_json_handler_responds_with_runtime_backend() {
+        use crate::handler::JsonHandler;
+
+        // The empty message fails to decode on the thread of the runtime handling it
+        let handler = JsonHandler::new(|req: Value| -> Result<Value, String> { Ok(req) });
+        let calls = handle_with_runtime(Box::new(handler));
+        assert_eq!(calls.len(), 1);
+        assert!(
+            calls[0].starts_with("error "),
+            "unexpected call {}",
+            calls[0]
+        );
+    }
+
+    #[test]
+    fn test_middleware_short_circuit_responds_with_runtime_backend() {
+        use crate::handler::{Handler, LambdaContext};
+        use crate::middleware::{Middleware, Stack};
+
+        struct Reject;
+
+        impl Middleware for Reject {
+            fn handle(&self, _: LambdaContext, _: &dyn Handler) -> Result<(), String> {
+                Err("rejected".to_owned())
+            }
+        }
+
+        struct Unreachable;
+
+        impl Handler for Unreachable {
+            fn handle(&self, _: LambdaContext) {
+                panic!("event was not short-circuited");
+            }
+        }
+
+        let handler = Stack::default()
+            .layer(Reject)
+            .service(Box::new(Unreachable));
+        assert_eq!(handle_with_runtime(handler), vec!["error rejected"]);
+    }
+}
@@ -0,0 +1,411 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides [`BatchingPublisher`], which combines the JSON messages published to a topic into one publish.
+//!
+//! Messages are accumulated per topic and published as one payload when the batch reaches the max count,
+//! when the next message would take it over the max size or when its oldest message has waited for the max latency.
+//!
+//! ```rust,no_run
+//! use aws_greengrass_core_rust::batch::{BatchEncoding, BatchOptions, BatchingPublisher};
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//! use std::time::Duration;
+//!
+//! let options = BatchOptions::default()
+//!     .with_max_count(50)
+//!     .with_max_latency(Duration::from_millis(500))
+//!     .with_encoding(BatchEncoding::NewlineDelimited);
+//! let publisher = BatchingPublisher::new(IOTDataClient::default(), options).unwrap();
+//! publisher.publish_json("sensors/temperature", 21.5).unwrap();
+//! publisher.flush().unwrap();
+//! ```
+use crate::error::GGError;
+use crate::iotdata::IotData;
+use crate::GGResult;
+use log::error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The largest payload of an MQTT message that AWS IoT accepts
+pub const MAX_PAYLOAD_BYTES: usize = 128 * 1024;
+
+/// How the messages of a batch are combined into one payload
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatchEncoding {
+    /// A JSON array of the messages
+    JsonArray,
+    /// One message per line
+    NewlineDelimited,
+}
+
+impl BatchEncoding {
+    /// The size of the payload of messages with the total size
+    fn encoded_len(self, count: usize, bytes: usize) -> usize {
+        let separators = count.saturating_sub(1);
+        match self {
+            Self::JsonArray => bytes + separators + 2,
+            Self::NewlineDelimited => bytes + separators,
+        }
+    }
+
+    fn encode(self, messages: &[Vec<u8>]) -> Vec<u8> {
+        match self {
+            Self::JsonArray => {
+                let mut payload = vec![b'['];
+                payload.extend(messages.join(&b','));
+                payload.push(b']');
+                payload
+            }
+            Self::NewlineDelimited => messages.join(&b'\n'),
+        }
+    }
+}
+
+/// Configures when a [`BatchingPublisher`] publishes a batch and how
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    /// The most messages in a batch
+    pub max_count: usize,
+    /// The largest payload of a batch, capped at [`MAX_PAYLOAD_BYTES`]
+    pub max_bytes: usize,
+    /// The longest a message waits before its batch is published
+    pub max_latency: Duration,
+    /// How the messages of a batch are combined
+    pub encoding: BatchEncoding,
+}
+
+impl Default for BatchOptions {
+    /// Batches of up to 100 messages and 128 KB encoded as a JSON array, published within a second
+    fn default() -> Self {
+        BatchOptions {
+            max_count: 100,
+            max_bytes: MAX_PAYLOAD_BYTES,
+            max_latency: Duration::from_secs(1),
+            encoding: BatchEncoding::JsonArray,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// The most messages in a batch
+    pub fn with_max_count(self, max_count: usize) -> Self {
+        BatchOptions { max_count, ..self }
+    }
+
+    /// The largest payload of a batch, capped at [`MAX_PAYLOAD_BYTES`]
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        BatchOptions { max_bytes, ..self }
+    }
+
+    /// The longest a message waits before its batch is published
+    pub fn with_max_latency(self, max_latency: Duration) -> Self {
+        BatchOptions {
+            max_latency,
+            ..self
+        }
+    }
+
+    /// How the messages of a batch are combined
+    pub fn with_encoding(self, encoding: BatchEncoding) -> Self {
+        BatchOptions { encoding, ..self }
+    }
+}
+
+type Publish = dyn Fn(&str, &[u8]) -> GGResult<()> + Send + Sync;
+
+struct Batch {
+    messages: Vec<Vec<u8>>,
+    /// The total size of the messages
+    bytes: usize,
+    started: Instant,
+}
+
+struct State {
+    batches: HashMap<String, Batch>,
+    shutdown: bool,
+}
+
+/// The state shared with the thread publishing batches that reached the max latency
+struct Batcher {
+    options: BatchOptions,
+    publish: Box<Publish>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Publishes JSON messages in batches.
+///
+/// Batches that fill up are published by the call to [`BatchingPublisher::publish_json`] that filled them,
+/// which returns the error if publishing fails. Batches that reach the max latency are published by a background thread,
+/// which logs errors. Either way the messages of a batch that failed to publish are dropped,
+/// so give the client a [`crate::retry::RetryPolicy`] to retry throttled publishes.
+///
+/// Dropping the publisher publishes the remaining batches.
+pub struct BatchingPublisher {
+    batcher: Arc<Batcher>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl BatchingPublisher {
+    /// Creates a publisher that publishes the batches with the client
+    pub fn new<C>(client: C, options: BatchOptions) -> GGResult<Self>
+    where
+        C: IotData + Send + Sync + 'static,
+    {
+        let options = BatchOptions {
+            max_bytes: options.max_bytes.min(MAX_PAYLOAD_BYTES),
+            max_count: options.max_count.max(1),
+            ..options
+        };
+        let batcher = Arc::new(Batcher {
+            options,
+            publish: Box::new(move |topic, payload| client.publish(topic, payload)),
+            state: Mutex::new(State {
+                batches: HashMap::new(),
+                shutdown: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let timer = {
+            let batcher = Arc::clone(&batcher);
+            thread::Builder::new()
+                .name("gg-batch".to_owned())
+                .spawn(move || batcher.run_timer())
+                .map_err(|e| GGError::Unknown(format!("Could not spawn batch thread: {}", e)))?
+        };
+        Ok(BatchingPublisher {
+            batcher,
+            timer: Some(timer),
+        })
+    }
+
+    /// Adds the message serialized as JSON to the batch of the topic, publishing the batch if it is full.
+    /// Returns [`GGError::InvalidParameter`] if the message alone is larger than the max bytes.
+    pub fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let message = serde_json::to_vec(&message).map_err(GGError::from)?;
+        let options = &self.batcher.options;
+        if options.encoding.encoded_len(1, message.len()) > options.max_bytes {
+            return Err(GGError::InvalidParameter);
+        }
+
+        let mut state = self.batcher.lock();
+        let mut full = vec![];
+        let overflows = state.batches.get(topic).is_some_and(|batch| {
+            let len = options
+                .encoding
+                .encoded_len(batch.messages.len() + 1, batch.bytes + message.len());
+            len > options.max_bytes
+        });
+        if overflows {
+            full.extend(state.batches.remove_entry(topic));
+        }
+        let batch = state
+            .batches
+            .entry(topic.to_owned())
+            .or_insert_with(|| Batch {
+                messages: vec![],
+                bytes: 0,
+                started: Instant::now(),
+            });
+        batch.bytes += message.len();
+        batch.messages.push(message);
+        if batch.messages.len() >= options.max_count {
+            full.extend(state.batches.remove_entry(topic));
+        }
+        self.batcher.changed.notify_all();
+        self.batcher.publish(full)
+    }
+
+    /// Publishes the batches of every topic, returning the first error
+    pub fn flush(&self) -> GGResult<()> {
+        let mut state = self.batcher.lock();
+        let mut batches: Vec<(String, Batch)> = state.batches.drain().collect();
+        batches.sort_by_key(|(_, batch)| batch.started);
+        self.batcher.publish(batches)
+    }
+}
+
+impl Drop for BatchingPublisher {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Could not publish batches on drop: {}", e);
+        }
+        self.batcher.lock().shutdown = true;
+        self.batcher.changed.notify_all();
+        if let Some(timer) = self.timer.take() {
+            if timer.join().is_err() {
+                error!("The batch thread panicked");
+            }
+        }
+    }
+}
+
+impl Batcher {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Publishes the batches in order, returning the first error.
+    /// Called with the state locked so that the batches of a topic are published in order.
+    fn publish(&self, batches: Vec<(String, Batch)>) -> GGResult<()> {
+        let mut result = Ok(());
+        for (topic, batch) in batches {
+            let payload = self.options.encoding.encode(&batch.messages);
+            if let Err(e) = (self.publish)(&topic, &payload) {
+                error!(
+                    "Could not publish batch of {} messages to {}: {}",
+                    batch.messages.len(),
+                    topic,
+                    e
+                );
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Publishes batches as they reach the max latency until shutdown
+    fn run_timer(&self) {
+        let mut state = self.lock();
+        while !state.shutdown {
+            let now = Instant::now();
+            let mut expired: Vec<String> = state
+                .batches
+                .iter()
+                .filter(|(_, batch)| batch.started + self.options.max_latency <= now)
+                .map(|(topic, _)| topic.clone())
+                .collect();
+            if !expired.is_empty() {
+                expired.sort_by_key(|topic| state.batches[topic].started);
+                let batches = expired
+                    .iter()
+                    .filter_map(|topic| state.batches.remove_entry(topic))
+                    .collect();
+                // errors are logged by publish
+                let _ = self.publish(batches);
+                continue;
+            }
+            let next = state
+                .batches
+                .values()
+                .map(|batch| batch.started + self.options.max_latency)
+                .min();
+            state = match next {
+                Some(deadline) => {
+                    self.changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.changed.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Records the published topics and payloads
+    #[derive(Clone, Default)]
+    struct FakeClient {
+        published: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl FakeClient {
+        fn published(&self) -> Vec<(String, String)> {
+            self.published.lock().unwrap().clone()
+        }
+    }
+
+    impl IotData for FakeClient {
+        fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+            let message = String::from_utf8_lossy(message.as_ref()).into_owned();
+            self.published
+                .lock()
+                .unwrap()
+                .push((topic.to_owned(), message));
+            Ok(())
+        }
+    }
+
+    fn options() -> BatchOptions {
+        BatchOptions::default().with_max_latency(Duration::from_secs(60))
+    }
+
+    #[test]
+    fn test_flushes_on_max_count() {
+        let client = FakeClient::default();
+        let publisher =
+            BatchingPublisher::new(client.clone(), options().with_max_count(3)).unwrap();
+        for i in 0..4 {
+            publisher.publish_json("a", i).unwrap();
+        }
+        assert_eq!(
+            client.published(),
+            vec![("a".to_owned(), "[0,1,2]".to_owned())]
+        );
+        drop(publisher);
+        assert_eq!(client.published()[1], ("a".to_owned(), "[3]".to_owned()));
+    }
+
+    #[test]
+    fn test_flushes_on_max_bytes() {
+        let client = FakeClient::default();
+        let options = options()
+            .with_max_bytes(11)
+            .with_encoding(BatchEncoding::NewlineDelimited);
+        let publisher = BatchingPublisher::new(client.clone(), options).unwrap();
+        for message in &["abc", "def", "ghi"] {
+            publisher.publish_json("a", message).unwrap();
+        }
+        let result = publisher.publish_json("a", "more than ten bytes");
+        assert!(matches!(result, Err(GGError::InvalidParameter)));
+        publisher.flush().unwrap();
+        let expected = vec![
+            ("a".to_owned(), "\"abc\"\n\"def\"".to_owned()),
+            ("a".to_owned(), "\"ghi\"".to_owned()),
+        ];
+        assert_eq!(client.published(), expected);
+    }
+
+    #[test]
+    fn test_flushes_on_max_latency() {
+        let client = FakeClient::default();
+        let options = options().with_max_latency(Duration::from_millis(10));
+        let publisher = BatchingPublisher::new(client.clone(), options).unwrap();
+        publisher.publish_json("a", 1).unwrap();
+        publisher.publish_json("b", 2).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.published().len() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let mut published = client.published();
+        published.sort();
+        let expected = vec![
+            ("a".to_owned(), "[1]".to_owned()),
+            ("b".to_owned(), "[2]".to_owned()),
+        ];
+        assert_eq!(published, expected);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let messages = vec![b"1".to_vec(), b"{}".to_vec()];
+        assert_eq!(BatchEncoding::JsonArray.encode(&messages), b"[1,{}]");
+        assert_eq!(BatchEncoding::JsonArray.encoded_len(2, 3), 6);
+        assert_eq!(BatchEncoding::NewlineDelimited.encode(&messages), b"1\n{}");
+        assert_eq!(BatchEncoding::NewlineDelimited.encoded_len(2, 3), 4);
+    }
+}
@@ -9,7 +9,7 @@
 #![allow(dead_code, improper_ctypes, unused_variables, non_upper_case_globals, non_camel_case_types,
 non_snake_case, clippy::all)]
 //! This module encapsulates the bindings for the C library
-//! The bindings are regenerated on build on every build.
+//! The bindings are regenerated on every build, unless the prebuilt-bindings feature uses the checked in ones.
 //! For testing we do two things
 //!
 //! 1. Use a mocked version with test hooks for the rest of the project
@@ -18,9 +18,25 @@ non_snake_case, clippy::all)]
 //! improper c_types is ignored. This is do to the u128 issue described here: https://github.com/rust-lang/rust-bindgen/issues/1549
 //! dead_code is allowed, do to a number of things in the bindings not being used
 
-#[cfg(all(not(test), not(feature = "coverage")))]
+#[cfg(all(
+    not(test),
+    not(feature = "coverage"),
+    not(feature = "ipc"),
+    not(feature = "prebuilt-bindings")
+))]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+#[cfg(all(
+    not(test),
+    not(feature = "coverage"),
+    not(feature = "ipc"),
+    feature = "prebuilt-bindings"
+))]
+include!("bindings/prebuilt.rs");
+
+#[cfg(all(not(test), not(feature = "coverage"), feature = "ipc"))]
+pub use crate::ipc::ffi::*;
+
 #[cfg(any(test, feature = "coverage"))]
 pub use self::test::*;
 
@@ -593,8 +609,11 @@ pub mod test {
     }
 }
 
-#[cfg(all(test, not(feature = "coverage")))]
+#[cfg(all(test, not(feature = "coverage"), not(feature = "ipc")))]
 mod bindings_test {
     // This is to make sure binding tests are still run
+    #[cfg(not(feature = "prebuilt-bindings"))]
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+    #[cfg(feature = "prebuilt-bindings")]
+    include!("bindings/prebuilt.rs");
 }
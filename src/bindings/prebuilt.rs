@@ -0,0 +1,198 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+// Bindings for greengrasssdk.h of the Greengrass Core C SDK v1 (see stubs/include/shared/greengrasssdk.h),
+// used by the prebuilt-bindings feature instead of generating them with bindgen.
+// These match what build.rs generates, without the layout tests since the same bindings are used for every target.
+
+pub const gg_error_GGE_SUCCESS: gg_error = 0;
+pub const gg_error_GGE_OUT_OF_MEMORY: gg_error = 1;
+pub const gg_error_GGE_INVALID_PARAMETER: gg_error = 2;
+pub const gg_error_GGE_INVALID_STATE: gg_error = 3;
+pub const gg_error_GGE_INTERNAL_FAILURE: gg_error = 4;
+pub const gg_error_GGE_TERMINATE: gg_error = 5;
+pub const gg_error_GGE_RESERVED_MAX: gg_error = 6;
+pub const gg_error_GGE_RESERVED_PAD: gg_error = 2147483647;
+pub type gg_error = u32;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct _gg_request {
+    _unused: [u8; 0],
+}
+pub type gg_request = *mut _gg_request;
+pub const gg_request_status_GG_REQUEST_SUCCESS: gg_request_status = 0;
+pub const gg_request_status_GG_REQUEST_HANDLED: gg_request_status = 1;
+pub const gg_request_status_GG_REQUEST_UNHANDLED: gg_request_status = 2;
+pub const gg_request_status_GG_REQUEST_UNKNOWN: gg_request_status = 3;
+pub const gg_request_status_GG_REQUEST_AGAIN: gg_request_status = 4;
+pub const gg_request_status_GG_REQUEST_RESERVED_MAX: gg_request_status = 5;
+pub const gg_request_status_GG_REQUEST_RESERVED_PAD: gg_request_status = 2147483647;
+pub type gg_request_status = u32;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_request_result {
+    pub request_status: gg_request_status,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_lambda_context {
+    pub function_arn: *const ::std::os::raw::c_char,
+    pub client_context: *const ::std::os::raw::c_char,
+}
+pub const gg_invoke_type_GG_INVOKE_EVENT: gg_invoke_type = 0;
+pub const gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE: gg_invoke_type = 1;
+pub const gg_invoke_type_GG_INVOKE_RESERVED_MAX: gg_invoke_type = 2;
+pub const gg_invoke_type_GG_INVOKE_RESERVED_PAD: gg_invoke_type = 2147483647;
+pub type gg_invoke_type = u32;
+pub const gg_runtime_opt_GG_RT_OPT_ASYNC: gg_runtime_opt = 1;
+pub const gg_runtime_opt_GG_RT_OPT_RESERVED_PAD: gg_runtime_opt = 2147483647;
+pub type gg_runtime_opt = u32;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_invoke_options {
+    pub function_arn: *const ::std::os::raw::c_char,
+    pub customer_context: *const ::std::os::raw::c_char,
+    pub qualifier: *const ::std::os::raw::c_char,
+    pub type_: gg_invoke_type,
+    pub payload: *const ::std::os::raw::c_void,
+    pub payload_size: usize,
+}
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT:
+    gg_queue_full_policy_options = 0;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR:
+    gg_queue_full_policy_options = 1;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_RESERVED_MAX:
+    gg_queue_full_policy_options = 2;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_RESERVED_PAD:
+    gg_queue_full_policy_options = 2147483647;
+pub type gg_queue_full_policy_options = u32;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct _gg_publish_options {
+    _unused: [u8; 0],
+}
+pub type gg_publish_options = *mut _gg_publish_options;
+pub const gg_log_level_GG_LOG_RESERVED_NOTSET: gg_log_level = 0;
+pub const gg_log_level_GG_LOG_DEBUG: gg_log_level = 1;
+pub const gg_log_level_GG_LOG_INFO: gg_log_level = 2;
+pub const gg_log_level_GG_LOG_WARN: gg_log_level = 3;
+pub const gg_log_level_GG_LOG_ERROR: gg_log_level = 4;
+pub const gg_log_level_GG_LOG_FATAL: gg_log_level = 5;
+pub const gg_log_level_GG_LOG_RESERVED_MAX: gg_log_level = 6;
+pub const gg_log_level_GG_LOG_RESERVED_PAD: gg_log_level = 2147483647;
+pub type gg_log_level = u32;
+extern "C" {
+    pub fn gg_global_init(opt: u32) -> gg_error;
+}
+extern "C" {
+    pub fn gg_log(level: gg_log_level, format: *const ::std::os::raw::c_char, ...) -> gg_error;
+}
+extern "C" {
+    pub fn gg_request_init(ggreq: *mut gg_request) -> gg_error;
+}
+extern "C" {
+    pub fn gg_request_close(ggreq: gg_request) -> gg_error;
+}
+extern "C" {
+    pub fn gg_request_read(
+        ggreq: gg_request,
+        buffer: *mut ::std::os::raw::c_void,
+        buffer_size: usize,
+        amount_read: *mut usize,
+    ) -> gg_error;
+}
+pub type gg_lambda_handler =
+    ::std::option::Option<unsafe extern "C" fn(cxt: *const gg_lambda_context)>;
+extern "C" {
+    pub fn gg_runtime_start(handler: gg_lambda_handler, opt: u32) -> gg_error;
+}
+extern "C" {
+    pub fn gg_lambda_handler_read(
+        buffer: *mut ::std::os::raw::c_void,
+        buffer_size: usize,
+        amount_read: *mut usize,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_lambda_handler_write_response(
+        response: *const ::std::os::raw::c_void,
+        response_size: usize,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_lambda_handler_write_error(error_message: *const ::std::os::raw::c_char) -> gg_error;
+}
+extern "C" {
+    pub fn gg_get_secret_value(
+        ggreq: gg_request,
+        secret_id: *const ::std::os::raw::c_char,
+        version_id: *const ::std::os::raw::c_char,
+        version_stage: *const ::std::os::raw::c_char,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_invoke(
+        ggreq: gg_request,
+        opts: *const gg_invoke_options,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_publish_options_init(opts: *mut gg_publish_options) -> gg_error;
+}
+extern "C" {
+    pub fn gg_publish_options_free(opts: gg_publish_options) -> gg_error;
+}
+extern "C" {
+    pub fn gg_publish_options_set_queue_full_policy(
+        opts: gg_publish_options,
+        policy: gg_queue_full_policy_options,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_publish_with_options(
+        ggreq: gg_request,
+        topic: *const ::std::os::raw::c_char,
+        payload: *const ::std::os::raw::c_void,
+        payload_size: usize,
+        opts: gg_publish_options,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_publish(
+        ggreq: gg_request,
+        topic: *const ::std::os::raw::c_char,
+        payload: *const ::std::os::raw::c_void,
+        payload_size: usize,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_get_thing_shadow(
+        ggreq: gg_request,
+        thing_name: *const ::std::os::raw::c_char,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_update_thing_shadow(
+        ggreq: gg_request,
+        thing_name: *const ::std::os::raw::c_char,
+        update_payload: *const ::std::os::raw::c_char,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
+extern "C" {
+    pub fn gg_delete_thing_shadow(
+        ggreq: gg_request,
+        thing_name: *const ::std::os::raw::c_char,
+        result: *mut gg_request_result,
+    ) -> gg_error;
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides the pool of threads that the async clients, e.g. [`crate::iotdata::AsyncIOTDataClient`], run their calls on.
+//!
+//! Calls to the C SDK block until Greengrass responds. Running them on a fixed number of threads keeps them off the
+//! threads of the executor and bounds how many of them are in flight at once.
+//!
+//! Dropping the future of a call that has not started yet cancels it. A call that has started runs to completion,
+//! as the C SDK can't be interrupted, but its result is discarded.
+use crate::error::GGError;
+use crate::GGResult;
+use crossbeam_channel::{unbounded, Sender};
+use futures::channel::oneshot;
+use lazy_static::lazy_static;
+use log::error;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+/// The number of threads of the pool shared by clients that were not given one
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+lazy_static! {
+    static ref DEFAULT_POOL: BlockingPool = BlockingPool::new(DEFAULT_POOL_SIZE);
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of threads that run blocking calls.
+/// Clones share the same threads, which exit once every clone has been dropped.
+#[derive(Clone)]
+pub struct BlockingPool {
+    sender: Sender<Job>,
+}
+
+impl BlockingPool {
+    /// Spawns a pool with the number of threads, at least one
+    pub fn new(size: usize) -> Self {
+        let (sender, receiver) = unbounded::<Job>();
+        for index in 0..size.max(1) {
+            let receiver = receiver.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("gg-blocking-{}", index))
+                .spawn(move || {
+                    for job in receiver {
+                        job();
+                    }
+                });
+            if let Err(e) = spawned {
+                error!("Could not spawn blocking worker {}: {}", index, e);
+            }
+        }
+        BlockingPool { sender }
+    }
+
+    /// Runs the call on the pool, completing with its result
+    pub async fn run<F, T>(&self, call: F) -> GGResult<T>
+    where
+        F: FnOnce() -> GGResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // The future was dropped while the call was queued
+            if sender.is_canceled() {
+                return;
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(call))
+                .unwrap_or_else(|_| Err(GGError::Unknown("Blocking call panicked".to_owned())));
+            let _ = sender.send(result);
+        });
+        self.sender
+            .send(job)
+            .map_err(|_| GGError::Unknown("Blocking pool has shut down".to_owned()))?;
+        receiver
+            .await
+            .map_err(|_| GGError::Unknown("Blocking call was dropped".to_owned()))?
+    }
+
+    /// Runs the call on the pool, failing with an [`io::ErrorKind::TimedOut`] error if it has not completed within the timeout.
+    /// Timeouts require a tokio runtime with the time driver enabled.
+    pub(crate) async fn run_with_timeout<F, T>(
+        &self,
+        timeout: Option<Duration>,
+        call: F,
+    ) -> GGResult<T>
+    where
+        F: FnOnce() -> GGResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.run(call))
+                .await
+                .map_err(|_| {
+                    GGError::from(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out waiting for Greengrass to respond",
+                    ))
+                })?,
+            None => self.run(call).await,
+        }
+    }
+}
+
+/// The pool shared by every client that was not given one
+impl Default for BlockingPool {
+    fn default() -> Self {
+        DEFAULT_POOL.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossbeam_channel::bounded;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_run() {
+        let pool = BlockingPool::new(2);
+        let name = pool
+            .run(|| Ok(thread::current().name().map(str::to_owned)))
+            .await
+            .unwrap();
+        assert!(name.unwrap().starts_with("gg-blocking-"));
+
+        let result: GGResult<()> = pool.run(|| Err(GGError::InvalidState)).await;
+        assert!(matches!(result, Err(GGError::InvalidState)));
+
+        let result: GGResult<()> = pool.run(|| panic!("boom")).await;
+        assert!(matches!(result, Err(GGError::Unknown(_))));
+        // the worker survives the panic
+        assert_eq!(pool.run(|| Ok(1)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_before_start() {
+        let pool = BlockingPool::new(1);
+        let (release, blocked) = bounded::<()>(0);
+        let busy = pool.run(move || blocked.recv().map_err(GGError::from));
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+        let queued = pool.run(move || {
+            ran_clone.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+
+        // Submit both calls, then drop the second while the first occupies the only worker
+        let mut busy = busy.boxed();
+        let mut queued = queued.boxed();
+        assert!((&mut busy).now_or_never().is_none());
+        assert!((&mut queued).now_or_never().is_none());
+        drop(queued);
+        release.send(()).unwrap();
+        busy.await.unwrap();
+
+        pool.run(|| Ok(())).await.unwrap();
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let pool = BlockingPool::new(1);
+        let result = pool
+            .run_with_timeout(Some(Duration::from_millis(10)), || {
+                thread::sleep(Duration::from_millis(200));
+                Ok(())
+            })
+            .await;
+        match result {
+            Err(GGError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            _ => panic!("Expected a timeout"),
+        }
+
+        let result = pool
+            .run_with_timeout(Some(Duration::from_secs(5)), || Ok(1))
+            .await;
+        assert_eq!(result.unwrap(), 1);
+    }
+}
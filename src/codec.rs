@@ -0,0 +1,198 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides the [`Codec`] trait, which encodes and decodes payloads, with JSON, CBOR, MessagePack and Protobuf codecs.
+//!
+//! JSON is always available. The others are enabled with the `cbor`, `msgpack` and `protobuf` features.
+//! Codecs are used with [`crate::iotdata::IotData::publish_with_codec`], [`crate::handler::LambdaContext::decode`]
+//! and the `*_with_codec` methods of [`crate::lambda::Lambda`].
+//!
+//! Shadows are always JSON, as that is what the shadow service accepts.
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::codec::{Codec, JsonCodec};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Reading {
+//!     sensor: String,
+//!     value: f64,
+//! }
+//!
+//! let reading = Reading { sensor: "temperature".to_owned(), value: 21.5 };
+//! let bytes = JsonCodec.encode(&reading).unwrap();
+//! let decoded: Reading = JsonCodec.decode(&bytes).unwrap();
+//! assert_eq!(decoded, reading);
+//! ```
+use crate::error::GGError;
+use crate::GGResult;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Encodes values of type `T` into payloads and decodes them back
+pub trait Codec<T> {
+    /// The MIME type of the payloads, e.g. application/json
+    fn content_type(&self) -> &'static str;
+
+    /// Encodes the value into a payload
+    fn encode(&self, value: &T) -> GGResult<Vec<u8>>;
+
+    /// Decodes a payload into a value
+    fn decode(&self, bytes: &[u8]) -> GGResult<T>;
+}
+
+/// Encodes serde values as JSON with serde_json
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode(&self, value: &T) -> GGResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(GGError::from)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> GGResult<T> {
+        serde_json::from_slice(bytes).map_err(GGError::from)
+    }
+}
+
+/// Encodes serde values as CBOR with serde_cbor
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for CborCodec {
+    fn content_type(&self) -> &'static str {
+        "application/cbor"
+    }
+
+    fn encode(&self, value: &T) -> GGResult<Vec<u8>> {
+        serde_cbor::to_vec(value).map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> GGResult<T> {
+        serde_cbor::from_slice(bytes).map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+}
+
+/// Encodes serde values as MessagePack with rmp-serde.
+/// Structs are encoded as maps with their field names so that other MessagePack libraries can decode them.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for MessagePackCodec {
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn encode(&self, value: &T) -> GGResult<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> GGResult<T> {
+        rmp_serde::from_slice(bytes).map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+}
+
+/// Encodes prost messages as Protobuf
+#[cfg(feature = "protobuf")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProtobufCodec;
+
+#[cfg(feature = "protobuf")]
+impl<T: prost::Message + Default> Codec<T> for ProtobufCodec {
+    fn content_type(&self) -> &'static str {
+        "application/x-protobuf"
+    }
+
+    fn encode(&self, value: &T) -> GGResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(value.encoded_len());
+        value
+            .encode(&mut bytes)
+            .map_err(|e| GGError::CodecError(Box::new(e)))?;
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> GGResult<T> {
+        T::decode(bytes).map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            sensor: "temperature".to_owned(),
+            value: 21.5,
+        }
+    }
+
+    fn round_trip<C: Codec<Reading>>(codec: C) {
+        let bytes = codec.encode(&reading()).unwrap();
+        assert_eq!(codec.decode(&bytes).unwrap(), reading());
+        let result: GGResult<Reading> = codec.decode(&[0xff, 0x00]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            JsonCodec.encode(&reading()).unwrap(),
+            br#"{"sensor":"temperature","value":21.5}"#.to_vec()
+        );
+        round_trip(JsonCodec);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        round_trip(CborCodec);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        round_trip(MessagePackCodec);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_protobuf() {
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct ProtoReading {
+            #[prost(string, tag = "1")]
+            sensor: String,
+            #[prost(double, tag = "2")]
+            value: f64,
+        }
+
+        let reading = ProtoReading {
+            sensor: "temperature".to_owned(),
+            value: 21.5,
+        };
+        let bytes = ProtobufCodec.encode(&reading).unwrap();
+        assert_eq!(bytes.len(), prost::Message::encoded_len(&reading));
+        let decoded: ProtoReading = ProtobufCodec.decode(&bytes).unwrap();
+        assert_eq!(decoded, reading);
+    }
+}
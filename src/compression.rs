@@ -0,0 +1,300 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides gzip and zstd compression of payloads, enabled with the `gzip` and `zstd` features.
+//!
+//! Compressed payloads are marked by the magic number that starts every gzip and zstd frame,
+//! so receivers can tell them apart from uncompressed ones. Text payloads, e.g. JSON, can never start with them.
+//!
+//! [`crate::iotdata::IOTDataClient::with_compression`] compresses published payloads,
+//! and [`DecompressingHandler`] decompresses the messages of a handler.
+//!
+//! ```rust
+//! # #[cfg(feature = "gzip")] {
+//! use aws_greengrass_core_rust::compression::{decompress, Compression};
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//!
+//! let client = IOTDataClient::default().with_compression(Some(Compression::Gzip(6)));
+//!
+//! let payload = r#"{"readings": [1, 2, 3]}"#.repeat(100);
+//! let compressed = Compression::Gzip(6).compress(payload.as_bytes()).unwrap();
+//! assert!(compressed.len() < payload.len());
+//! assert_eq!(decompress(&compressed, 1024 * 1024).unwrap(), payload.as_bytes());
+//! # }
+//! ```
+use crate::error::GGError;
+use crate::handler::{Handler, LambdaContext};
+use crate::runtime;
+use crate::GGResult;
+use log::error;
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+/// The default largest size of a decompressed message of a [`DecompressingHandler`]
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 1024 * 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The algorithm and level to compress payloads with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// gzip, with a level from 0 to 9
+    #[cfg(feature = "gzip")]
+    Gzip(u32),
+    /// zstd, with a level from 1 to 22
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Compresses the payload
+    pub fn compress(&self, payload: &[u8]) -> GGResult<Vec<u8>> {
+        let compressed = match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(level) => {
+                use std::io::Write;
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(payload.len() / 2),
+                    flate2::Compression::new(level),
+                );
+                encoder.write_all(payload).and_then(|_| encoder.finish())
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => zstd::stream::encode_all(payload, level),
+        };
+        compressed.map_err(|e| GGError::CodecError(Box::new(e)))
+    }
+}
+
+/// Compresses the payload with the compression, if any, unless that doesn't make it smaller
+pub(crate) fn compress_payload<'a>(
+    compression: Option<&Compression>,
+    payload: &'a [u8],
+) -> GGResult<Cow<'a, [u8]>> {
+    if let Some(compression) = compression {
+        let compressed = compression.compress(payload)?;
+        if compressed.len() < payload.len() {
+            return Ok(Cow::Owned(compressed));
+        }
+    }
+    Ok(Cow::Borrowed(payload))
+}
+
+/// True if the payload starts with the magic number of a compression whose feature is enabled
+pub fn is_compressed(payload: &[u8]) -> bool {
+    (cfg!(feature = "gzip") && payload.starts_with(GZIP_MAGIC))
+        || (cfg!(feature = "zstd") && payload.starts_with(ZSTD_MAGIC))
+}
+
+/// Decompresses the payload if it is compressed, returning it unchanged otherwise.
+/// Fails with a [`GGError::CodecError`] if it decompresses to more than `max_size` bytes,
+/// so that a small malicious payload can't exhaust memory.
+pub fn decompress(payload: &[u8], max_size: usize) -> GGResult<Cow<'_, [u8]>> {
+    let codec_error = |e: io::Error| GGError::CodecError(Box::new(e));
+    let decoder: Box<dyn Read + '_> = match payload {
+        #[cfg(feature = "gzip")]
+        p if p.starts_with(GZIP_MAGIC) => Box::new(flate2::read::GzDecoder::new(p)),
+        #[cfg(feature = "zstd")]
+        p if p.starts_with(ZSTD_MAGIC) => {
+            Box::new(zstd::stream::read::Decoder::with_buffer(p).map_err(codec_error)?)
+        }
+        _ => return Ok(Cow::Borrowed(payload)),
+    };
+
+    let mut decompressed = Vec::with_capacity(payload.len().saturating_mul(4).min(max_size));
+    decoder
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(codec_error)?;
+    if decompressed.len() > max_size {
+        return Err(codec_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Decompressed payload is larger than {} bytes", max_size),
+        )));
+    }
+    Ok(Cow::Owned(decompressed))
+}
+
+/// Adapter that decompresses the [`LambdaContext`] message before passing it to the wrapped handler.
+/// Uncompressed messages are passed on unchanged.
+///
+/// If a message can't be decompressed, or decompresses to more than the max size,
+/// the error is written as the lambda error response instead.
+///
+/// ```rust
+/// use aws_greengrass_core_rust::compression::DecompressingHandler;
+/// use aws_greengrass_core_rust::handler::{Handler, LambdaContext};
+/// use aws_greengrass_core_rust::runtime::Runtime;
+///
+/// struct MyHandler;
+///
+/// impl Handler for MyHandler {
+///     fn handle(&self, ctx: LambdaContext) {
+///         println!("Received {} bytes", ctx.message.len());
+///     }
+/// }
+///
+/// let handler = DecompressingHandler::new(MyHandler).with_max_size(256 * 1024);
+/// Runtime::default().with_handler(Some(Box::new(handler)));
+/// ```
+pub struct DecompressingHandler<H> {
+    handler: H,
+    max_size: usize,
+}
+
+impl<H: Handler> DecompressingHandler<H> {
+    /// Wraps the handler, with messages limited to [`DEFAULT_MAX_DECOMPRESSED_SIZE`]
+    pub fn new(handler: H) -> Self {
+        DecompressingHandler {
+            handler,
+            max_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+
+    /// The largest size of a decompressed message
+    pub fn with_max_size(self, max_size: usize) -> Self {
+        DecompressingHandler { max_size, ..self }
+    }
+}
+
+impl<H: Handler> Handler for DecompressingHandler<H> {
+    fn handle(&self, ctx: LambdaContext) {
+        let message = match decompress(&ctx.message, self.max_size) {
+            Ok(Cow::Borrowed(_)) => None,
+            Ok(Cow::Owned(message)) => Some(message),
+            Err(e) => {
+                error!("Could not decompress message: {}", e);
+                if let Err(e) = runtime::send_response(Err(&format!("{}", e))) {
+                    error!("Error writing error response: {}", e);
+                }
+                return;
+            }
+        };
+        let mut ctx = ctx;
+        if let Some(message) = message {
+            ctx.message = message;
+        }
+        self.handler.handle(ctx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn algorithms() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(6),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(3),
+        ]
+    }
+
+    fn payload() -> Vec<u8> {
+        r#"{"sensor": "temperature", "value": 21.5}"#.repeat(50).into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for compression in algorithms() {
+            let compressed = compression.compress(&payload()).unwrap();
+            assert!(is_compressed(&compressed));
+            assert!(compressed.len() < payload().len() / 5);
+            assert_eq!(decompress(&compressed, 1024 * 1024).unwrap(), payload());
+        }
+        let uncompressed = decompress(b"{}", 1024).unwrap();
+        assert!(matches!(uncompressed, Cow::Borrowed(b"{}")));
+    }
+
+    #[test]
+    fn test_compresses_only_if_smaller() {
+        let payload = payload();
+        for compression in algorithms() {
+            let small = compress_payload(Some(&compression), b"{}").unwrap();
+            assert_eq!(small, Cow::Borrowed(b"{}" as &[u8]));
+            let large = compress_payload(Some(&compression), &payload).unwrap();
+            assert!(matches!(large, Cow::Owned(_)));
+        }
+    }
+
+    #[test]
+    fn test_max_size() {
+        let bomb = vec![0u8; 1024 * 1024];
+        for compression in algorithms() {
+            let compressed = compression.compress(&bomb).unwrap();
+            assert!(decompress(&compressed, bomb.len()).is_ok());
+            let result = decompress(&compressed, 1024);
+            assert!(matches!(result, Err(GGError::CodecError(_))));
+        }
+    }
+
+    struct RecordingHandler {
+        messages: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl Handler for RecordingHandler {
+        fn handle(&self, ctx: LambdaContext) {
+            self.messages.lock().unwrap().push(ctx.message);
+        }
+    }
+
+    #[test]
+    fn test_decompressing_handler() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let handler = DecompressingHandler::new(RecordingHandler {
+            messages: Arc::clone(&messages),
+        });
+        for compression in algorithms() {
+            let compressed = compression.compress(&payload()).unwrap();
+            handler.handle(LambdaContext::new(
+                "arn".to_owned(),
+                String::new(),
+                compressed,
+            ));
+        }
+        handler.handle(LambdaContext::new(
+            "arn".to_owned(),
+            String::new(),
+            payload(),
+        ));
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), algorithms().len() + 1);
+        assert!(messages.iter().all(|message| *message == payload()));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_decompressing_handler_too_large() {
+        use crate::bindings::*;
+        use crate::runtime::test::runtime_lock;
+
+        let _lock = runtime_lock();
+        reset_test_state();
+        let messages = Arc::new(Mutex::new(vec![]));
+        let handler = DecompressingHandler::new(RecordingHandler {
+            messages: Arc::clone(&messages),
+        })
+        .with_max_size(10);
+        let compressed = algorithms()[0].compress(&payload()).unwrap();
+        handler.handle(LambdaContext::new(
+            "arn".to_owned(),
+            String::new(),
+            compressed,
+        ));
+        assert!(messages.lock().unwrap().is_empty());
+        GG_LAMBDA_HANDLER_WRITE_ERROR.with(|rc| {
+            assert_eq!(
+                *rc.borrow(),
+                "Error encoding or decoding payload: Decompressed payload is larger than 10 bytes"
+            );
+        });
+    }
+}
@@ -15,11 +15,10 @@ use crossbeam_channel::{RecvError, SendError};
 use log::error;
 use serde_json::Error as SerdeError;
 use std::convert::From;
-use std::convert::Into;
 use std::error::Error;
 use std::ffi;
 use std::fmt;
-use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use std::io::Error as IOError;
 use std::string::FromUtf8Error;
 
 /// Provices a wrapper for the various errors that are incurred both working with the
@@ -42,8 +41,9 @@ pub enum GGError {
     InvalidString(String),
     /// If receive an error type from the C API that isn't known
     Unknown(String),
-    /// If there are issues in communicating to the Handler  
-    HandlerChannelSendError(SendError<LambdaContext>),
+    /// If there are issues in communicating to the Handler.
+    /// Boxed as it contains the LambdaContext that could not be sent
+    HandlerChannelSendError(Box<SendError<LambdaContext>>),
     /// If there are issues in communicating to the Handler  
     HandlerChannelRecvError(RecvError),
     /// If an AWS response contains an unauthorized error code
@@ -54,6 +54,13 @@ pub enum GGError {
     /// If the error is a 404, it should be handled as an Option instead. Otherwise
     /// this error type can be returned.
     ErrorResponse(GGRequestResponse),
+    /// If communicating with the Greengrass nucleus over its IPC socket failed
+    IoError(IOError),
+    /// If a request failed after being retried by a [`crate::retry::RetryPolicy`],
+    /// with the number of attempts made and the error of the last one
+    RetryFailed(u32, Box<GGError>),
+    /// If a payload could not be encoded or decoded by a [`crate::codec::Codec`], or compressed or decompressed
+    CodecError(Box<dyn Error + Send + Sync>),
 }
 
 impl GGError {
@@ -78,7 +85,7 @@ impl GGError {
     // Converts the error to an IoError
     #[allow(clippy::wrong_self_convention)]
     pub fn as_ioerror(self) -> IOError {
-        IOError::new(IOErrorKind::Other, self)
+        IOError::other(self)
     }
 }
 
@@ -102,6 +109,11 @@ impl fmt::Display for GGError {
             Self::InvalidString(ref e) => write!(f, "Invalid String: {}", e),
             Self::Unauthorized(ref s) => write!(f, "{}", s),
             Self::ErrorResponse(ref r) => write!(f, "Green responded with error: {:?}", r),
+            Self::IoError(ref e) => write!(f, "IPC error: {}", e),
+            Self::RetryFailed(attempts, ref e) => {
+                write!(f, "Failed after {} attempts: {}", attempts, e)
+            }
+            Self::CodecError(ref e) => write!(f, "Error encoding or decoding payload: {}", e),
         }
     }
 }
@@ -110,9 +122,12 @@ impl Error for GGError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             Self::NulError(ref e) => Some(e),
-            Self::HandlerChannelSendError(ref e) => Some(e),
+            Self::HandlerChannelSendError(ref e) => Some(e.as_ref()),
             Self::HandlerChannelRecvError(ref e) => Some(e),
             Self::JsonError(ref e) => Some(e),
+            Self::IoError(ref e) => Some(e),
+            Self::RetryFailed(_, ref e) => Some(e.as_ref()),
+            Self::CodecError(ref e) => Some(e.as_ref()),
             _ => None,
         }
     }
@@ -126,7 +141,7 @@ impl From<ffi::NulError> for GGError {
 
 impl From<SendError<LambdaContext>> for GGError {
     fn from(e: SendError<LambdaContext>) -> Self {
-        GGError::HandlerChannelSendError(e)
+        GGError::HandlerChannelSendError(Box::new(e))
     }
 }
 
@@ -136,9 +151,19 @@ impl From<RecvError> for GGError {
     }
 }
 
-impl Into<IOError> for GGError {
-    fn into(self) -> IOError {
-        self.as_ioerror()
+impl From<GGError> for IOError {
+    fn from(e: GGError) -> Self {
+        e.as_ioerror()
+    }
+}
+
+impl From<IOError> for GGError {
+    fn from(e: IOError) -> Self {
+        // Errors converted with as_ioerror, e.g. by a reader, are unwrapped
+        match e.downcast::<GGError>() {
+            Ok(e) => e,
+            Err(e) => Self::IoError(e),
+        }
     }
 }
 
@@ -0,0 +1,260 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Safe wrappers over the functions of the C SDK used by [`crate::backend::CBackend`].
+//!
+//! Strings are passed as borrowed [`CStr`]s, so the compiler checks that they live until the call returns.
+//! Publish options are held by a [`PublishOptionsHandle`] that frees them when it is dropped.
+use crate::bindings::*;
+use crate::error::GGError;
+use crate::request::GGRequestStatus;
+use crate::GGResult;
+use log::error;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+/// Publish options created with gg_publish_options_init, freed when dropped
+pub(crate) struct PublishOptionsHandle(gg_publish_options);
+
+impl PublishOptionsHandle {
+    pub fn new() -> GGResult<Self> {
+        let mut opts: gg_publish_options = ptr::null_mut();
+        GGError::from_code(unsafe { gg_publish_options_init(&mut opts) })?;
+        Ok(PublishOptionsHandle(opts))
+    }
+
+    pub fn set_queue_full_policy(&mut self, policy: gg_queue_full_policy_options) -> GGResult<()> {
+        GGError::from_code(unsafe { gg_publish_options_set_queue_full_policy(self.0, policy) })
+    }
+}
+
+impl Drop for PublishOptionsHandle {
+    fn drop(&mut self) {
+        if let Err(e) = GGError::from_code(unsafe { gg_publish_options_free(self.0) }) {
+            error!("Could not free publish options: {}", e);
+        }
+    }
+}
+
+/// The pointer to pass for an optional string argument, null if there is none
+fn optional_ptr(s: Option<&CStr>) -> *const c_char {
+    s.map_or(ptr::null(), CStr::as_ptr)
+}
+
+fn new_result() -> gg_request_result {
+    gg_request_result {
+        request_status: gg_request_status_GG_REQUEST_SUCCESS,
+    }
+}
+
+/// Converts the error code of a request function and its result into the request status
+fn request_status(code: gg_error, res: &gg_request_result) -> GGResult<GGRequestStatus> {
+    GGError::from_code(code)?;
+    GGRequestStatus::try_from(res.request_status)
+}
+
+pub(crate) fn request_init() -> GGResult<gg_request> {
+    let mut req: gg_request = ptr::null_mut();
+    GGError::from_code(unsafe { gg_request_init(&mut req) })?;
+    Ok(req)
+}
+
+// The functions below take a request and are unsafe because the C SDK trusts it.
+// The request must have been created by request_init and not yet been closed.
+
+pub(crate) unsafe fn request_read(req: gg_request, buffer: &mut [u8]) -> GGResult<usize> {
+    let mut read: usize = 0;
+    GGError::from_code(gg_request_read(
+        req,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        &mut read,
+    ))?;
+    Ok(read)
+}
+
+pub(crate) unsafe fn request_close(req: gg_request) -> GGResult<()> {
+    GGError::from_code(gg_request_close(req))
+}
+
+pub(crate) unsafe fn publish(
+    req: gg_request,
+    topic: &CStr,
+    payload: &[u8],
+    options: Option<&PublishOptionsHandle>,
+) -> GGResult<GGRequestStatus> {
+    let mut res = new_result();
+    let code = match options {
+        Some(options) => gg_publish_with_options(
+            req,
+            topic.as_ptr(),
+            payload.as_ptr() as *const c_void,
+            payload.len(),
+            options.0,
+            &mut res,
+        ),
+        None => gg_publish(
+            req,
+            topic.as_ptr(),
+            payload.as_ptr() as *const c_void,
+            payload.len(),
+            &mut res,
+        ),
+    };
+    request_status(code, &res)
+}
+
+pub(crate) unsafe fn invoke(
+    req: gg_request,
+    function_arn: &CStr,
+    customer_context: &CStr,
+    qualifier: &CStr,
+    invoke_type: gg_invoke_type,
+    payload: Option<&[u8]>,
+) -> GGResult<GGRequestStatus> {
+    let (payload, payload_size) = match payload {
+        Some(p) => (p.as_ptr() as *const c_void, p.len()),
+        None => (ptr::null(), 0),
+    };
+    let options = gg_invoke_options {
+        function_arn: function_arn.as_ptr(),
+        customer_context: customer_context.as_ptr(),
+        qualifier: qualifier.as_ptr(),
+        type_: invoke_type,
+        payload,
+        payload_size,
+    };
+    let mut res = new_result();
+    let code = gg_invoke(req, &options, &mut res);
+    request_status(code, &res)
+}
+
+pub(crate) unsafe fn get_thing_shadow(
+    req: gg_request,
+    thing_name: &CStr,
+) -> GGResult<GGRequestStatus> {
+    let mut res = new_result();
+    let code = gg_get_thing_shadow(req, thing_name.as_ptr(), &mut res);
+    request_status(code, &res)
+}
+
+pub(crate) unsafe fn update_thing_shadow(
+    req: gg_request,
+    thing_name: &CStr,
+    document: &CStr,
+) -> GGResult<GGRequestStatus> {
+    let mut res = new_result();
+    let code = gg_update_thing_shadow(req, thing_name.as_ptr(), document.as_ptr(), &mut res);
+    request_status(code, &res)
+}
+
+pub(crate) unsafe fn delete_thing_shadow(
+    req: gg_request,
+    thing_name: &CStr,
+) -> GGResult<GGRequestStatus> {
+    let mut res = new_result();
+    let code = gg_delete_thing_shadow(req, thing_name.as_ptr(), &mut res);
+    request_status(code, &res)
+}
+
+pub(crate) unsafe fn get_secret_value(
+    req: gg_request,
+    secret_id: &CStr,
+    version_id: Option<&CStr>,
+    version_stage: Option<&CStr>,
+) -> GGResult<GGRequestStatus> {
+    let mut res = new_result();
+    let code = gg_get_secret_value(
+        req,
+        secret_id.as_ptr(),
+        optional_ptr(version_id),
+        optional_ptr(version_stage),
+        &mut res,
+    );
+    request_status(code, &res)
+}
+
+pub(crate) fn log(level: gg_log_level, message: &CStr) -> GGResult<()> {
+    GGError::from_code(unsafe { gg_log(level, message.as_ptr()) })
+}
+
+pub(crate) fn handler_read(buffer: &mut [u8]) -> GGResult<usize> {
+    let mut read: usize = 0;
+    GGError::from_code(unsafe {
+        gg_lambda_handler_read(buffer.as_mut_ptr() as *mut c_void, buffer.len(), &mut read)
+    })?;
+    Ok(read)
+}
+
+pub(crate) fn handler_write_response(response: &[u8]) -> GGResult<()> {
+    GGError::from_code(unsafe {
+        gg_lambda_handler_write_response(response.as_ptr() as *const c_void, response.len())
+    })
+}
+
+pub(crate) fn handler_write_error(message: &CStr) -> GGResult<()> {
+    GGError::from_code(unsafe { gg_lambda_handler_write_error(message.as_ptr()) })
+}
+
+/// These tests only call the test bindings, so they can also be run with `cargo +nightly miri test`
+#[cfg(all(test, not(feature = "mock")))]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_publish_options_freed_on_drop() {
+        reset_test_state();
+        let topic = CString::new("my/topic").unwrap();
+        let mut options = PublishOptionsHandle::new().unwrap();
+        options
+            .set_queue_full_policy(gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR)
+            .unwrap();
+        let req = request_init().unwrap();
+        let status = unsafe { publish(req, &topic, b"payload", Some(&options)) }.unwrap();
+        assert_eq!(status, GGRequestStatus::Success);
+        GG_PUBLISH_OPTION_FREE_COUNT.with(|rc| assert_eq!(*rc.borrow(), 0));
+
+        drop(options);
+        GG_PUBLISH_OPTION_INIT_COUNT.with(|rc| assert_eq!(*rc.borrow(), 1));
+        GG_PUBLISH_OPTION_FREE_COUNT.with(|rc| assert_eq!(*rc.borrow(), 1));
+        GG_PUBLISH_WITH_OPTIONS_ARGS.with(|rc| {
+            assert_eq!(rc.borrow().topic, "my/topic");
+            assert_eq!(rc.borrow().payload, b"payload");
+        });
+        GG_PUBLISH_OPTIONS_SET_QUEUE_FULL_POLICY.with(|rc| {
+            assert_eq!(
+                *rc.borrow(),
+                gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR
+            )
+        });
+        unsafe { request_close(req) }.unwrap();
+    }
+
+    #[test]
+    fn test_optional_strings() {
+        reset_test_state();
+        let secret_id = CString::new("my_secret").unwrap();
+        let version_stage = CString::new("AWSCURRENT").unwrap();
+        let req = request_init().unwrap();
+        unsafe { get_secret_value(req, &secret_id, None, Some(&version_stage)) }.unwrap();
+        GG_GET_SECRET_VALUE_ARGS.with(|rc| {
+            let args = rc.borrow();
+            assert_eq!(args.secret_id, "my_secret");
+            assert_eq!(args.version_id, None);
+            assert_eq!(args.version_stage, Some("AWSCURRENT".to_owned()));
+        });
+
+        GG_GET_SECRET_VALUE_RETURN.with(|rc| rc.replace(gg_error_GGE_INVALID_STATE));
+        let result = unsafe { get_secret_value(req, &secret_id, None, None) };
+        assert!(result.is_err());
+        unsafe { request_close(req) }.unwrap();
+    }
+}
@@ -25,26 +25,204 @@
 //! let runtime = Runtime::default().with_handler(Some(Box::new(MyHandler)));
 //! Initializer::default().with_runtime(runtime).init();
 //! ```
+//!
+//! ## Registering an AsyncHandler
+//! Requires the `async` feature.
+//! ```ignore
+//! use aws_greengrass_core_rust::handler::{AsyncHandler, LambdaContext};
+//! use aws_greengrass_core_rust::runtime::Runtime;
+//! use aws_greengrass_core_rust::Initializer;
+//! use futures::future::{BoxFuture, FutureExt};
+//!
+//! struct MyAsyncHandler;
+//! impl AsyncHandler for MyAsyncHandler {
+//!     fn handle(&self, ctx: LambdaContext) -> BoxFuture<'static, ()> {
+//!         async move {
+//!             println!("Received an event! {:?}", ctx);
+//!         }
+//!         .boxed()
+//!     }
+//! }
+//!
+//! let runtime = Runtime::default().with_async_handler(Some(Box::new(MyAsyncHandler)));
+//! Initializer::default().with_runtime(runtime).init();
+//! ```
+
+use crate::backend::{Backend, InvocationHandle};
+use crate::codec::Codec;
+use crate::error::GGError;
+use crate::request::{read_chunks, DEFAULT_CHUNK_SIZE};
+use crate::runtime;
+use crate::GGResult;
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, Read};
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// Provides information around the the event that was received
 #[derive(Debug, Clone, PartialEq)]
 pub struct LambdaContext {
     /// The full lambda ARN
     pub function_arn: String,
-    /// Client context information
+    /// Client context information, as received from greengrass
     pub client_context: String,
     /// The message received in bytes
     pub message: Vec<u8>,
+    /// The client context decoded when the context was created, or why it could not be decoded
+    parsed_client_context: Result<Option<ClientContext>, String>,
+    /// The invocation, if it was detached from the handler callback to be handled on another thread
+    invocation: Option<InvocationHandle>,
 }
 
 impl LambdaContext {
     pub fn new(function_arn: String, client_context: String, message: Vec<u8>) -> Self {
+        let parsed_client_context = if client_context.is_empty() {
+            Ok(None)
+        } else {
+            ClientContext::decode(&client_context)
+                .map(Some)
+                .map_err(|e| e.to_string())
+        };
         LambdaContext {
             function_arn,
             client_context,
             message,
+            parsed_client_context,
+            invocation: None,
         }
     }
+
+    pub(crate) fn with_invocation(self, invocation: Option<InvocationHandle>) -> Self {
+        LambdaContext { invocation, ..self }
+    }
+
+    /// The invocation that responses to this event are written for, when it is handled on another thread than
+    /// the handler callback
+    pub(crate) fn invocation(&self) -> Option<&InvocationHandle> {
+        self.invocation.as_ref()
+    }
+
+    /// The client context decoded into its sections.
+    /// None if the client context was empty or could not be decoded.
+    pub fn parsed_client_context(&self) -> Option<&ClientContext> {
+        self.parsed_client_context
+            .as_ref()
+            .ok()
+            .and_then(Option::as_ref)
+    }
+
+    /// Why the client context could not be decoded, if it could not be
+    pub fn client_context_error(&self) -> Option<&str> {
+        self.parsed_client_context
+            .as_ref()
+            .err()
+            .map(String::as_str)
+    }
+
+    /// The MQTT topic the message was received on, if the lambda was triggered by a subscription
+    pub fn subject(&self) -> Option<&str> {
+        self.parsed_client_context()
+            .and_then(ClientContext::subject)
+    }
+
+    /// Deserializes the custom section of the client context.
+    /// This will contain the data sent with [`aws_greengrass_core_rust::lambda::InvokeOptions::customer_context`]
+    /// when the lambda was invoked by another lambda.
+    ///
+    /// Returns Ok(None) if there is no custom section.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::handler::LambdaContext;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct MyContext {
+    ///     foo: String,
+    /// }
+    ///
+    /// let raw = base64::encode(r#"{"custom": {"foo": "bar"}}"#);
+    /// let ctx = LambdaContext::new("my_arn".to_owned(), raw, vec![]);
+    /// let custom: MyContext = ctx.custom().unwrap().unwrap();
+    /// assert_eq!(custom.foo, "bar");
+    /// ```
+    pub fn custom<T: DeserializeOwned>(&self) -> GGResult<Option<T>> {
+        match self.parsed_client_context().and_then(|c| c.custom.as_ref()) {
+            Some(custom) => T::deserialize(custom).map(Some).map_err(GGError::from),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the message with the codec
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::codec::JsonCodec;
+    /// use aws_greengrass_core_rust::handler::LambdaContext;
+    ///
+    /// let ctx = LambdaContext::new("my_arn".to_owned(), String::new(), b"[1,2,3]".to_vec());
+    /// let readings: Vec<u32> = ctx.decode(&JsonCodec).unwrap();
+    /// assert_eq!(readings, vec![1, 2, 3]);
+    /// ```
+    pub fn decode<T, C: Codec<T>>(&self, codec: &C) -> GGResult<T> {
+        codec.decode(&self.message)
+    }
+}
+
+/// The client context sent by greengrass with each event.
+///
+/// Greengrass sends the client context as a base64 encoded JSON document with `custom`, `client` and `env` sections.
+/// Subscriptions place the MQTT topic the message arrived on in `custom.subject`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ClientContext {
+    /// Custom values, including the MQTT subject
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<Value>,
+    /// Information about the client application
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<Value>,
+    /// Information about the environment of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Value>,
+}
+
+impl ClientContext {
+    /// Decodes the raw client context.
+    ///
+    /// The raw value is expected to be base64 encoded JSON, but plain JSON is also accepted.
+    /// A document that contains none of the `custom`, `client` or `env` sections is treated as
+    /// the custom section, as this is how a customer context sent from [`aws_greengrass_core_rust::lambda::LambdaClient`]
+    /// arrives.
+    pub fn decode(raw: &str) -> GGResult<Self> {
+        let json = match base64::decode(raw.trim()) {
+            Ok(bytes) => bytes,
+            Err(_) => raw.as_bytes().to_vec(),
+        };
+        let doc: Map<String, Value> = serde_json::from_slice(&json).map_err(GGError::from)?;
+        if ["custom", "client", "env"]
+            .iter()
+            .any(|section| doc.contains_key(*section))
+        {
+            serde_json::from_value(Value::Object(doc)).map_err(GGError::from)
+        } else {
+            Ok(ClientContext {
+                custom: Some(Value::Object(doc)),
+                ..ClientContext::default()
+            })
+        }
+    }
+
+    /// The MQTT topic the message was received on
+    pub fn subject(&self) -> Option<&str> {
+        self.custom
+            .as_ref()
+            .and_then(|c| c.get("subject"))
+            .and_then(Value::as_str)
+    }
 }
 
 /// Trait to implement for specifying a handler to the greengrass runtime.
@@ -55,9 +233,261 @@ pub trait Handler {
     fn handle(&self, ctx: LambdaContext);
 }
 
+/// Trait to implement for lambdas that are invoked with a request-response invocation
+/// (e.g. with [`aws_greengrass_core_rust::lambda::LambdaClient::invoke_sync`]).
+///
+/// The runtime writes the returned value back to the caller as the response. If an error is returned,
+/// or the handler panics, an error response is written instead.
+///
+/// See [`aws_greengrass_core_rust::runtime::Runtime::with_response_handler`] on registering response handlers.
+///
+/// ```rust
+/// use aws_greengrass_core_rust::handler::{LambdaContext, ResponseHandler};
+///
+/// struct EchoHandler;
+///
+/// impl ResponseHandler for EchoHandler {
+///     type Error = String;
+///
+///     fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
+///         if ctx.message.is_empty() {
+///             Err("No message to echo".to_owned())
+///         } else {
+///             Ok(ctx.message)
+///         }
+///     }
+/// }
+/// ```
+pub trait ResponseHandler {
+    type Error: Display;
+
+    fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Trait to implement for handlers that stream the message of an event instead of receiving it in memory.
+///
+/// The message is read from the [`HandlerInput`] as it is needed, so the message of the [`LambdaContext`] is empty.
+/// Streaming handlers are called on the thread the C SDK delivers events on, as the message can only be read there,
+/// and the SDK moves on to the next event once the handler has returned. If the handler panics an error response is written.
+///
+/// See [`aws_greengrass_core_rust::runtime::Runtime::with_streaming_handler`] on registering streaming handlers.
+///
+/// ```rust
+/// use aws_greengrass_core_rust::handler::{HandlerInput, LambdaContext, StreamingHandler};
+/// use log::info;
+/// use std::io::BufRead;
+///
+/// struct LineCounter;
+///
+/// impl StreamingHandler for LineCounter {
+///     fn handle(&self, ctx: LambdaContext, input: HandlerInput) {
+///         let lines = input.lines().take_while(Result::is_ok).count();
+///         info!("Received {} lines from {}", lines, ctx.function_arn);
+///     }
+/// }
+/// ```
+pub trait StreamingHandler {
+    fn handle(&self, ctx: LambdaContext, input: HandlerInput);
+}
+
+/// Function wrapped by a [`JsonHandler`] with its error type converted to a String
+type JsonHandlerFn<In, Out> = dyn Fn(In) -> Result<Out, String> + Send + Sync;
+
+/// Adapter that decodes the [`LambdaContext`] message as JSON, passes it to a function and
+/// serializes the function's output as the lambda response.
+///
+/// If the message cannot be decoded, the output cannot be encoded, or the function returns an error,
+/// a JSON [`JsonErrorResponse`] is written as the error response.
+///
+/// JsonHandler implements both [`Handler`] and [`ResponseHandler`] so it can be registered with either
+/// [`aws_greengrass_core_rust::runtime::Runtime::with_handler`] or
+/// [`aws_greengrass_core_rust::runtime::Runtime::with_response_handler`].
+///
+/// ```rust
+/// use aws_greengrass_core_rust::handler::JsonHandler;
+/// use aws_greengrass_core_rust::runtime::Runtime;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize)]
+/// struct Request {
+///     name: String,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Response {
+///     greeting: String,
+/// }
+///
+/// let handler = JsonHandler::new(|req: Request| -> Result<Response, String> {
+///     Ok(Response { greeting: format!("Hello {}", req.name) })
+/// });
+/// Runtime::default().with_handler(Some(Box::new(handler)));
+/// ```
+pub struct JsonHandler<In, Out> {
+    handler: Box<JsonHandlerFn<In, Out>>,
+    _marker: PhantomData<fn(In) -> Out>,
+}
+
+impl<In: DeserializeOwned, Out: Serialize> JsonHandler<In, Out> {
+    /// Creates a JsonHandler that calls the specified function with each decoded message
+    pub fn new<F, E>(handler: F) -> Self
+    where
+        F: Fn(In) -> Result<Out, E> + Send + Sync + 'static,
+        E: Display,
+    {
+        JsonHandler {
+            handler: Box::new(move |input| handler(input).map_err(|e| format!("{}", e))),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<In: DeserializeOwned, Out: Serialize> ResponseHandler for JsonHandler<In, Out> {
+    type Error = JsonErrorResponse;
+
+    fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
+        let input = serde_json::from_slice::<In>(&ctx.message)
+            .map_err(|e| JsonErrorResponse::new(JsonErrorType::DecodeError, e))?;
+        let output = (self.handler)(input)
+            .map_err(|e| JsonErrorResponse::new(JsonErrorType::HandlerError, e))?;
+        serde_json::to_vec(&output)
+            .map_err(|e| JsonErrorResponse::new(JsonErrorType::EncodeError, e))
+    }
+}
+
+impl<In: DeserializeOwned, Out: Serialize> Handler for JsonHandler<In, Out> {
+    fn handle(&self, ctx: LambdaContext) {
+        let result = ResponseHandler::handle(self, ctx);
+        let send_result = match &result {
+            Ok(bytes) => runtime::send_response(Ok(bytes.as_slice())),
+            Err(e) => {
+                error!("JSON handler failed: {}", e);
+                runtime::send_response(Err(&format!("{}", e)))
+            }
+        };
+        if let Err(e) = send_result {
+            error!("Error sending response: {}", e);
+        }
+    }
+}
+
+/// The kind of failure described by a [`JsonErrorResponse`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum JsonErrorType {
+    /// The message could not be deserialized
+    DecodeError,
+    /// The output of the handler could not be serialized
+    EncodeError,
+    /// The handler function returned an error
+    HandlerError,
+}
+
+/// Error response written by a [`JsonHandler`].
+/// This is written to the caller as a JSON object, e.g.
+/// `{"errorType":"DecodeError","errorMessage":"expected value at line 1 column 1"}`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonErrorResponse {
+    pub error_type: JsonErrorType,
+    pub error_message: String,
+}
+
+impl JsonErrorResponse {
+    fn new<E: Display>(error_type: JsonErrorType, e: E) -> Self {
+        JsonErrorResponse {
+            error_type,
+            error_message: format!("{}", e),
+        }
+    }
+}
+
+impl Display for JsonErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match serde_json::to_string(self) {
+            Ok(json) => write!(f, "{}", json),
+            Err(_) => write!(f, "{:?}: {}", self.error_type, self.error_message),
+        }
+    }
+}
+
+/// Streams the message of the event being handled from the backend.
+///
+/// The C SDK only lets the callback that received an event read its message, so the runtime reads the message
+/// of each event with this reader before it is passed to a [`Handler`], [`ResponseHandler`] or `AsyncHandler`.
+/// A [`StreamingHandler`] is called on the thread of the callback instead and is given the reader of its event.
+/// The message is read in chunks of [`DEFAULT_CHUNK_SIZE`] bytes unless another size is set.
+/// [`Read::read_to_end`] reads the chunks into the vec without copying them through the buffer,
+/// reserving the size hint up front.
+pub struct HandlerInput {
+    inner: BufReader<RawHandlerInput>,
+    size_hint: usize,
+}
+
+impl HandlerInput {
+    pub fn new(backend: Arc<dyn Backend>) -> Self {
+        HandlerInput {
+            inner: BufReader::with_capacity(DEFAULT_CHUNK_SIZE, RawHandlerInput(backend)),
+            size_hint: 0,
+        }
+    }
+
+    /// The number of bytes to read from the backend at a time.
+    /// Anything already buffered is discarded, so this should be set before reading.
+    pub fn with_chunk_size(self, chunk_size: usize) -> Self {
+        HandlerInput {
+            inner: BufReader::with_capacity(chunk_size.max(1), self.inner.into_inner()),
+            ..self
+        }
+    }
+
+    /// The expected size of the message, reserved when it is read to the end
+    pub fn with_size_hint(self, size_hint: usize) -> Self {
+        HandlerInput { size_hint, ..self }
+    }
+}
+
+impl Read for HandlerInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        read_chunks(&mut self.inner, buf, self.size_hint)
+    }
+}
+
+impl BufRead for HandlerInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Reads the message from the backend without buffering
+struct RawHandlerInput(Arc<dyn Backend>);
+
+impl Read for RawHandlerInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.handler_read(buf).map_err(io::Error::from)
+    }
+}
+
+/// Asynchronous version of [`Handler`].
+/// The returned future will be spawned on the executor the runtime was configured with.
+///
+/// See [`aws_greengrass_core_rust::runtime::Runtime::with_async_handler`] on registering async handlers.
+#[cfg(feature = "async")]
+pub trait AsyncHandler {
+    fn handle(&self, ctx: LambdaContext) -> BoxFuture<'static, ()>;
+}
+
 #[cfg(test)]
 mod test {
-    use crate::handler::LambdaContext;
+    use super::*;
+    use serde::Deserialize;
 
     #[test]
     fn test_new() {
@@ -74,4 +504,143 @@ mod test {
         let cloned = ctx.message.to_owned();
         assert_eq!(cloned, message.clone());
     }
+
+    #[test]
+    fn test_subject() {
+        let raw = base64::encode(r#"{"custom":{"subject":"my/topic"},"client":{},"env":{}}"#);
+        let ctx = LambdaContext::new("arn".to_owned(), raw.clone(), vec![]);
+        assert_eq!(ctx.client_context, raw);
+        assert_eq!(ctx.subject(), Some("my/topic"));
+        assert!(ctx.client_context_error().is_none());
+        let parsed = ctx.parsed_client_context().unwrap();
+        assert_eq!(parsed.client, Some(serde_json::json!({})));
+        assert_eq!(parsed.env, Some(serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_undecodable_client_context() {
+        let ctx = LambdaContext::new("arn".to_owned(), "not a context".to_owned(), vec![]);
+        assert!(ctx.parsed_client_context().is_none());
+        assert!(ctx.client_context_error().is_some());
+        assert!(ctx.subject().is_none());
+        assert!(ctx.custom::<Value>().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_custom() {
+        #[derive(Deserialize, Serialize, Debug, PartialEq)]
+        struct Custom {
+            foo: String,
+        }
+
+        let wrapped = base64::encode(r#"{"custom":{"foo":"bar"}}"#);
+        let ctx = LambdaContext::new("arn".to_owned(), wrapped, vec![]);
+        assert_eq!(
+            ctx.custom::<Custom>().unwrap(),
+            Some(Custom {
+                foo: "bar".to_owned()
+            })
+        );
+
+        // customer contexts sent with InvokeOptions are not wrapped in a custom section
+        let unwrapped = base64::encode(r#"{"foo":"baz"}"#);
+        let ctx = LambdaContext::new("arn".to_owned(), unwrapped, vec![]);
+        assert_eq!(
+            ctx.custom::<Custom>().unwrap(),
+            Some(Custom {
+                foo: "baz".to_owned()
+            })
+        );
+        assert!(ctx.custom::<Vec<String>>().is_err());
+    }
+
+    #[derive(Deserialize)]
+    struct TestRequest {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestResponse {
+        greeting: String,
+    }
+
+    fn test_json_handler() -> JsonHandler<TestRequest, TestResponse> {
+        JsonHandler::new(|req: TestRequest| {
+            if req.name.is_empty() {
+                Err("name is required")
+            } else {
+                Ok(TestResponse {
+                    greeting: format!("Hello {}", req.name),
+                })
+            }
+        })
+    }
+
+    fn context_with(message: &str) -> LambdaContext {
+        LambdaContext::new("arn".to_owned(), "".to_owned(), message.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_json_handler_response() {
+        let response =
+            ResponseHandler::handle(&test_json_handler(), context_with(r#"{"name": "Bob"}"#));
+        assert_eq!(response.unwrap(), br#"{"greeting":"Hello Bob"}"#.to_vec());
+    }
+
+    #[test]
+    fn test_json_handler_decode_error() {
+        let err =
+            ResponseHandler::handle(&test_json_handler(), context_with("not json")).unwrap_err();
+        assert_eq!(err.error_type, JsonErrorType::DecodeError);
+        assert!(format!("{}", err).starts_with(r#"{"errorType":"DecodeError","errorMessage":"#));
+    }
+
+    #[test]
+    fn test_json_handler_handler_error() {
+        let err = ResponseHandler::handle(&test_json_handler(), context_with(r#"{"name": ""}"#))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            JsonErrorResponse::new(JsonErrorType::HandlerError, "name is required")
+        );
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_json_handler_writes_response() {
+        use crate::bindings::*;
+        use crate::runtime::test::runtime_lock;
+
+        // Responses are written with the backend of the runtime
+        let _lock = runtime_lock();
+        let handler = test_json_handler();
+        Handler::handle(&handler, context_with(r#"{"name": "Alice"}"#));
+        GG_LAMBDA_HANDLER_WRITE_RESPONSE
+            .with(|rc| assert_eq!(*rc.borrow(), br#"{"greeting":"Hello Alice"}"#.to_vec()));
+        Handler::handle(&handler, context_with("{"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR
+            .with(|rc| assert!(rc.borrow().starts_with(r#"{"errorType":"DecodeError""#)));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_handler_input() {
+        use crate::backend::CBackend;
+        use crate::bindings::*;
+
+        GG_LAMBDA_HANDLER_READ_BUFFER.with(|rc| rc.replace(br#"{"name": "Alice"}"#.to_vec()));
+        let input = HandlerInput::new(Arc::new(CBackend)).with_chunk_size(4);
+        let value: Value = serde_json::from_reader(input).unwrap();
+        assert_eq!(value["name"], "Alice");
+
+        let message = vec![7u8; 1000];
+        GG_LAMBDA_HANDLER_READ_BUFFER.with(|rc| rc.replace(message.clone()));
+        let mut read = Vec::new();
+        HandlerInput::new(Arc::new(CBackend))
+            .with_chunk_size(64)
+            .with_size_hint(1000)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, message);
+    }
 }
@@ -7,21 +7,32 @@
  */
 
 //! Provides the ability to publish MQTT topics
+#[cfg(not(all(test, feature = "mock")))]
 use log::info;
 use serde::ser::Serialize;
-use std::convert::TryFrom;
 use std::default::Default;
-use std::ffi::CString;
-use std::os::raw::c_void;
-use std::ptr;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::time::Duration;
 
 #[cfg(all(test, feature = "mock"))]
 use self::mock::*;
 
+#[cfg(not(all(test, feature = "mock")))]
+use crate::backend::default_backend;
+use crate::backend::Backend;
 use crate::bindings::*;
+#[cfg(feature = "async")]
+use crate::blocking::BlockingPool;
+use crate::codec::Codec;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::compression::{compress_payload, Compression};
 use crate::error::GGError;
-use crate::request::GGRequestResponse;
-use crate::with_request;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::request::{GGRequestResponse, Request};
+#[cfg(not(all(test, feature = "mock")))]
+use crate::retry::with_retries;
+use crate::retry::RetryPolicy;
 use crate::GGResult;
 
 /// What actions should be taken if an MQTT queue is full
@@ -36,7 +47,7 @@ pub enum QueueFullPolicy {
 }
 
 impl QueueFullPolicy {
-    fn to_queue_full_c(&self) -> gg_queue_full_policy_options {
+    pub(crate) fn to_queue_full_c(&self) -> gg_queue_full_policy_options {
         match self {
             Self::BestEffort => gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT,
             Self::AllOrError => gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR,
@@ -92,15 +103,40 @@ pub struct IOTDataClient {
     /// The policy that this client will use when publishing
     /// if one has been defined
     pub publish_options: Option<PublishOptions>,
+    #[cfg(not(all(test, feature = "mock")))]
+    retry_policy: Option<RetryPolicy>,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    compression: Option<Compression>,
+    #[cfg(not(all(test, feature = "mock")))]
+    backend: Arc<dyn Backend>,
     /// When the mock feature is turned on this field will contain captured input
     /// and values to be returned
     #[cfg(all(test, feature = "mock"))]
     pub mocks: MockHolder,
 }
 
+#[allow(clippy::derivable_impls)]
+impl Default for IOTDataClient {
+    fn default() -> Self {
+        IOTDataClient {
+            publish_options: None,
+            #[cfg(not(all(test, feature = "mock")))]
+            retry_policy: None,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compression: None,
+            #[cfg(not(all(test, feature = "mock")))]
+            backend: default_backend(),
+            #[cfg(all(test, feature = "mock"))]
+            mocks: MockHolder::default(),
+        }
+    }
+}
+
 impl IOTDataClient {
     /// Allows publishing a message of anything that implements AsRef<[u8]> to be published
     pub fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let message = compress_payload(self.compression.as_ref(), message.as_ref())?;
         let as_bytes = message.as_ref();
         let size = as_bytes.len();
         self.publish_raw(topic, as_bytes, size)
@@ -112,89 +148,35 @@ impl IOTDataClient {
         self.publish(topic, &bytes)
     }
 
-    /// Raw publish method that wraps gg_request_init, gg_publish
-    #[cfg(not(all(test, feature = "mock")))]
-    pub fn publish_raw(&self, topic: &str, buffer: &[u8], read: usize) -> GGResult<()> {
-        self.publish_with_options(topic, buffer, read)
-    }
-
-    /// This wraps publish_internal and will set any publish options if publish options were specified
-    /// The primary reason this is a separate function from publish_internal is to ensure that if
-    /// options is specified we clean up the pointer we create on error
-    fn publish_with_options(&self, topic: &str, buffer: &[u8], read: usize) -> GGResult<()> {
-        unsafe {
-            // If options were defined, initialize the options pointer and
-            // set queue policy
-            let options_c: Option<gg_publish_options> = if let Some(po) = &self.publish_options {
-                let mut opts_c: gg_publish_options = ptr::null_mut();
-                let init_resp = gg_publish_options_init(&mut opts_c);
-                GGError::from_code(init_resp)?;
-
-                let queue_policy_c = po.queue_full_policy.to_queue_full_c();
-                let policy_resp = gg_publish_options_set_queue_full_policy(opts_c, queue_policy_c);
-                if let Err(e) = GGError::from_code(policy_resp) {
-                    // make sure that we free the options pointer
-                    let free_resp = gg_publish_options_free(opts_c);
-                    GGError::from_code(free_resp)?;
-                    return Err(e);
-                }
-
-                Some(opts_c)
-            } else {
-                None
-            };
-
-            let publish_result = self.publish_internal(topic, buffer, read, options_c);
-
-            // Clean up the options pointer if we created one
-            if let Some(opts) = options_c {
-                let free_resp = gg_publish_options_free(opts);
-                GGError::from_code(free_resp)?;
-            }
-            publish_result
-        }
-    }
-
-    /// Raw publish method that wraps gg_request_init, gg_publish
-    unsafe fn publish_internal(
+    /// Publish the message encoded by the codec
+    pub fn publish_with_codec<T, C: Codec<T>>(
         &self,
         topic: &str,
-        buffer: &[u8],
-        read: usize,
-        options_tuple: Option<gg_publish_options>,
+        message: &T,
+        codec: &C,
     ) -> GGResult<()> {
+        let bytes = codec.encode(message)?;
+        self.publish(topic, &bytes)
+    }
+
+    /// Raw publish method that publishes the first `read` bytes of the buffer
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn publish_raw(&self, topic: &str, buffer: &[u8], read: usize) -> GGResult<()> {
         info!("Publishing message of length {} to topic {}", read, topic);
-        let topic_c = CString::new(topic).map_err(GGError::from)?;
-        let mut req: gg_request = ptr::null_mut();
-        with_request!(req, {
-            let mut res = gg_request_result {
-                request_status: gg_request_status_GG_REQUEST_SUCCESS,
-            };
-            let pub_res = if let Some(options_c) = options_tuple {
-                gg_publish_with_options(
-                    req,
-                    topic_c.as_ptr(),
-                    buffer as *const _ as *const c_void,
-                    read,
-                    options_c,
-                    &mut res,
-                )
-            } else {
-                gg_publish(
-                    req,
-                    topic_c.as_ptr(),
-                    buffer as *const _ as *const c_void,
-                    read,
-                    &mut res,
-                )
-            };
-            GGError::from_code(pub_res)?;
-            GGRequestResponse::try_from(&res)?.to_error_result(req)
+        let payload = buffer.get(..read).ok_or(GGError::InvalidParameter)?;
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Publish", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe {
+                backend.publish(req.handle(), topic, payload, self.publish_options.as_ref())
+            }?;
+            GGRequestResponse::from(status).to_error_result(&req)?;
+            req.close()
         })
     }
 
     /// Optionally define a publishing options for this Client
-    #[allow(clippy::needless_update)]
     pub fn with_publish_options(self, publish_options: Option<PublishOptions>) -> Self {
         IOTDataClient {
             publish_options,
@@ -202,6 +184,31 @@ impl IOTDataClient {
         }
     }
 
+    /// Optionally define a policy to retry failed publishes with
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn with_retry_policy(self, retry_policy: Option<RetryPolicy>) -> Self {
+        IOTDataClient {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Optionally compress published payloads, when that makes them smaller.
+    /// See [`crate::compression`] on how receivers detect compressed payloads.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn with_compression(self, compression: Option<Compression>) -> Self {
+        IOTDataClient {
+            compression,
+            ..self
+        }
+    }
+
+    /// Use the specified backend instead of the C SDK
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        IOTDataClient { backend, ..self }
+    }
+
     // -----------------------------------
     // Mock methods
     // -----------------------------------
@@ -227,16 +234,144 @@ impl IOTDataClient {
     pub fn with_mocks(self, mocks: MockHolder) -> Self {
         IOTDataClient { mocks, ..self }
     }
+
+    /// Mocks don't use a backend, so it is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_backend(self, _backend: Arc<dyn Backend>) -> Self {
+        self
+    }
+
+    /// Mocks aren't retried, so the policy is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_retry_policy(self, _retry_policy: Option<RetryPolicy>) -> Self {
+        self
+    }
 }
 
-impl Default for IOTDataClient {
-    fn default() -> Self {
-        IOTDataClient {
-            publish_options: None,
-            #[cfg(all(test, feature = "mock"))]
-            mocks: MockHolder::default(),
+/// The publishing operations of [`IOTDataClient`], with the same signatures whether or not the mock feature is enabled.
+///
+/// Business logic can be generic over this trait so that it can be tested with a fake client.
+///
+/// ```rust
+/// use aws_greengrass_core_rust::iotdata::{IOTDataClient, IotData};
+/// use aws_greengrass_core_rust::GGResult;
+///
+/// fn report<C: IotData>(client: &C, temperature: f64) -> GGResult<()> {
+///     client.publish_json("sensors/temperature", temperature)
+/// }
+///
+/// if let Err(e) = report(&IOTDataClient::default(), 21.5) {
+///     eprintln!("An error occurred publishing: {}", e);
+/// }
+/// ```
+pub trait IotData {
+    /// Publishes anything that implements AsRef<[u8]>
+    fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()>;
+
+    /// Publishes anything that is a serializable serde object as JSON
+    fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let bytes = serde_json::to_vec(&message).map_err(GGError::from)?;
+        self.publish(topic, &bytes)
+    }
+
+    /// Publishes the message encoded by the codec
+    fn publish_with_codec<T, C: Codec<T>>(
+        &self,
+        topic: &str,
+        message: &T,
+        codec: &C,
+    ) -> GGResult<()> {
+        let bytes = codec.encode(message)?;
+        self.publish(topic, &bytes)
+    }
+}
+
+impl IotData for IOTDataClient {
+    fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        IOTDataClient::publish(self, topic, message)
+    }
+
+    fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        IOTDataClient::publish_json(self, topic, message)
+    }
+}
+
+/// Publishes without blocking the executor by running the calls of an [`IOTDataClient`] on a [`BlockingPool`].
+///
+/// ```rust,no_run
+/// use aws_greengrass_core_rust::iotdata::AsyncIOTDataClient;
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// let client = AsyncIOTDataClient::default().with_timeout(Some(Duration::from_secs(5)));
+/// if let Err(e) = client.publish("some_topic", r#"{"msg": "some payload"}"#).await {
+///     eprintln!("An error occurred publishing: {}", e);
+/// }
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[derive(Clone, Default)]
+pub struct AsyncIOTDataClient {
+    client: IOTDataClient,
+    pool: BlockingPool,
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncIOTDataClient {
+    /// Publishes anything that implements AsRef<[u8]>
+    pub async fn publish<T>(&self, topic: &str, message: T) -> GGResult<()>
+    where
+        T: AsRef<[u8]> + Send + 'static,
+    {
+        let client = self.client.clone();
+        let topic = topic.to_owned();
+        self.pool
+            .run_with_timeout(self.timeout, move || client.publish(&topic, message))
+            .await
+    }
+
+    /// Publishes anything that is a serializable serde object as JSON
+    pub async fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let bytes = serde_json::to_vec(&message).map_err(GGError::from)?;
+        self.publish(topic, bytes).await
+    }
+
+    /// Optionally define a publishing options for this Client
+    pub fn with_publish_options(self, publish_options: Option<PublishOptions>) -> Self {
+        AsyncIOTDataClient {
+            client: self.client.with_publish_options(publish_options),
+            ..self
+        }
+    }
+
+    /// Optionally compress published payloads, when that makes them smaller
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn with_compression(self, compression: Option<Compression>) -> Self {
+        AsyncIOTDataClient {
+            client: self.client.with_compression(compression),
+            ..self
         }
     }
+
+    /// Use the specified backend instead of the C SDK
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        AsyncIOTDataClient {
+            client: self.client.with_backend(backend),
+            ..self
+        }
+    }
+
+    /// Run the calls on the specified pool instead of the shared one
+    pub fn with_pool(self, pool: BlockingPool) -> Self {
+        AsyncIOTDataClient { pool, ..self }
+    }
+
+    /// Fail calls that have not completed within the timeout with an [`std::io::ErrorKind::TimedOut`] error.
+    /// No timeout is applied by default.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        AsyncIOTDataClient { timeout, ..self }
+    }
 }
 
 /// Provides mock testing utilities
@@ -303,12 +438,13 @@ pub mod mock {
 
             let mocks = MockHolder::default().with_publish_raw_outputs(vec![Ok(())]);
             let client = IOTDataClient::default().with_mocks(mocks);
-            let response = client.publish(topic, message).unwrap();
-            println!("response: {:?}", response);
+            client.publish(topic, message).unwrap();
 
             let PublishRawInput(raw_topic, raw_bytes, raw_read) =
                 &client.mocks.publish_raw_inputs.borrow()[0];
             assert_eq!(raw_topic, topic);
+            assert_eq!(raw_bytes, message.as_bytes());
+            assert_eq!(*raw_read, message.len());
         }
     }
 }
@@ -316,6 +452,7 @@ pub mod mock {
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(not(feature = "mock"))]
     use serde_json::Value;
 
     #[cfg(not(feature = "mock"))]
@@ -366,4 +503,64 @@ mod test {
             )
         });
     }
+    /// Records published messages
+    #[derive(Default)]
+    struct FakeIotData {
+        published: std::cell::RefCell<Vec<(String, Vec<u8>)>>,
+    }
+
+    impl IotData for FakeIotData {
+        fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+            self.published
+                .borrow_mut()
+                .push((topic.to_owned(), message.as_ref().to_vec()));
+            Ok(())
+        }
+    }
+
+    fn report<C: IotData>(client: &C, temperature: f64) -> GGResult<()> {
+        client.publish_json("sensors/temperature", temperature)
+    }
+
+    #[test]
+    fn test_generic_over_iot_data() {
+        let fake = FakeIotData::default();
+        report(&fake, 21.5).unwrap();
+        assert_eq!(
+            *fake.published.borrow(),
+            vec![("sensors/temperature".to_owned(), b"21.5".to_vec())]
+        );
+    }
+
+    #[cfg(all(not(feature = "mock"), feature = "gzip"))]
+    #[test]
+    fn test_publish_with_compression() {
+        use crate::compression::{decompress, is_compressed};
+
+        reset_test_state();
+        let payload = r#"{"value": 21.5}"#.repeat(20);
+        IOTDataClient::default()
+            .with_compression(Some(Compression::Gzip(6)))
+            .publish("sensors/readings", &payload)
+            .unwrap();
+        GG_PUBLISH_ARGS.with(|rc| {
+            let args = rc.borrow();
+            assert!(is_compressed(&args.payload));
+            assert!(args.payload.len() < payload.len());
+            assert_eq!(decompress(&args.payload, 1024).unwrap(), payload.as_bytes());
+        });
+    }
+
+    #[test]
+    fn test_publish_with_codec() {
+        use crate::codec::JsonCodec;
+
+        let fake = FakeIotData::default();
+        fake.publish_with_codec("sensors/readings", &vec![1, 2], &JsonCodec)
+            .unwrap();
+        assert_eq!(
+            *fake.published.borrow(),
+            vec![("sensors/readings".to_owned(), b"[1,2]".to_vec())]
+        );
+    }
 }
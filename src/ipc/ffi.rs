@@ -0,0 +1,1266 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Implements the subset of greengrasssdk.h used by this crate on top of [`IpcClient`].
+//! The names and signatures match the generated bindings so the rest of the crate is unaware of the backend.
+#![allow(non_upper_case_globals, non_camel_case_types)]
+
+use super::{InvocationType, IpcClient, IpcError, ShadowOperation, WorkItem, WorkResult};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long to wait before polling for work again after the endpoint could not be reached
+const POLL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub type gg_error = u32;
+pub const gg_error_GGE_SUCCESS: gg_error = 0;
+pub const gg_error_GGE_OUT_OF_MEMORY: gg_error = 1;
+pub const gg_error_GGE_INVALID_PARAMETER: gg_error = 2;
+pub const gg_error_GGE_INVALID_STATE: gg_error = 3;
+pub const gg_error_GGE_INTERNAL_FAILURE: gg_error = 4;
+pub const gg_error_GGE_TERMINATE: gg_error = 5;
+
+pub type gg_request_status = u32;
+pub const gg_request_status_GG_REQUEST_SUCCESS: gg_request_status = 0;
+pub const gg_request_status_GG_REQUEST_HANDLED: gg_request_status = 1;
+pub const gg_request_status_GG_REQUEST_UNHANDLED: gg_request_status = 2;
+pub const gg_request_status_GG_REQUEST_UNKNOWN: gg_request_status = 3;
+pub const gg_request_status_GG_REQUEST_AGAIN: gg_request_status = 4;
+
+pub type gg_queue_full_policy_options = u32;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT:
+    gg_queue_full_policy_options = 0;
+pub const gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR:
+    gg_queue_full_policy_options = 1;
+
+pub type gg_log_level = u32;
+pub const gg_log_level_GG_LOG_DEBUG: gg_log_level = 1;
+pub const gg_log_level_GG_LOG_INFO: gg_log_level = 2;
+pub const gg_log_level_GG_LOG_WARN: gg_log_level = 3;
+pub const gg_log_level_GG_LOG_ERROR: gg_log_level = 4;
+pub const gg_log_level_GG_LOG_FATAL: gg_log_level = 5;
+
+pub type gg_invoke_type = u32;
+pub const gg_invoke_type_GG_INVOKE_EVENT: gg_invoke_type = 0;
+pub const gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE: gg_invoke_type = 1;
+
+pub type gg_runtime_opt = u32;
+pub const gg_runtime_opt_GG_RT_OPT_ASYNC: gg_runtime_opt = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_request_result {
+    pub request_status: gg_request_status,
+}
+
+/// The state of a request, the response is read with gg_request_read
+#[derive(Debug, Default)]
+pub struct _gg_request {
+    response: Vec<u8>,
+    read_pos: usize,
+}
+
+pub type gg_request = *mut _gg_request;
+
+#[derive(Debug)]
+pub struct _gg_publish_options {
+    queue_full_policy: gg_queue_full_policy_options,
+}
+
+pub type gg_publish_options = *mut _gg_publish_options;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_lambda_context {
+    pub function_arn: *const c_char,
+    pub client_context: *const c_char,
+}
+
+pub type gg_lambda_handler = Option<unsafe extern "C" fn(cxt: *const gg_lambda_context)>;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct gg_invoke_options {
+    pub function_arn: *const c_char,
+    pub customer_context: *const c_char,
+    pub qualifier: *const c_char,
+    pub type_: gg_invoke_type,
+    pub payload: *const c_void,
+    pub payload_size: usize,
+}
+
+/// An invocation that has been received and not completed yet
+struct Invocation {
+    client: IpcClient,
+    function_arn: String,
+    work: WorkItem,
+    read_pos: usize,
+    responded: bool,
+    /// Whether the invocation is completed with complete_invocation instead of when the handler callback returns
+    detached: bool,
+}
+
+lazy_static! {
+    /// The invocations that have not been completed, by invocation id
+    static ref INVOCATIONS: Mutex<HashMap<String, Invocation>> = Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    // The id of the invocation being handled on this thread. As with the C SDK this is the thread the
+    // handler callback was called on, unless the invocation was detached and attached to another thread.
+    static CURRENT_INVOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub unsafe extern "C" fn gg_global_init(_opt: u32) -> gg_error {
+    gg_error_GGE_SUCCESS
+}
+
+/// Greengrass captures the output of lambdas in their log, so entries are written to stderr
+pub unsafe extern "C" fn gg_log(level: gg_log_level, format: *const c_char) -> gg_error {
+    match c_string(format) {
+        Some(msg) => {
+            write_log(level, &msg);
+            gg_error_GGE_SUCCESS
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+pub unsafe extern "C" fn gg_request_init(ggreq: *mut gg_request) -> gg_error {
+    if ggreq.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    *ggreq = Box::into_raw(Box::default());
+    gg_error_GGE_SUCCESS
+}
+
+pub unsafe extern "C" fn gg_request_close(ggreq: gg_request) -> gg_error {
+    if ggreq.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    drop(Box::from_raw(ggreq));
+    gg_error_GGE_SUCCESS
+}
+
+pub unsafe extern "C" fn gg_request_read(
+    ggreq: gg_request,
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    match ggreq.as_mut() {
+        Some(req) => copy_out(
+            &req.response,
+            &mut req.read_pos,
+            buffer,
+            buffer_size,
+            amount_read,
+        ),
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+/// Polls the IPC endpoint for work and calls the handler for each invocation.
+/// Blocks forever unless the async option is specified.
+pub unsafe extern "C" fn gg_runtime_start(
+    handler: gg_lambda_handler,
+    opt: gg_runtime_opt,
+) -> gg_error {
+    let handler = match handler {
+        Some(handler) => handler,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let function_arn = match env::var(super::FUNCTION_ARN_ENV) {
+        Ok(function_arn) => function_arn,
+        Err(_) => {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!(
+                    "{} is not set, cannot poll for work",
+                    super::FUNCTION_ARN_ENV
+                ),
+            );
+            return gg_error_GGE_INVALID_STATE;
+        }
+    };
+    let client = IpcClient::from_env();
+
+    if opt & gg_runtime_opt_GG_RT_OPT_ASYNC != 0 {
+        let spawn_result = thread::Builder::new()
+            .name("gg-ipc-runtime".to_owned())
+            .spawn(move || poll_work(client, function_arn, handler));
+        match spawn_result {
+            Ok(_) => gg_error_GGE_SUCCESS,
+            Err(_) => gg_error_GGE_INTERNAL_FAILURE,
+        }
+    } else {
+        poll_work(client, function_arn, handler)
+    }
+}
+
+fn poll_work(
+    client: IpcClient,
+    function_arn: String,
+    handler: unsafe extern "C" fn(*const gg_lambda_context),
+) -> ! {
+    let function_arn_c = CString::new(function_arn.as_str()).unwrap_or_default();
+    loop {
+        let work = match client.get_work(&function_arn) {
+            Ok(work) => work,
+            Err(e) => {
+                write_log(
+                    gg_log_level_GG_LOG_ERROR,
+                    &format!("Could not get work: {}", e),
+                );
+                thread::sleep(POLL_RETRY_DELAY);
+                continue;
+            }
+        };
+
+        let client_context_c = CString::new(work.client_context.as_str()).unwrap_or_default();
+        let context = gg_lambda_context {
+            function_arn: function_arn_c.as_ptr(),
+            client_context: client_context_c.as_ptr(),
+        };
+        let invocation_id = work.invocation_id.clone();
+        invocations().insert(
+            invocation_id.clone(),
+            Invocation {
+                client: client.clone(),
+                function_arn: function_arn.clone(),
+                work,
+                read_pos: 0,
+                responded: false,
+                detached: false,
+            },
+        );
+        attach_invocation(Some(&invocation_id));
+
+        unsafe { handler(&context) };
+
+        attach_invocation(None);
+        let detached = invocations()
+            .get(&invocation_id)
+            .is_none_or(|invocation| invocation.detached);
+        if !detached {
+            complete_invocation(&invocation_id);
+        }
+    }
+}
+
+fn invocations() -> MutexGuard<'static, HashMap<String, Invocation>> {
+    INVOCATIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Detaches the invocation being handled on this thread, so that it is not completed when the handler callback
+/// returns. Returns the id of the invocation, which can be attached to another thread with attach_invocation.
+#[cfg(not(all(test, feature = "mock")))]
+pub(crate) fn detach_invocation() -> Option<String> {
+    let invocation_id = CURRENT_INVOCATION.with(|rc| rc.borrow_mut().take())?;
+    let mut invocations = invocations();
+    let invocation = invocations.get_mut(&invocation_id)?;
+    invocation.detached = true;
+    Some(invocation_id)
+}
+
+/// Sets the invocation being handled on this thread, or clears it
+pub(crate) fn attach_invocation(invocation_id: Option<&str>) {
+    CURRENT_INVOCATION.with(|rc| *rc.borrow_mut() = invocation_id.map(str::to_owned));
+}
+
+/// Greengrass waits for a result for every invocation, so an empty one is sent if the handler didn't send one
+pub(crate) fn complete_invocation(invocation_id: &str) {
+    let invocation = match invocations().remove(invocation_id) {
+        Some(invocation) => invocation,
+        None => return,
+    };
+    if !invocation.responded {
+        let result =
+            invocation
+                .client
+                .post_work_result(&invocation.function_arn, invocation_id, &[]);
+        if let Err(e) = result {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!("Could not post work result: {}", e),
+            );
+        }
+    }
+}
+
+pub unsafe extern "C" fn gg_lambda_handler_read(
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    let invocation_id = match CURRENT_INVOCATION.with(|rc| rc.borrow().clone()) {
+        Some(invocation_id) => invocation_id,
+        None => return gg_error_GGE_INVALID_STATE,
+    };
+    match invocations().get_mut(&invocation_id) {
+        Some(invocation) => copy_out(
+            &invocation.work.payload,
+            &mut invocation.read_pos,
+            buffer,
+            buffer_size,
+            amount_read,
+        ),
+        None => gg_error_GGE_INVALID_STATE,
+    }
+}
+
+pub unsafe extern "C" fn gg_lambda_handler_write_response(
+    response: *const c_void,
+    response_size: usize,
+) -> gg_error {
+    let response = bytes(response, response_size);
+    respond(|client, function_arn, invocation_id| {
+        client.post_work_result(function_arn, invocation_id, response)
+    })
+}
+
+pub unsafe extern "C" fn gg_lambda_handler_write_error(error_message: *const c_char) -> gg_error {
+    let error_message = match c_string(error_message) {
+        Some(error_message) => error_message,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    respond(|client, function_arn, invocation_id| {
+        client.post_handler_err(function_arn, invocation_id, &error_message)
+    })
+}
+
+/// Sends the response for the current invocation, only one response can be sent.
+/// The invocation is only known on the thread the handler callback was called on,
+/// or the thread a detached invocation was attached to.
+fn respond<F: FnOnce(&IpcClient, &str, &str) -> Result<(), IpcError>>(f: F) -> gg_error {
+    let invocation_id = match CURRENT_INVOCATION.with(|rc| rc.borrow().clone()) {
+        Some(invocation_id) => invocation_id,
+        None => {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                "No invocation is being handled on this thread. Responses must be written from the \
+                 thread the handler was called on",
+            );
+            return gg_error_GGE_INVALID_STATE;
+        }
+    };
+    // The response is posted without holding the lock, so other invocations can respond meanwhile
+    let (client, function_arn) = match invocations().get_mut(&invocation_id) {
+        Some(invocation) if !invocation.responded => {
+            invocation.responded = true;
+            (invocation.client.clone(), invocation.function_arn.clone())
+        }
+        _ => return gg_error_GGE_INVALID_STATE,
+    };
+    match f(&client, &function_arn, &invocation_id) {
+        Ok(_) => gg_error_GGE_SUCCESS,
+        Err(e) => {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!("Could not post response: {}", e),
+            );
+            gg_error_GGE_INTERNAL_FAILURE
+        }
+    }
+}
+
+pub unsafe extern "C" fn gg_get_secret_value(
+    ggreq: gg_request,
+    secret_id: *const c_char,
+    version_id: *const c_char,
+    version_stage: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let secret_id = match c_string(secret_id) {
+        Some(secret_id) => secret_id,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let version_id = c_string(version_id);
+    let version_stage = c_string(version_stage);
+    let outcome = IpcClient::from_env().get_secret_value(
+        &secret_id,
+        version_id.as_deref(),
+        version_stage.as_deref(),
+    );
+    complete(ggreq, result, outcome)
+}
+
+pub unsafe extern "C" fn gg_invoke(
+    ggreq: gg_request,
+    opts: *const gg_invoke_options,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let opts = match opts.as_ref() {
+        Some(opts) => opts,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let mut function_arn = match c_string(opts.function_arn) {
+        Some(function_arn) => function_arn,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    match c_string(opts.qualifier) {
+        Some(qualifier) if !qualifier.is_empty() => {
+            function_arn = format!("{}:{}", function_arn, qualifier)
+        }
+        _ => (),
+    }
+    let customer_context = c_string(opts.customer_context).unwrap_or_default();
+    let invocation_type = match opts.type_ {
+        gg_invoke_type_GG_INVOKE_EVENT => InvocationType::Event,
+        gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE => InvocationType::RequestResponse,
+        _ => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let outcome = IpcClient::from_env()
+        .invoke(
+            &function_arn,
+            bytes(opts.payload, opts.payload_size),
+            &customer_context,
+            invocation_type,
+        )
+        .map(Option::unwrap_or_default);
+    complete(ggreq, result, outcome)
+}
+
+pub unsafe extern "C" fn gg_publish_options_init(opts: *mut gg_publish_options) -> gg_error {
+    if opts.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    *opts = Box::into_raw(Box::new(_gg_publish_options {
+        queue_full_policy: gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT,
+    }));
+    gg_error_GGE_SUCCESS
+}
+
+pub unsafe extern "C" fn gg_publish_options_free(opts: gg_publish_options) -> gg_error {
+    if opts.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    drop(Box::from_raw(opts));
+    gg_error_GGE_SUCCESS
+}
+
+pub unsafe extern "C" fn gg_publish_options_set_queue_full_policy(
+    opts: gg_publish_options,
+    policy: gg_queue_full_policy_options,
+) -> gg_error {
+    match opts.as_mut() {
+        Some(opts) => {
+            opts.queue_full_policy = policy;
+            gg_error_GGE_SUCCESS
+        }
+        None => gg_error_GGE_INVALID_PARAMETER,
+    }
+}
+
+pub unsafe extern "C" fn gg_publish_with_options(
+    ggreq: gg_request,
+    topic: *const c_char,
+    payload: *const c_void,
+    payload_size: usize,
+    opts: gg_publish_options,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let policy = match opts.as_ref() {
+        Some(opts) => opts.queue_full_policy,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    publish(ggreq, topic, payload, payload_size, policy, result)
+}
+
+pub unsafe extern "C" fn gg_publish(
+    ggreq: gg_request,
+    topic: *const c_char,
+    payload: *const c_void,
+    payload_size: usize,
+    result: *mut gg_request_result,
+) -> gg_error {
+    publish(
+        ggreq,
+        topic,
+        payload,
+        payload_size,
+        gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_BEST_EFFORT,
+        result,
+    )
+}
+
+unsafe fn publish(
+    ggreq: gg_request,
+    topic: *const c_char,
+    payload: *const c_void,
+    payload_size: usize,
+    policy: gg_queue_full_policy_options,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let topic = match c_string(topic) {
+        Some(topic) => topic,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let policy = match policy {
+        gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR => "AllOrException",
+        _ => "BestEffort",
+    };
+    let source_arn = env::var(super::FUNCTION_ARN_ENV).unwrap_or_default();
+    let outcome = IpcClient::from_env()
+        .publish(&source_arn, &topic, bytes(payload, payload_size), policy)
+        .map(|_| WorkResult::default());
+    complete(ggreq, result, outcome)
+}
+
+pub unsafe extern "C" fn gg_get_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    shadow(ggreq, ShadowOperation::Get, thing_name, &[], result)
+}
+
+pub unsafe extern "C" fn gg_update_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    update_payload: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    let update_payload = match c_string(update_payload) {
+        Some(update_payload) => update_payload,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    shadow(
+        ggreq,
+        ShadowOperation::Update,
+        thing_name,
+        update_payload.as_bytes(),
+        result,
+    )
+}
+
+pub unsafe extern "C" fn gg_delete_thing_shadow(
+    ggreq: gg_request,
+    thing_name: *const c_char,
+    result: *mut gg_request_result,
+) -> gg_error {
+    shadow(ggreq, ShadowOperation::Delete, thing_name, &[], result)
+}
+
+unsafe fn shadow(
+    ggreq: gg_request,
+    operation: ShadowOperation,
+    thing_name: *const c_char,
+    payload: &[u8],
+    result: *mut gg_request_result,
+) -> gg_error {
+    let thing_name = match c_string(thing_name) {
+        Some(thing_name) => thing_name,
+        None => return gg_error_GGE_INVALID_PARAMETER,
+    };
+    let outcome = IpcClient::from_env().shadow(operation, &thing_name, payload);
+    complete(ggreq, result, outcome)
+}
+
+/// Stores the outcome of a request so it can be read with gg_request_read and sets the request status
+unsafe fn complete(
+    ggreq: gg_request,
+    result: *mut gg_request_result,
+    outcome: Result<WorkResult, IpcError>,
+) -> gg_error {
+    let (status, body) = match outcome {
+        Ok(work) => (work_status(&work), work.payload),
+        Err(IpcError::Io(e)) => {
+            write_log(
+                gg_log_level_GG_LOG_ERROR,
+                &format!("IPC request failed: {}", e),
+            );
+            return gg_error_GGE_INTERNAL_FAILURE;
+        }
+        Err(IpcError::Status(response)) => {
+            let status = match response.status {
+                429 | 503 => gg_request_status_GG_REQUEST_AGAIN,
+                _ => gg_request_status_GG_REQUEST_UNHANDLED,
+            };
+            let body = if is_error_response(&response.body) {
+                response.body
+            } else {
+                error_response(response.status, &String::from_utf8_lossy(&response.body))
+            };
+            (status, body)
+        }
+    };
+
+    if let Some(req) = ggreq.as_mut() {
+        req.response = body;
+        req.read_pos = 0;
+    }
+    if let Some(result) = result.as_mut() {
+        result.request_status = status;
+    }
+    gg_error_GGE_SUCCESS
+}
+
+/// Determines the request status from the result of invoking a system or user lambda
+fn work_status(work: &WorkResult) -> gg_request_status {
+    match work.function_error.as_deref() {
+        Some("Unhandled") => gg_request_status_GG_REQUEST_UNHANDLED,
+        Some(_) => gg_request_status_GG_REQUEST_HANDLED,
+        None if is_error_response(&work.payload) => gg_request_status_GG_REQUEST_HANDLED,
+        None => gg_request_status_GG_REQUEST_SUCCESS,
+    }
+}
+
+/// The Greengrass services respond with a json object containing an http status code on failure
+fn is_error_response(payload: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(payload)
+        .ok()
+        .and_then(|v| v.get("code").and_then(Value::as_u64))
+        .is_some_and(|code| code >= 400)
+}
+
+/// Creates an error response in the same format the Greengrass services use
+fn error_response(code: u16, message: &str) -> Vec<u8> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    json!({ "code": code, "message": message, "timestamp": timestamp })
+        .to_string()
+        .into_bytes()
+}
+
+/// Copies the unread part of the source into the buffer
+unsafe fn copy_out(
+    source: &[u8],
+    read_pos: &mut usize,
+    buffer: *mut c_void,
+    buffer_size: usize,
+    amount_read: *mut usize,
+) -> gg_error {
+    if buffer.is_null() || amount_read.is_null() {
+        return gg_error_GGE_INVALID_PARAMETER;
+    }
+    let remaining = &source[(*read_pos).min(source.len())..];
+    let amount = remaining.len().min(buffer_size);
+    ptr::copy_nonoverlapping(remaining.as_ptr(), buffer as *mut u8, amount);
+    *read_pos += amount;
+    *amount_read = amount;
+    gg_error_GGE_SUCCESS
+}
+
+unsafe fn c_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+unsafe fn bytes<'a>(ptr: *const c_void, len: usize) -> &'a [u8] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr as *const u8, len)
+    }
+}
+
+fn write_log(level: gg_log_level, msg: &str) {
+    let level = match level {
+        gg_log_level_GG_LOG_DEBUG => "DEBUG",
+        gg_log_level_GG_LOG_INFO => "INFO",
+        gg_log_level_GG_LOG_WARN => "WARN",
+        gg_log_level_GG_LOG_ERROR => "ERROR",
+        gg_log_level_GG_LOG_FATAL => "FATAL",
+        _ => "NOTSET",
+    };
+    eprintln!("[{}] {}", level, msg);
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{stand_in, CannedResponse, RecordedRequest};
+    use super::super::{
+        HEADER_CLIENT_CONTEXT, HEADER_FUNCTION_ERROR, HEADER_INVOCATION_ID, HEADER_INVOCATION_TYPE,
+    };
+    use super::*;
+    use crate::bindings;
+    use std::time::Duration;
+
+    unsafe extern "C" fn echo_handler(ctx: *const gg_lambda_context) {
+        let mut buffer = [0u8; 4];
+        let mut message = vec![];
+        loop {
+            let mut read = 0;
+            let res = gg_lambda_handler_read(buffer.as_mut_ptr() as *mut c_void, 4, &mut read);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            if read == 0 {
+                break;
+            }
+            message.extend_from_slice(&buffer[..read]);
+        }
+        assert_eq!(
+            CStr::from_ptr((*ctx).function_arn).to_str().unwrap(),
+            "my_func_arn"
+        );
+        if message == b"fail" {
+            let error = CString::new("I was asked to fail").unwrap();
+            let res = gg_lambda_handler_write_error(error.as_ptr());
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+        } else if message != b"silent" {
+            let response = [b"echo: ", message.as_slice()].concat();
+            let res = gg_lambda_handler_write_response(
+                response.as_ptr() as *const c_void,
+                response.len(),
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            // only one response can be written
+            let res = gg_lambda_handler_write_response(response.as_ptr() as *const c_void, 0);
+            assert_eq!(res, gg_error_GGE_INVALID_STATE);
+        } else {
+            // responses can't be written from another thread
+            let res = thread::spawn(|| unsafe { gg_lambda_handler_write_response(ptr::null(), 0) })
+                .join()
+                .unwrap();
+            assert_eq!(res, gg_error_GGE_INVALID_STATE);
+        }
+    }
+
+    unsafe fn read_all(req: gg_request) -> Vec<u8> {
+        let mut buffer = [0u8; 8];
+        let mut collected = vec![];
+        loop {
+            let mut read = 0;
+            gg_request_read(req, buffer.as_mut_ptr() as *mut c_void, 8, &mut read);
+            if read == 0 {
+                return collected;
+            }
+            collected.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    /// The subject in the client context of a request to a system lambda
+    fn subject(request: &RecordedRequest) -> Value {
+        let context = base64::decode(request.header(HEADER_CLIENT_CONTEXT).unwrap()).unwrap();
+        serde_json::from_slice::<Value>(&context).unwrap()["custom"]["subject"].clone()
+    }
+
+    /// The rest of the crate uses the values of the C SDK, which the test bindings define
+    #[test]
+    fn test_constants_match_bindings() {
+        let errors: [gg_error; 6] = [
+            gg_error_GGE_SUCCESS,
+            gg_error_GGE_OUT_OF_MEMORY,
+            gg_error_GGE_INVALID_PARAMETER,
+            gg_error_GGE_INVALID_STATE,
+            gg_error_GGE_INTERNAL_FAILURE,
+            gg_error_GGE_TERMINATE,
+        ];
+        let bindings_errors = [
+            bindings::gg_error_GGE_SUCCESS,
+            bindings::gg_error_GGE_OUT_OF_MEMORY,
+            bindings::gg_error_GGE_INVALID_PARAMETER,
+            bindings::gg_error_GGE_INVALID_STATE,
+            bindings::gg_error_GGE_INTERNAL_FAILURE,
+            bindings::gg_error_GGE_TERMINATE,
+        ];
+        assert_eq!(errors, bindings_errors);
+
+        let statuses: [gg_request_status; 5] = [
+            gg_request_status_GG_REQUEST_SUCCESS,
+            gg_request_status_GG_REQUEST_HANDLED,
+            gg_request_status_GG_REQUEST_UNHANDLED,
+            gg_request_status_GG_REQUEST_UNKNOWN,
+            gg_request_status_GG_REQUEST_AGAIN,
+        ];
+        let bindings_statuses = [
+            bindings::gg_request_status_GG_REQUEST_SUCCESS,
+            bindings::gg_request_status_GG_REQUEST_HANDLED,
+            bindings::gg_request_status_GG_REQUEST_UNHANDLED,
+            bindings::gg_request_status_GG_REQUEST_UNKNOWN,
+            bindings::gg_request_status_GG_REQUEST_AGAIN,
+        ];
+        assert_eq!(statuses, bindings_statuses);
+
+        let invoke_types: [gg_invoke_type; 2] = [
+            gg_invoke_type_GG_INVOKE_EVENT,
+            gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE,
+        ];
+        let bindings_invoke_types = [
+            bindings::gg_invoke_type_GG_INVOKE_EVENT,
+            bindings::gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE,
+        ];
+        assert_eq!(invoke_types, bindings_invoke_types);
+    }
+
+    #[test]
+    fn test_log() {
+        let message = CString::new("hello").unwrap();
+        unsafe {
+            assert_eq!(gg_global_init(0), gg_error_GGE_SUCCESS);
+            assert_eq!(
+                gg_log(gg_log_level_GG_LOG_INFO, message.as_ptr()),
+                gg_error_GGE_SUCCESS
+            );
+            assert_eq!(
+                gg_log(gg_log_level_GG_LOG_INFO, ptr::null()),
+                gg_error_GGE_INVALID_PARAMETER
+            );
+        }
+    }
+
+    /// Environment variables are global to the process, so everything that reads them is tested here
+    #[test]
+    fn test_ffi() {
+        env::set_var(super::super::FUNCTION_ARN_ENV, "my_func_arn");
+        env::set_var(super::super::AUTH_TOKEN_ENV, "my token");
+
+        let requests = unsafe {
+            // a shadow that doesn't exist
+            let (endpoint, _) = stand_in(vec![
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1"),
+                CannedResponse::ok(br#"{"code":404,"message":"Not found","timestamp":1}"#),
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let thing = CString::new("my_thing").unwrap();
+            let mut req: gg_request = ptr::null_mut();
+            assert_eq!(gg_request_init(&mut req), gg_error_GGE_SUCCESS);
+            let mut result = gg_request_result {
+                request_status: gg_request_status_GG_REQUEST_SUCCESS,
+            };
+            let res = gg_get_thing_shadow(req, thing.as_ptr(), &mut result);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_HANDLED);
+            assert_eq!(
+                read_all(req),
+                br#"{"code":404,"message":"Not found","timestamp":1}"#
+            );
+            assert_eq!(gg_request_close(req), gg_error_GGE_SUCCESS);
+
+            // a throttled publish
+            let (endpoint, _) = stand_in(vec![CannedResponse {
+                status: 429,
+                headers: vec![],
+                body: b"slow down".to_vec(),
+                method: None,
+            }]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let topic = CString::new("my/topic").unwrap();
+            let mut req: gg_request = ptr::null_mut();
+            gg_request_init(&mut req);
+            let res = gg_publish(
+                req,
+                topic.as_ptr(),
+                b"hello".as_ptr() as *const c_void,
+                5,
+                &mut result,
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_AGAIN);
+            let body: Value = serde_json::from_slice(&read_all(req)).unwrap();
+            assert_eq!(body["code"], 429);
+            assert_eq!(body["message"], "slow down");
+            gg_request_close(req);
+
+            // a publish that must not be dropped when the core's queue is full
+            let (endpoint, publishes) = stand_in(vec![
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1")
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let mut opts: gg_publish_options = ptr::null_mut();
+            assert_eq!(gg_publish_options_init(&mut opts), gg_error_GGE_SUCCESS);
+            let res = gg_publish_options_set_queue_full_policy(
+                opts,
+                gg_queue_full_policy_options_GG_QUEUE_FULL_POLICY_ALL_OR_ERROR,
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            gg_request_init(&mut req);
+            let res = gg_publish_with_options(
+                req,
+                topic.as_ptr(),
+                b"hello".as_ptr() as *const c_void,
+                5,
+                opts,
+                &mut result,
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_SUCCESS);
+            assert_eq!(gg_publish_options_free(opts), gg_error_GGE_SUCCESS);
+            gg_request_close(req);
+            let publish = publishes.try_recv().unwrap();
+            assert_eq!(
+                publish.path,
+                format!(
+                    "/2016-11-01/functions/{}",
+                    super::super::ROUTER_FUNCTION_ARN
+                )
+            );
+            assert_eq!(publish.header(HEADER_INVOCATION_TYPE), Some("Event"));
+            let context = base64::decode(publish.header(HEADER_CLIENT_CONTEXT).unwrap()).unwrap();
+            let context: Value = serde_json::from_slice(&context).unwrap();
+            assert_eq!(context["custom"]["subject"], "my/topic");
+            assert_eq!(context["custom"]["queueFullPolicy"], "AllOrException");
+            assert_eq!(publish.body, b"hello");
+
+            // a shadow update and delete
+            let (endpoint, shadow_requests) = stand_in(vec![
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1"),
+                CannedResponse::ok(br#"{"state":{}}"#),
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "2"),
+                CannedResponse::ok(b""),
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let document = CString::new(r#"{"state":{"desired":{"on":true}}}"#).unwrap();
+            gg_request_init(&mut req);
+            let res = gg_update_thing_shadow(req, thing.as_ptr(), document.as_ptr(), &mut result);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_SUCCESS);
+            assert_eq!(read_all(req), br#"{"state":{}}"#);
+            gg_request_close(req);
+            gg_request_init(&mut req);
+            let res = gg_delete_thing_shadow(req, thing.as_ptr(), &mut result);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            gg_request_close(req);
+            let shadow_posts: Vec<RecordedRequest> = shadow_requests
+                .try_iter()
+                .filter(|r| r.method == "POST")
+                .collect();
+            assert_eq!(shadow_posts.len(), 2);
+            assert_eq!(
+                subject(&shadow_posts[0]),
+                "$aws/things/my_thing/shadow/update"
+            );
+            assert_eq!(shadow_posts[0].body, document.as_bytes());
+            assert_eq!(
+                subject(&shadow_posts[1]),
+                "$aws/things/my_thing/shadow/delete"
+            );
+
+            // a secret at a stage
+            let (endpoint, secret_requests) = stand_in(vec![
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1"),
+                CannedResponse::ok(br#"{"SecretString":"shh"}"#),
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let secret_id = CString::new("my_secret").unwrap();
+            let version_stage = CString::new("AWSCURRENT").unwrap();
+            gg_request_init(&mut req);
+            let res = gg_get_secret_value(
+                req,
+                secret_id.as_ptr(),
+                ptr::null(),
+                version_stage.as_ptr(),
+                &mut result,
+            );
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(read_all(req), br#"{"SecretString":"shh"}"#);
+            gg_request_close(req);
+            let secret_request = secret_requests.try_recv().unwrap();
+            assert_eq!(
+                secret_request.path,
+                format!(
+                    "/2016-11-01/functions/{}",
+                    super::super::SECRETS_MANAGER_FUNCTION_ARN
+                )
+            );
+            let payload: Value = serde_json::from_slice(&secret_request.body).unwrap();
+            assert_eq!(
+                payload,
+                json!({"SecretId": "my_secret", "VersionStage": "AWSCURRENT"})
+            );
+
+            // a request-response invocation of a qualified lambda that handled an error
+            let (endpoint, invoke_requests) = stand_in(vec![
+                CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1"),
+                CannedResponse::ok(b"pong").with_header(HEADER_FUNCTION_ERROR, "Handled"),
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let function_arn = CString::new("other_arn").unwrap();
+            let customer_context = CString::new("e30=").unwrap();
+            let qualifier = CString::new("1").unwrap();
+            let opts = gg_invoke_options {
+                function_arn: function_arn.as_ptr(),
+                customer_context: customer_context.as_ptr(),
+                qualifier: qualifier.as_ptr(),
+                type_: gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE,
+                payload: b"ping".as_ptr() as *const c_void,
+                payload_size: 4,
+            };
+            gg_request_init(&mut req);
+            let res = gg_invoke(req, &opts, &mut result);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            assert_eq!(result.request_status, gg_request_status_GG_REQUEST_HANDLED);
+            assert_eq!(read_all(req), b"pong");
+            gg_request_close(req);
+            let invoke_request = invoke_requests.try_recv().unwrap();
+            assert_eq!(invoke_request.path, "/2016-11-01/functions/other_arn:1");
+            assert_eq!(
+                invoke_request.header(HEADER_INVOCATION_TYPE),
+                Some("RequestResponse")
+            );
+            assert_eq!(invoke_request.header(HEADER_CLIENT_CONTEXT), Some("e30="));
+            assert_eq!(invoke_request.body, b"ping");
+
+            // the handler loop
+            let (endpoint, requests) = stand_in(vec![
+                CannedResponse::ok(b"hello")
+                    .with_header(HEADER_INVOCATION_ID, "1")
+                    .with_header(HEADER_CLIENT_CONTEXT, "e30="),
+                CannedResponse::ok(b""),
+                CannedResponse::ok(b"silent").with_header(HEADER_INVOCATION_ID, "2"),
+                CannedResponse::ok(b""),
+                CannedResponse::ok(b"fail").with_header(HEADER_INVOCATION_ID, "3"),
+                CannedResponse::ok(b""),
+            ]);
+            env::set_var(super::super::ENDPOINT_ENV, &endpoint);
+            let res = gg_runtime_start(Some(echo_handler), gg_runtime_opt_GG_RT_OPT_ASYNC);
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+            requests
+        };
+
+        let timeout = Duration::from_secs(5);
+        let next = || requests.recv_timeout(timeout).expect("Expected request");
+        let get_work = next();
+        assert_eq!(get_work.path, "/2016-11-01/functions/my_func_arn/work");
+        assert_eq!(get_work.header("Authorization"), Some("my token"));
+        let response = next();
+        assert_eq!(response.method, "POST");
+        assert_eq!(response.header(HEADER_INVOCATION_ID), Some("1"));
+        assert_eq!(response.body, b"echo: hello");
+        assert_eq!(response.header(HEADER_FUNCTION_ERROR), None);
+        next();
+        let empty_response = next();
+        assert_eq!(empty_response.header(HEADER_INVOCATION_ID), Some("2"));
+        assert!(empty_response.body.is_empty());
+        next();
+        let error_response = next();
+        assert_eq!(error_response.header(HEADER_INVOCATION_ID), Some("3"));
+        assert_eq!(
+            error_response.header(HEADER_FUNCTION_ERROR),
+            Some("Handled")
+        );
+        let body: Value = serde_json::from_slice(&error_response.body).unwrap();
+        assert_eq!(body["errorMessage"], "I was asked to fail");
+
+        // responses can't be written outside of the handler
+        let res = unsafe { gg_lambda_handler_write_response(ptr::null(), 0) };
+        assert_eq!(res, gg_error_GGE_INVALID_STATE);
+
+        #[cfg(not(feature = "mock"))]
+        runtime::test_handler_responds_from_worker(timeout);
+    }
+
+    /// Runs a [`Runtime`] against the functions of this module
+    #[cfg(not(feature = "mock"))]
+    mod runtime {
+        use super::*;
+        use crate::backend::{Backend, CBackend, InvocationHandle, InvokeArgs, RequestHandle};
+        use crate::error::GGError;
+        use crate::handler::{Handler, LambdaContext};
+        use crate::iotdata::PublishOptions;
+        use crate::lambda::LambdaClient;
+        use crate::request::GGRequestStatus;
+        use crate::runtime::test::runtime_lock;
+        use crate::runtime::Runtime;
+        use crate::{GGResult, Initializer};
+        use log::Level;
+        use std::sync::Arc;
+
+        /// Handles events with the functions of this module, requests go to the test bindings
+        struct IpcBackend;
+
+        impl Backend for IpcBackend {
+            fn request_init(&self) -> GGResult<RequestHandle> {
+                CBackend.request_init()
+            }
+
+            unsafe fn request_read(
+                &self,
+                request: RequestHandle,
+                buffer: &mut [u8],
+            ) -> GGResult<usize> {
+                unsafe { CBackend.request_read(request, buffer) }
+            }
+
+            unsafe fn request_close(&self, request: RequestHandle) -> GGResult<()> {
+                unsafe { CBackend.request_close(request) }
+            }
+
+            unsafe fn publish(
+                &self,
+                request: RequestHandle,
+                topic: &str,
+                payload: &[u8],
+                options: Option<&PublishOptions>,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.publish(request, topic, payload, options) }
+            }
+
+            unsafe fn invoke(
+                &self,
+                request: RequestHandle,
+                args: &InvokeArgs,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.invoke(request, args) }
+            }
+
+            unsafe fn get_thing_shadow(
+                &self,
+                request: RequestHandle,
+                thing_name: &str,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.get_thing_shadow(request, thing_name) }
+            }
+
+            unsafe fn update_thing_shadow(
+                &self,
+                request: RequestHandle,
+                thing_name: &str,
+                document: &str,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.update_thing_shadow(request, thing_name, document) }
+            }
+
+            unsafe fn delete_thing_shadow(
+                &self,
+                request: RequestHandle,
+                thing_name: &str,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.delete_thing_shadow(request, thing_name) }
+            }
+
+            unsafe fn get_secret_value(
+                &self,
+                request: RequestHandle,
+                secret_id: &str,
+                version_id: Option<&str>,
+                version_stage: Option<&str>,
+            ) -> GGResult<GGRequestStatus> {
+                unsafe { CBackend.get_secret_value(request, secret_id, version_id, version_stage) }
+            }
+
+            fn log(&self, level: Level, message: &str) -> GGResult<()> {
+                CBackend.log(level, message)
+            }
+
+            fn handler_read(&self, buffer: &mut [u8]) -> GGResult<usize> {
+                let mut read = 0;
+                let res = unsafe {
+                    gg_lambda_handler_read(
+                        buffer.as_mut_ptr() as *mut c_void,
+                        buffer.len(),
+                        &mut read,
+                    )
+                };
+                GGError::from_code(res).map(|_| read)
+            }
+
+            fn handler_write_response(&self, response: &[u8]) -> GGResult<()> {
+                GGError::from_code(unsafe {
+                    gg_lambda_handler_write_response(
+                        response.as_ptr() as *const c_void,
+                        response.len(),
+                    )
+                })
+            }
+
+            fn handler_write_error(&self, message: &str) -> GGResult<()> {
+                let message = CString::new(message).map_err(GGError::from)?;
+                GGError::from_code(unsafe { gg_lambda_handler_write_error(message.as_ptr()) })
+            }
+
+            fn handler_detach(&self) -> Option<InvocationHandle> {
+                detach_invocation().map(InvocationHandle)
+            }
+
+            fn handler_attach(&self, invocation: Option<&InvocationHandle>) {
+                attach_invocation(invocation.map(|i| i.0.as_str()))
+            }
+
+            fn handler_complete(&self, invocation: &InvocationHandle) {
+                complete_invocation(&invocation.0)
+            }
+        }
+
+        struct EchoHandler;
+
+        impl Handler for EchoHandler {
+            fn handle(&self, ctx: LambdaContext) {
+                assert_eq!(thread::current().name(), Some("gg-handler-0"));
+                if ctx.message != b"silent" {
+                    let response = [b"echo: ", ctx.message.as_slice()].concat();
+                    LambdaClient::default()
+                        .with_backend(Arc::new(IpcBackend))
+                        .send_response(Ok(&response))
+                        .expect("Could not send response from the handler thread");
+                }
+            }
+        }
+
+        /// The runtime was started with the test bindings, whose context has the same layout
+        unsafe extern "C" fn forward_to_runtime(ctx: *const gg_lambda_context) {
+            let handler = *bindings::GG_HANDLER.lock().unwrap();
+            let handler = handler.expect("The runtime was not started");
+            handler(ctx as *const bindings::gg_lambda_context);
+        }
+
+        pub(super) fn test_handler_responds_from_worker(timeout: Duration) {
+            let _lock = runtime_lock();
+            let (endpoint, requests) = stand_in(vec![
+                CannedResponse::ok(b"hello")
+                    .with_header(HEADER_INVOCATION_ID, "11")
+                    .for_method("GET"),
+                CannedResponse::ok(b"silent")
+                    .with_header(HEADER_INVOCATION_ID, "12")
+                    .for_method("GET"),
+                CannedResponse::ok(b"").for_method("POST"),
+                CannedResponse::ok(b"").for_method("POST"),
+            ]);
+            env::set_var(super::super::super::ENDPOINT_ENV, &endpoint);
+            let runtime = Runtime::default()
+                .with_backend(Arc::new(IpcBackend))
+                .with_handler(Some(Box::new(EchoHandler)));
+            Initializer::default()
+                .with_runtime(runtime)
+                .init()
+                .expect("Initialization failed");
+            let res = unsafe {
+                gg_runtime_start(Some(forward_to_runtime), gg_runtime_opt_GG_RT_OPT_ASYNC)
+            };
+            assert_eq!(res, gg_error_GGE_SUCCESS);
+
+            // The poll loop gets the next invocation while the handler is running, so the order varies
+            let mut responses: Vec<RecordedRequest> = (0..5)
+                .map(|_| requests.recv_timeout(timeout).expect("Expected request"))
+                .filter(|r| r.method == "POST")
+                .collect();
+            responses.sort_by(|a, b| {
+                a.header(HEADER_INVOCATION_ID)
+                    .cmp(&b.header(HEADER_INVOCATION_ID))
+            });
+            assert_eq!(responses.len(), 2);
+            assert_eq!(responses[0].header(HEADER_INVOCATION_ID), Some("11"));
+            assert_eq!(responses[0].body, b"echo: hello");
+            assert_eq!(responses[0].header(HEADER_FUNCTION_ERROR), None);
+            // Greengrass waits for a response, so an empty one is sent once the handler has returned
+            assert_eq!(responses[1].header(HEADER_INVOCATION_ID), Some("12"));
+            assert!(responses[1].body.is_empty());
+
+            // Restores the backend of the runtime for the other tests
+            Initializer::default()
+                .with_runtime(Runtime::default())
+                .init()
+                .expect("Initialization failed");
+        }
+    }
+}
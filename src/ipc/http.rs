@@ -0,0 +1,170 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! A minimal HTTP/1.1 client, just enough to talk to the local Greengrass IPC endpoint.
+//! Every request uses its own connection, which is closed by the server once the response is sent.
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// A response received from the IPC endpoint
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Returns the value of the first header with the specified name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Sends a request to the endpoint (host:port) and waits for the complete response
+pub(crate) fn send(
+    endpoint: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<HttpResponse> {
+    let mut stream = TcpStream::connect(endpoint)?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        path,
+        endpoint,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw)?;
+    parse_response(&raw)
+}
+
+/// Parses a complete response, as read from a connection closed by the server
+fn parse_response(raw: &[u8]) -> io::Result<HttpResponse> {
+    let head_end = find(raw, b"\r\n\r\n").ok_or_else(|| invalid("incomplete response head"))?;
+    let head = String::from_utf8_lossy(&raw[..head_end]);
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect();
+
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: vec![],
+    };
+
+    let rest = &raw[head_end + 4..];
+    response.body = if response
+        .header("Transfer-Encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+    {
+        decode_chunked(rest)?
+    } else if let Some(length) = response.header("Content-Length") {
+        let length = length
+            .parse::<usize>()
+            .map_err(|_| invalid("invalid content length"))?;
+        rest.get(..length)
+            .ok_or_else(|| invalid("response body shorter than content length"))?
+            .to_vec()
+    } else {
+        rest.to_vec()
+    };
+    Ok(response)
+}
+
+/// Decodes a body sent with the chunked transfer encoding
+fn decode_chunked(mut raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = find(raw, b"\r\n").ok_or_else(|| invalid("incomplete chunk size"))?;
+        let size_line = String::from_utf8_lossy(&raw[..line_end]);
+        // ignore any chunk extensions
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let size =
+            usize::from_str_radix(size_hex, 16).map_err(|_| invalid("invalid chunk size"))?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = raw.get(..size).ok_or_else(|| invalid("incomplete chunk"))?;
+        body.extend_from_slice(chunk);
+        // skip the chunk and its trailing CRLF
+        raw = raw.get(size + 2..).unwrap_or(&[]);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_length() {
+        let raw = b"HTTP/1.1 200 OK\r\nX-Amz-InvocationId: abc\r\nContent-Length: 5\r\n\r\nhello";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("x-amz-invocationid"), Some("abc"));
+        assert_eq!(response.body, b"hello");
+    }
+
+    #[test]
+    fn test_parse_chunked() {
+        let raw =
+            b"HTTP/1.1 202 Accepted\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 202);
+        assert_eq!(response.body, b"hello world");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(parse_response(b"garbage\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,643 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! A pure Rust implementation of the Greengrass v1 local IPC protocol.
+//!
+//! When the `ipc` feature is enabled this module replaces the C SDK bindings, so neither bindgen
+//! nor `libaws-greengrass-core-sdk-c` are needed to build. The functions in [`ffi`] mirror the C SDK
+//! functions used by the rest of the crate and are implemented with HTTP requests to the IPC endpoint
+//! of the Greengrass core.
+//!
+//! The following environment variables, set by Greengrass for every lambda, are used:
+//! * `AWS_CONTAINER_AUTHORIZATION_TOKEN` - the token used to authorize requests
+//! * `MY_FUNCTION_ARN` - the arn of this lambda, used to poll for work
+//!
+//! The endpoint defaults to `localhost:8000` and can be overridden with `GG_IPC_ENDPOINT`.
+//!
+//! Invocations are tracked by their id. As with the C SDK, the response to an invocation is written from
+//! the thread the handler callback was called on. The runtime handles events with a [`crate::handler::Handler`]
+//! on other threads, so it detaches the invocation from the callback and attaches it to the thread running the handler.
+//! Writing a response from any other thread fails with [`crate::error::GGError::InvalidState`].
+//! Once the handler has returned the invocation is completed, if no response was written an empty one is sent
+//! as Greengrass waits for one.
+pub(crate) mod ffi;
+mod http;
+
+use self::http::HttpResponse;
+use serde_json::{json, Value};
+use std::env;
+use std::fmt;
+use std::io;
+
+const DEFAULT_ENDPOINT: &str = "localhost:8000";
+const ENDPOINT_ENV: &str = "GG_IPC_ENDPOINT";
+const AUTH_TOKEN_ENV: &str = "AWS_CONTAINER_AUTHORIZATION_TOKEN";
+pub(crate) const FUNCTION_ARN_ENV: &str = "MY_FUNCTION_ARN";
+
+const API_VERSION: &str = "2016-11-01";
+const HEADER_INVOCATION_ID: &str = "X-Amz-InvocationId";
+const HEADER_CLIENT_CONTEXT: &str = "X-Amz-Client-Context";
+const HEADER_AUTH_TOKEN: &str = "Authorization";
+const HEADER_INVOCATION_TYPE: &str = "X-Amz-Invocation-Type";
+const HEADER_FUNCTION_ERROR: &str = "X-Amz-Function-Error";
+
+/// The system lambdas that provide the Greengrass services
+const ROUTER_FUNCTION_ARN: &str = "arn:aws:lambda:::function:GGRouter";
+const SHADOW_FUNCTION_ARN: &str = "arn:aws:lambda:::function:GGShadowService";
+const SECRETS_MANAGER_FUNCTION_ARN: &str = "arn:aws:lambda:::function:GGSecretManager:1";
+
+/// How a lambda is invoked through the IPC endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum InvocationType {
+    Event,
+    RequestResponse,
+}
+
+impl InvocationType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Event => "Event",
+            Self::RequestResponse => "RequestResponse",
+        }
+    }
+}
+
+/// Shadow operations, which are part of the topic sent to the shadow service
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ShadowOperation {
+    Get,
+    Update,
+    Delete,
+}
+
+impl ShadowOperation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Get => "get",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// Errors communicating with the IPC endpoint
+#[derive(Debug)]
+pub(crate) enum IpcError {
+    /// The endpoint could not be reached or sent an invalid response
+    Io(io::Error),
+    /// The endpoint responded with a non success status
+    Status(HttpResponse),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IPC request failed: {}", e),
+            Self::Status(resp) => write!(
+                f,
+                "IPC request failed with status {}: {}",
+                resp.status,
+                String::from_utf8_lossy(&resp.body)
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for IpcError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// An invocation of this lambda received from the endpoint
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct WorkItem {
+    pub invocation_id: String,
+    pub client_context: String,
+    pub payload: Vec<u8>,
+}
+
+/// The result of a request-response invocation of another lambda
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct WorkResult {
+    pub payload: Vec<u8>,
+    /// Set if the invoked lambda failed, either Handled or Unhandled
+    pub function_error: Option<String>,
+}
+
+/// Client for the IPC endpoint of the Greengrass core
+#[derive(Debug, Clone)]
+pub(crate) struct IpcClient {
+    endpoint: String,
+    auth_token: String,
+}
+
+impl IpcClient {
+    pub fn new(endpoint: &str, auth_token: &str) -> Self {
+        IpcClient {
+            endpoint: endpoint.to_owned(),
+            auth_token: auth_token.to_owned(),
+        }
+    }
+
+    /// Creates a client using the environment Greengrass provides to lambdas
+    pub fn from_env() -> Self {
+        let endpoint = env::var(ENDPOINT_ENV).unwrap_or_else(|_| DEFAULT_ENDPOINT.to_owned());
+        let auth_token = env::var(AUTH_TOKEN_ENV).unwrap_or_default();
+        Self::new(&endpoint, &auth_token)
+    }
+
+    /// Invokes a lambda, returning the invocation id
+    pub fn post_work(
+        &self,
+        function_arn: &str,
+        payload: &[u8],
+        client_context: &str,
+        invocation_type: InvocationType,
+    ) -> Result<String, IpcError> {
+        let response = self.send(
+            "POST",
+            &function_path(function_arn),
+            &[
+                (HEADER_CLIENT_CONTEXT, client_context),
+                (HEADER_INVOCATION_TYPE, invocation_type.as_str()),
+            ],
+            payload,
+        )?;
+        Ok(response
+            .header(HEADER_INVOCATION_ID)
+            .unwrap_or_default()
+            .to_owned())
+    }
+
+    /// Waits for the next invocation of the specified lambda
+    pub fn get_work(&self, function_arn: &str) -> Result<WorkItem, IpcError> {
+        let response = self.send("GET", &work_path(function_arn), &[], &[])?;
+        Ok(WorkItem {
+            invocation_id: response
+                .header(HEADER_INVOCATION_ID)
+                .unwrap_or_default()
+                .to_owned(),
+            client_context: response
+                .header(HEADER_CLIENT_CONTEXT)
+                .unwrap_or_default()
+                .to_owned(),
+            payload: response.body,
+        })
+    }
+
+    /// Sends the result of an invocation of the specified lambda
+    pub fn post_work_result(
+        &self,
+        function_arn: &str,
+        invocation_id: &str,
+        payload: &[u8],
+    ) -> Result<(), IpcError> {
+        self.send(
+            "POST",
+            &work_path(function_arn),
+            &[(HEADER_INVOCATION_ID, invocation_id)],
+            payload,
+        )
+        .map(|_| ())
+    }
+
+    /// Reports that the invocation of the specified lambda failed
+    pub fn post_handler_err(
+        &self,
+        function_arn: &str,
+        invocation_id: &str,
+        message: &str,
+    ) -> Result<(), IpcError> {
+        let payload = json!({ "errorMessage": message }).to_string();
+        self.send(
+            "POST",
+            &work_path(function_arn),
+            &[
+                (HEADER_INVOCATION_ID, invocation_id),
+                (HEADER_FUNCTION_ERROR, "Handled"),
+            ],
+            payload.as_bytes(),
+        )
+        .map(|_| ())
+    }
+
+    /// Waits for the result of a request-response invocation
+    pub fn get_work_result(
+        &self,
+        function_arn: &str,
+        invocation_id: &str,
+    ) -> Result<WorkResult, IpcError> {
+        let response = self.send(
+            "GET",
+            &function_path(function_arn),
+            &[(HEADER_INVOCATION_ID, invocation_id)],
+            &[],
+        )?;
+        Ok(WorkResult {
+            function_error: response.header(HEADER_FUNCTION_ERROR).map(str::to_owned),
+            payload: response.body,
+        })
+    }
+
+    /// Invokes a lambda and waits for its result if it is a request-response invocation
+    pub fn invoke(
+        &self,
+        function_arn: &str,
+        payload: &[u8],
+        client_context: &str,
+        invocation_type: InvocationType,
+    ) -> Result<Option<WorkResult>, IpcError> {
+        let invocation_id =
+            self.post_work(function_arn, payload, client_context, invocation_type)?;
+        match invocation_type {
+            InvocationType::Event => Ok(None),
+            InvocationType::RequestResponse => {
+                self.get_work_result(function_arn, &invocation_id).map(Some)
+            }
+        }
+    }
+
+    /// Publishes to a topic through the Greengrass router
+    pub fn publish(
+        &self,
+        source_arn: &str,
+        topic: &str,
+        payload: &[u8],
+        queue_full_policy: &str,
+    ) -> Result<(), IpcError> {
+        let context = json!({
+            "custom": {
+                "source": source_arn,
+                "subject": topic,
+                "queueFullPolicy": queue_full_policy,
+            }
+        });
+        self.invoke(
+            ROUTER_FUNCTION_ARN,
+            payload,
+            &encode_context(&context),
+            InvocationType::Event,
+        )
+        .map(|_| ())
+    }
+
+    /// Performs an operation on the shadow of a thing through the Greengrass shadow service
+    pub fn shadow(
+        &self,
+        operation: ShadowOperation,
+        thing_name: &str,
+        payload: &[u8],
+    ) -> Result<WorkResult, IpcError> {
+        let context = json!({
+            "custom": {
+                "subject": format!("$aws/things/{}/shadow/{}", thing_name, operation.as_str()),
+            }
+        });
+        self.request_response(SHADOW_FUNCTION_ARN, payload, &encode_context(&context))
+    }
+
+    /// Retrieves a secret through the Greengrass secrets manager
+    pub fn get_secret_value(
+        &self,
+        secret_id: &str,
+        version_id: Option<&str>,
+        version_stage: Option<&str>,
+    ) -> Result<WorkResult, IpcError> {
+        let mut payload = json!({ "SecretId": secret_id });
+        if let Some(version_id) = version_id {
+            payload["VersionId"] = Value::from(version_id);
+        }
+        if let Some(version_stage) = version_stage {
+            payload["VersionStage"] = Value::from(version_stage);
+        }
+        self.request_response(
+            SECRETS_MANAGER_FUNCTION_ARN,
+            payload.to_string().as_bytes(),
+            "",
+        )
+    }
+
+    fn request_response(
+        &self,
+        function_arn: &str,
+        payload: &[u8],
+        client_context: &str,
+    ) -> Result<WorkResult, IpcError> {
+        self.invoke(
+            function_arn,
+            payload,
+            client_context,
+            InvocationType::RequestResponse,
+        )
+        .map(Option::unwrap_or_default)
+    }
+
+    /// Sends an authorized request, returning an error for non success responses
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<HttpResponse, IpcError> {
+        let mut all_headers = vec![(HEADER_AUTH_TOKEN, self.auth_token.as_str())];
+        all_headers.extend_from_slice(headers);
+        let response = http::send(&self.endpoint, method, path, &all_headers, body)?;
+        if response.is_success() {
+            Ok(response)
+        } else {
+            Err(IpcError::Status(response))
+        }
+    }
+}
+
+fn function_path(function_arn: &str) -> String {
+    format!("/{}/functions/{}", API_VERSION, function_arn)
+}
+
+fn work_path(function_arn: &str) -> String {
+    format!("{}/work", function_path(function_arn))
+}
+
+fn encode_context(context: &Value) -> String {
+    base64::encode(context.to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    /// A request received by the stand-in endpoint
+    #[derive(Debug, Clone)]
+    pub(crate) struct RecordedRequest {
+        pub method: String,
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl RecordedRequest {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// A canned response sent by the stand-in endpoint
+    pub(crate) struct CannedResponse {
+        pub status: u16,
+        pub headers: Vec<(&'static str, String)>,
+        pub body: Vec<u8>,
+        /// Only send this response to requests with the method, any request if None
+        pub method: Option<&'static str>,
+    }
+
+    impl CannedResponse {
+        pub fn ok(body: &[u8]) -> Self {
+            CannedResponse {
+                status: 200,
+                headers: vec![],
+                body: body.to_vec(),
+                method: None,
+            }
+        }
+
+        #[cfg(not(feature = "mock"))]
+        pub fn for_method(self, method: &'static str) -> Self {
+            CannedResponse {
+                method: Some(method),
+                ..self
+            }
+        }
+
+        pub fn with_header(mut self, name: &'static str, value: &str) -> Self {
+            self.headers.push((name, value.to_owned()));
+            self
+        }
+    }
+
+    /// Starts a stand-in for the IPC endpoint that sends the canned responses in order and records the requests.
+    /// Each request is sent the first remaining response for its method. Connections received when there is
+    /// no such response are held open without a response.
+    pub(crate) fn stand_in(responses: Vec<CannedResponse>) -> (String, Receiver<RecordedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Could not bind stand-in endpoint");
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let mut responses = responses;
+            let mut held = vec![];
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let request = read_request(&mut stream);
+                let next = responses
+                    .iter()
+                    .position(|r| r.method.is_none_or(|method| method == request.method));
+                let _ = sender.send(request);
+                match next {
+                    Some(index) => write_response(&mut stream, responses.remove(index)),
+                    None => held.push(stream),
+                }
+            }
+        });
+        (endpoint, receiver)
+    }
+
+    fn read_request(stream: &mut TcpStream) -> RecordedRequest {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let path = parts.next().unwrap_or_default().to_owned();
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let mut parts = line.splitn(2, ':');
+            headers.push((
+                parts.next().unwrap().trim().to_owned(),
+                parts.next().unwrap_or_default().trim().to_owned(),
+            ));
+        }
+
+        let length = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+            .map_or(0, |(_, v)| v.parse::<usize>().unwrap());
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+        RecordedRequest {
+            method,
+            path,
+            headers,
+            body,
+        }
+    }
+
+    fn write_response(stream: &mut TcpStream, response: CannedResponse) {
+        let mut head = format!(
+            "HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\n",
+            response.status,
+            response.body.len()
+        );
+        for (name, value) in response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&response.body).unwrap();
+    }
+
+    fn decode_context(request: &RecordedRequest) -> Value {
+        let raw = base64::decode(request.header(HEADER_CLIENT_CONTEXT).unwrap()).unwrap();
+        serde_json::from_slice(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_publish() {
+        let (endpoint, requests) = stand_in(vec![
+            CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1234")
+        ]);
+        let client = IpcClient::new(&endpoint, "my token");
+        client
+            .publish("my_func_arn", "my/topic", b"hello", "BestEffort")
+            .unwrap();
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.path,
+            "/2016-11-01/functions/arn:aws:lambda:::function:GGRouter"
+        );
+        assert_eq!(request.header(HEADER_AUTH_TOKEN), Some("my token"));
+        assert_eq!(request.header(HEADER_INVOCATION_TYPE), Some("Event"));
+        assert_eq!(request.body, b"hello");
+        assert_eq!(
+            decode_context(&request),
+            json!({"custom": {"source": "my_func_arn", "subject": "my/topic", "queueFullPolicy": "BestEffort"}})
+        );
+    }
+
+    #[test]
+    fn test_shadow() {
+        let (endpoint, requests) = stand_in(vec![
+            CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1234"),
+            CannedResponse::ok(br#"{"state": {}}"#),
+        ]);
+        let client = IpcClient::new(&endpoint, "my token");
+        let result = client
+            .shadow(ShadowOperation::Get, "my_thing", b"")
+            .unwrap();
+        assert_eq!(result.payload, br#"{"state": {}}"#);
+        assert_eq!(result.function_error, None);
+
+        let invoke = requests.recv().unwrap();
+        assert_eq!(
+            invoke.header(HEADER_INVOCATION_TYPE),
+            Some("RequestResponse")
+        );
+        assert_eq!(
+            decode_context(&invoke),
+            json!({"custom": {"subject": "$aws/things/my_thing/shadow/get"}})
+        );
+        let result = requests.recv().unwrap();
+        assert_eq!(result.method, "GET");
+        assert_eq!(
+            result.path,
+            "/2016-11-01/functions/arn:aws:lambda:::function:GGShadowService"
+        );
+        assert_eq!(result.header(HEADER_INVOCATION_ID), Some("1234"));
+    }
+
+    #[test]
+    fn test_get_secret_value() {
+        let (endpoint, requests) = stand_in(vec![
+            CannedResponse::ok(b"").with_header(HEADER_INVOCATION_ID, "1234"),
+            CannedResponse::ok(b"error").with_header(HEADER_FUNCTION_ERROR, "Unhandled"),
+        ]);
+        let client = IpcClient::new(&endpoint, "my token");
+        let result = client
+            .get_secret_value("my_secret", None, Some("AWSCURRENT"))
+            .unwrap();
+        assert_eq!(result.function_error, Some("Unhandled".to_owned()));
+
+        let invoke = requests.recv().unwrap();
+        let payload: Value = serde_json::from_slice(&invoke.body).unwrap();
+        assert_eq!(
+            payload,
+            json!({"SecretId": "my_secret", "VersionStage": "AWSCURRENT"})
+        );
+    }
+
+    #[test]
+    fn test_error_status() {
+        let (endpoint, _requests) = stand_in(vec![CannedResponse {
+            status: 503,
+            headers: vec![],
+            body: b"busy".to_vec(),
+            method: None,
+        }]);
+        let client = IpcClient::new(&endpoint, "my token");
+        match client.post_work("my_func_arn", b"", "", InvocationType::Event) {
+            Err(IpcError::Status(response)) => assert_eq!(response.status, 503),
+            other => panic!("Expected a status error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_work() {
+        let (endpoint, requests) = stand_in(vec![
+            CannedResponse::ok(b"event payload")
+                .with_header(HEADER_INVOCATION_ID, "1234")
+                .with_header(HEADER_CLIENT_CONTEXT, "e30="),
+            CannedResponse::ok(b""),
+            CannedResponse::ok(b""),
+        ]);
+        let client = IpcClient::new(&endpoint, "my token");
+        let work = client.get_work("my_func_arn").unwrap();
+        assert_eq!(
+            work,
+            WorkItem {
+                invocation_id: "1234".to_owned(),
+                client_context: "e30=".to_owned(),
+                payload: b"event payload".to_vec(),
+            }
+        );
+        client
+            .post_work_result("my_func_arn", "1234", b"result")
+            .unwrap();
+        client
+            .post_handler_err("my_func_arn", "1234", "it failed")
+            .unwrap();
+
+        let get_work = requests.recv().unwrap();
+        assert_eq!(get_work.method, "GET");
+        assert_eq!(get_work.path, "/2016-11-01/functions/my_func_arn/work");
+        let result = requests.recv().unwrap();
+        assert_eq!(result.body, b"result");
+        assert_eq!(result.header(HEADER_INVOCATION_ID), Some("1234"));
+        let err = requests.recv().unwrap();
+        assert_eq!(err.header(HEADER_FUNCTION_ERROR), Some("Handled"));
+        assert_eq!(err.body, br#"{"errorMessage":"it failed"}"#);
+    }
+}
@@ -5,20 +5,30 @@
  * This source code is licensed under the Apache-2.0 license found in
  * the LICENSE file in the root of this source tree.
  */
- 
+
+#[cfg(not(all(test, feature = "mock")))]
 use base64::encode;
 use serde::Serialize;
 use serde_json;
 use std::convert::TryFrom;
 use std::default::Default;
-use std::ffi::CString;
-use std::os::raw::c_void;
-use std::ptr;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::time::Duration;
 
+use crate::backend::Backend;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::backend::{default_backend, InvokeArgs};
 use crate::bindings::*;
+#[cfg(feature = "async")]
+use crate::blocking::BlockingPool;
+use crate::codec::Codec;
 use crate::error::GGError;
-use crate::request::GGRequestResponse;
-use crate::with_request;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::request::{GGRequestResponse, Request, ResponseReader};
+#[cfg(not(all(test, feature = "mock")))]
+use crate::retry::with_retries;
+use crate::retry::RetryPolicy;
 use crate::GGResult;
 
 #[cfg(all(test, feature = "mock"))]
@@ -45,6 +55,7 @@ impl<C: Serialize> InvokeOptions<C> {
         }
     }
 
+    #[cfg(not(all(test, feature = "mock")))]
     fn serialize_customer_context(&self) -> GGResult<String> {
         let json = serde_json::to_string(&self.customer_context).map_err(GGError::from)?;
         Ok(encode(json))
@@ -53,11 +64,45 @@ impl<C: Serialize> InvokeOptions<C> {
 
 /// Provides the ability to execute other lambda functions
 pub struct LambdaClient {
+    #[cfg(not(all(test, feature = "mock")))]
+    backend: Arc<dyn Backend>,
+    #[cfg(not(all(test, feature = "mock")))]
+    retry_policy: Option<RetryPolicy>,
     #[cfg(all(test, feature = "mock"))]
     pub mocks: MockHolder,
 }
 
+#[allow(clippy::derivable_impls)]
+impl Default for LambdaClient {
+    fn default() -> Self {
+        LambdaClient {
+            #[cfg(not(all(test, feature = "mock")))]
+            backend: default_backend(),
+            #[cfg(not(all(test, feature = "mock")))]
+            retry_policy: None,
+            #[cfg(all(test, feature = "mock"))]
+            mocks: MockHolder::default(),
+        }
+    }
+}
+
 impl LambdaClient {
+    /// Use the specified backend instead of the C SDK
+    #[cfg(not(all(test, feature = "mock")))]
+    #[allow(clippy::needless_update)]
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        LambdaClient { backend, ..self }
+    }
+
+    /// Optionally define a policy to retry failed invocations with
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn with_retry_policy(self, retry_policy: Option<RetryPolicy>) -> Self {
+        LambdaClient {
+            retry_policy,
+            ..self
+        }
+    }
+
     /// Allows lambda invocation with an optional payload and wait for a response.
     ///
     /// # Example
@@ -78,13 +123,58 @@ impl LambdaClient {
     /// let response = LambdaClient::default().invoke_sync(options, Some(payload));
     /// println!("response: {:?}", response);
     /// ```
-    #[cfg(not(feature = "mock"))]
+    #[cfg(not(all(test, feature = "mock")))]
     pub fn invoke_sync<C: Serialize, P: AsRef<[u8]>>(
         &self,
         option: InvokeOptions<C>,
         payload: Option<P>,
     ) -> GGResult<Option<Vec<u8>>> {
-        invoke(&option, InvokeType::InvokeRequestResponse, &payload)
+        invoke(
+            self.backend.as_ref(),
+            self.retry_policy.as_ref(),
+            &option,
+            InvokeType::InvokeRequestResponse,
+            &payload,
+        )
+    }
+
+    /// Like [`LambdaClient::invoke_sync`], but returns a reader that streams the response
+    /// instead of reading it into memory.
+    ///
+    /// # Example
+    /// ```rust
+    /// use aws_greengrass_core_rust::lambda::{InvokeOptions, LambdaClient};
+    /// use std::io::{BufRead, BufReader};
+    ///
+    /// let options = InvokeOptions::new("my_func_arn".to_owned(), (), "lambda qualifier".to_owned());
+    /// let client = LambdaClient::default();
+    /// if let Ok(Some(reader)) = client.invoke_sync_reader(options, Some("Some payload")) {
+    ///     for line in BufReader::new(reader).lines() {
+    ///         println!("response line: {:?}", line);
+    ///     }
+    /// };
+    /// ```
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn invoke_sync_reader<C: Serialize, P: AsRef<[u8]>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<Option<ResponseReader<'_>>> {
+        let backend = self.backend.as_ref();
+        let customer_context = option.serialize_customer_context()?;
+        let args = InvokeArgs {
+            function_arn: &option.function_arn,
+            customer_context: &customer_context,
+            qualifier: &option.qualifier,
+            invoke_type: InvokeType::InvokeRequestResponse,
+            payload: payload.as_ref().map(|p| p.as_ref()),
+        };
+        with_retries(self.retry_policy.as_ref(), "Invoke", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe { backend.invoke(req.handle(), &args) }?;
+            GGRequestResponse::from(status).reader(req)
+        })
     }
 
     /// Allows lambda invocation with an optional payload. The lambda will be executed asynchronously and no response will be returned
@@ -108,28 +198,62 @@ impl LambdaClient {
     ///     eprintln!("Error occurred: {}", e);
     /// }
     /// ```
-    #[cfg(not(feature = "mock"))]
+    #[cfg(not(all(test, feature = "mock")))]
     pub fn invoke_async<C: Serialize, P: AsRef<[u8]>>(
         &self,
         option: InvokeOptions<C>,
         payload: Option<P>,
     ) -> GGResult<()> {
-        invoke(&option, InvokeType::InvokeEvent, &payload).map(|_| ())
+        invoke(
+            self.backend.as_ref(),
+            self.retry_policy.as_ref(),
+            &option,
+            InvokeType::InvokeEvent,
+            &payload,
+        )
+        .map(|_| ())
     }
 
     /// Allows lambda functions that have been invoked by another lambda to send a response back
     /// On success send Ok(P)
     /// On Error send Err(String)
-    #[cfg(not(feature = "mock"))]
+    #[cfg(not(all(test, feature = "mock")))]
     pub fn send_response(&self, result: Result<&[u8], &str>) -> GGResult<()> {
-        unsafe {
-            match result {
-                Ok(bytes) => write_lambda_response(bytes),
-                Err(e) => write_lambda_err_response(e),
-            }
+        match result {
+            Ok(bytes) => self.backend.handler_write_response(bytes),
+            Err(e) => self.backend.handler_write_error(e),
         }
     }
 
+    /// Invokes a lambda with the payload encoded by the codec and decodes its response with it
+    pub fn invoke_sync_with_codec<C: Serialize, T, R, E: Codec<T> + Codec<R>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<&T>,
+        codec: &E,
+    ) -> GGResult<Option<R>> {
+        Lambda::invoke_sync_with_codec(self, option, payload, codec)
+    }
+
+    /// Invokes a lambda asynchronously with the payload encoded by the codec
+    pub fn invoke_async_with_codec<C: Serialize, T, E: Codec<T>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<&T>,
+        codec: &E,
+    ) -> GGResult<()> {
+        Lambda::invoke_async_with_codec(self, option, payload, codec)
+    }
+
+    /// Sends the response encoded by the codec back to the lambda that invoked this one
+    pub fn send_response_with_codec<T, E: Codec<T>>(
+        &self,
+        result: Result<&T, &str>,
+        codec: &E,
+    ) -> GGResult<()> {
+        Lambda::send_response_with_codec(self, result, codec)
+    }
+
     // -----------------------------------
     // Mock methods
     // -----------------------------------
@@ -137,11 +261,11 @@ impl LambdaClient {
     #[cfg(all(test, feature = "mock"))]
     pub fn invoke_sync<C: Serialize, P: AsRef<[u8]>>(
         &self,
-        option: &InvokeOptions<C>,
-        payload: &Option<P>,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
     ) -> GGResult<Option<Vec<u8>>> {
         log::warn!("Mock invoke_sync is being executed!!! This should not happen in prod!!!!");
-        let opts = InvokeOptionsInput::from(option);
+        let opts = InvokeOptionsInput::from(&option);
         let payload_bytes = payload.as_ref().map(|p| p.as_ref().to_vec());
         self.mocks
             .invoke_sync_inputs
@@ -158,11 +282,11 @@ impl LambdaClient {
     #[cfg(all(test, feature = "mock"))]
     pub fn invoke_async<C: Serialize, P: AsRef<[u8]>>(
         &self,
-        option: &InvokeOptions<C>,
-        payload: &Option<P>,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
     ) -> GGResult<()> {
         log::warn!("Mock invoke_async is being executed!!! This should not happen in prod!!!!");
-        let opts = InvokeOptionsInput::from(option);
+        let opts = InvokeOptionsInput::from(&option);
 
         let payload_bytes = payload.as_ref().map(|p| p.as_ref().to_vec());
         self.mocks
@@ -193,22 +317,27 @@ impl LambdaClient {
     /// provided outputs
     #[cfg(all(test, feature = "mock"))]
     pub fn with_mocks(self, mocks: MockHolder) -> Self {
-        LambdaClient { mocks, ..self }
+        LambdaClient { mocks }
     }
-}
 
-impl Default for LambdaClient {
-    fn default() -> Self {
-        LambdaClient {
-            #[cfg(all(test, feature = "mock"))]
-            mocks: MockHolder::default(),
-        }
+    /// Mocks don't use a backend, so it is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_backend(self, _backend: Arc<dyn Backend>) -> Self {
+        self
+    }
+
+    /// Mocks aren't retried, so the policy is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_retry_policy(self, _retry_policy: Option<RetryPolicy>) -> Self {
+        self
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub(crate) enum InvokeType {
+/// Whether the invoked lambda's response is waited for
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum InvokeType {
     /// Invoke the function asynchronously
+    #[default]
     InvokeEvent,
     /// Invoke the function synchronously (default)
     InvokeRequestResponse,
@@ -227,14 +356,8 @@ impl TryFrom<gg_invoke_type> for InvokeType {
     }
 }
 
-impl Default for InvokeType {
-    fn default() -> Self {
-        InvokeType::InvokeEvent
-    }
-}
-
 impl InvokeType {
-    fn as_c_invoke_type(&self) -> gg_invoke_type {
+    pub(crate) fn as_c_invoke_type(&self) -> gg_invoke_type {
         match *self {
             Self::InvokeEvent => gg_invoke_type_GG_INVOKE_EVENT,
             Self::InvokeRequestResponse => gg_invoke_type_GG_INVOKE_REQUEST_RESPONSE,
@@ -242,63 +365,203 @@ impl InvokeType {
     }
 }
 
+#[cfg(not(all(test, feature = "mock")))]
 fn invoke<C: Serialize, P: AsRef<[u8]>>(
+    backend: &dyn Backend,
+    retry_policy: Option<&RetryPolicy>,
     option: &InvokeOptions<C>,
     invoke_type: InvokeType,
     payload: &Option<P>,
 ) -> GGResult<Option<Vec<u8>>> {
-    unsafe {
-        let function_arn_c = CString::new(option.function_arn.as_str()).map_err(GGError::from)?;
-        let customer_context_c =
-            CString::new(option.serialize_customer_context()?).map_err(GGError::from)?;
-        let qualifier_c = CString::new(option.qualifier.as_str()).map_err(GGError::from)?;
-        let payload_bytes = payload.as_ref().map(|p| p.as_ref());
-        let (payload_c, payload_size) = if let Some(p) = payload_bytes {
-            (p as *const _ as *const c_void, p.len())
-        } else {
-            (ptr::null(), 0)
+    let customer_context = option.serialize_customer_context()?;
+    let args = InvokeArgs {
+        function_arn: &option.function_arn,
+        customer_context: &customer_context,
+        qualifier: &option.qualifier,
+        invoke_type: invoke_type.clone(),
+        payload: payload.as_ref().map(|p| p.as_ref()),
+    };
+    with_retries(retry_policy, "Invoke", || {
+        let req = Request::new(backend)?;
+        // The request was created above with the same backend and is still open
+        let response = GGRequestResponse::from(unsafe { backend.invoke(req.handle(), &args) }?);
+        let output = match invoke_type {
+            InvokeType::InvokeEvent => {
+                response.to_error_result(&req)?;
+                None
+            }
+            InvokeType::InvokeRequestResponse => response.read(&req)?,
         };
+        req.close()?;
+        Ok(output)
+    })
+}
 
-        let options_c = Box::new(gg_invoke_options {
-            function_arn: function_arn_c.as_ptr(),
-            customer_context: customer_context_c.as_ptr(),
-            qualifier: qualifier_c.as_ptr(),
-            type_: invoke_type.as_c_invoke_type(),
-            payload: payload_c,
-            payload_size,
-        });
+/// The operations of [`LambdaClient`], with the same signatures whether or not the mock feature is enabled.
+///
+/// Business logic can be generic over this trait so that it can be tested with a fake client.
+pub trait Lambda {
+    /// Invokes a lambda with an optional payload and waits for its response
+    fn invoke_sync<C: Serialize, P: AsRef<[u8]>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<Option<Vec<u8>>>;
+
+    /// Invokes a lambda with an optional payload without waiting for it to execute
+    fn invoke_async<C: Serialize, P: AsRef<[u8]>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<()>;
+
+    /// Sends the response of a lambda that was invoked by another lambda
+    fn send_response(&self, result: Result<&[u8], &str>) -> GGResult<()>;
+
+    /// Invokes a lambda with the payload encoded by the codec and decodes its response with it
+    fn invoke_sync_with_codec<C: Serialize, T, R, E: Codec<T> + Codec<R>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<&T>,
+        codec: &E,
+    ) -> GGResult<Option<R>> {
+        let payload = payload.map(|p| codec.encode(p)).transpose()?;
+        self.invoke_sync(option, payload)?
+            .map(|response| codec.decode(&response))
+            .transpose()
+    }
+
+    /// Invokes a lambda asynchronously with the payload encoded by the codec
+    fn invoke_async_with_codec<C: Serialize, T, E: Codec<T>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<&T>,
+        codec: &E,
+    ) -> GGResult<()> {
+        let payload = payload.map(|p| codec.encode(p)).transpose()?;
+        self.invoke_async(option, payload)
+    }
 
-        let mut req: gg_request = ptr::null_mut();
-        with_request!(req, {
-            let mut res = gg_request_result {
-                request_status: gg_request_status_GG_REQUEST_SUCCESS,
-            };
-            let raw_options = Box::into_raw(options_c);
-            let invoke_res = gg_invoke(req, raw_options, &mut res);
-            let _ = Box::from_raw(raw_options); // cleanup
-            GGError::from_code(invoke_res)?;
-
-            match invoke_type {
-                InvokeType::InvokeEvent => {
-                    GGRequestResponse::try_from(&res)?.to_error_result(req)?;
-                    Ok(None)
-                }
-                InvokeType::InvokeRequestResponse => GGRequestResponse::try_from(&res)?.read(req),
+    /// Sends the response encoded by the codec
+    fn send_response_with_codec<T, E: Codec<T>>(
+        &self,
+        result: Result<&T, &str>,
+        codec: &E,
+    ) -> GGResult<()> {
+        match result {
+            Ok(response) => {
+                let bytes = codec.encode(response)?;
+                self.send_response(Ok(&bytes))
             }
-        })
+            Err(e) => self.send_response(Err(e)),
+        }
     }
 }
 
-unsafe fn write_lambda_response(buffer: &[u8]) -> GGResult<()> {
-    let buffer_c = buffer as *const _ as *const c_void;
-    let resp = gg_lambda_handler_write_response(buffer_c, buffer.len());
-    GGError::from_code(resp)
+impl Lambda for LambdaClient {
+    fn invoke_sync<C: Serialize, P: AsRef<[u8]>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<Option<Vec<u8>>> {
+        LambdaClient::invoke_sync(self, option, payload)
+    }
+
+    fn invoke_async<C: Serialize, P: AsRef<[u8]>>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<()> {
+        LambdaClient::invoke_async(self, option, payload)
+    }
+
+    fn send_response(&self, result: Result<&[u8], &str>) -> GGResult<()> {
+        LambdaClient::send_response(self, result)
+    }
 }
 
-unsafe fn write_lambda_err_response(err_msg: &str) -> GGResult<()> {
-    let err_msg_c = CString::new(err_msg).map_err(GGError::from)?;
-    let resp = gg_lambda_handler_write_error(err_msg_c.as_ptr());
-    GGError::from_code(resp)
+/// Executes other lambda functions without blocking the executor by running the calls of a [`LambdaClient`] on a [`BlockingPool`].
+///
+/// ```rust,no_run
+/// use aws_greengrass_core_rust::lambda::{AsyncLambdaClient, InvokeOptions};
+///
+/// # async fn run() {
+/// let options = InvokeOptions::new("my_func_arn".to_owned(), "context".to_owned(), "lambda qualifier".to_owned());
+/// let response = AsyncLambdaClient::default().invoke_sync(options, Some("Some payload")).await;
+/// println!("response: {:?}", response);
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[derive(Clone, Default)]
+pub struct AsyncLambdaClient {
+    client: Arc<LambdaClient>,
+    pool: BlockingPool,
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncLambdaClient {
+    /// Invokes a lambda with an optional payload and waits for its response
+    pub async fn invoke_sync<C, P>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<Option<Vec<u8>>>
+    where
+        C: Serialize + Send + 'static,
+        P: AsRef<[u8]> + Send + 'static,
+    {
+        let client = Arc::clone(&self.client);
+        self.pool
+            .run_with_timeout(self.timeout, move || client.invoke_sync(option, payload))
+            .await
+    }
+
+    /// Invokes a lambda with an optional payload without waiting for it to execute
+    pub async fn invoke_async<C, P>(
+        &self,
+        option: InvokeOptions<C>,
+        payload: Option<P>,
+    ) -> GGResult<()>
+    where
+        C: Serialize + Send + 'static,
+        P: AsRef<[u8]> + Send + 'static,
+    {
+        let client = Arc::clone(&self.client);
+        self.pool
+            .run_with_timeout(self.timeout, move || client.invoke_async(option, payload))
+            .await
+    }
+
+    /// Sends the response of a lambda that was invoked by another lambda
+    pub async fn send_response(&self, result: Result<&[u8], &str>) -> GGResult<()> {
+        let client = Arc::clone(&self.client);
+        let result = result.map(<[u8]>::to_vec).map_err(str::to_owned);
+        self.pool
+            .run_with_timeout(self.timeout, move || {
+                client.send_response(result.as_deref().map_err(String::as_str))
+            })
+            .await
+    }
+
+    /// Use the specified backend instead of the C SDK
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        AsyncLambdaClient {
+            client: Arc::new(LambdaClient::default().with_backend(backend)),
+            ..self
+        }
+    }
+
+    /// Run the calls on the specified pool instead of the shared one
+    pub fn with_pool(self, pool: BlockingPool) -> Self {
+        AsyncLambdaClient { pool, ..self }
+    }
+
+    /// Fail calls that have not completed within the timeout with an [`std::io::ErrorKind::TimedOut`] error.
+    /// No timeout is applied by default.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        AsyncLambdaClient { timeout, ..self }
+    }
 }
 
 /// Provides mock testing utilities
@@ -389,7 +652,7 @@ pub mod mock {
     unsafe impl Sync for MockHolder {}
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "mock")))]
 mod test {
     use super::*;
     use serde::Deserialize;
@@ -405,7 +668,6 @@ mod test {
     }
 
     //noinspection DuplicatedCode
-    #[cfg(not(feature = "mock"))]
     #[test]
     fn test_invoke_async() {
         reset_test_state();
@@ -448,7 +710,6 @@ mod test {
     }
 
     //noinspection DuplicatedCode
-    #[cfg(not(feature = "mock"))]
     #[test]
     fn test_invoke_sync() {
         reset_test_state();
@@ -503,7 +764,62 @@ mod test {
     }
 
     #[test]
-    #[cfg(not(feature = "mock"))]
+    fn test_invoke_sync_reader() {
+        use std::io::Read;
+
+        reset_test_state();
+        let response = b"A streamed response".repeat(1000);
+        GG_REQUEST_READ_BUFFER.with(|rc| rc.replace(response.clone()));
+        let options = InvokeOptions::new("arn".to_owned(), (), "1".to_owned());
+
+        let client = LambdaClient::default();
+        let mut reader = client
+            .invoke_sync_reader(options, Some(b"payload"))
+            .unwrap()
+            .unwrap();
+        let mut result = vec![];
+        reader.read_to_end(&mut result).unwrap();
+        assert_eq!(result, response);
+        GG_INVOKE_ARGS.with(|rc| {
+            let args = rc.borrow();
+            assert_eq!(args.payload, b"payload");
+            assert_eq!(args.invoke_type, InvokeType::InvokeRequestResponse);
+        });
+        drop(reader);
+        GG_CLOSE_REQUEST_COUNT.with(|rc| assert_eq!(*rc.borrow(), 1));
+    }
+
+    #[test]
+    fn test_invoke_sync_with_codec() {
+        use crate::codec::JsonCodec;
+
+        reset_test_state();
+        let response = TestPayload {
+            msg: "Decoded response".to_owned(),
+        };
+        GG_REQUEST_READ_BUFFER.with(|rc| rc.replace(serde_json::to_vec(&response).unwrap()));
+        let payload = TestPayload {
+            msg: "Encoded payload".to_owned(),
+        };
+        let options = InvokeOptions::new("arn".to_owned(), (), "1".to_owned());
+
+        let result: Option<TestPayload> = LambdaClient::default()
+            .invoke_sync_with_codec(options, Some(&payload), &JsonCodec)
+            .unwrap();
+        assert_eq!(result, Some(response));
+        GG_INVOKE_ARGS.with(|rc| {
+            assert_eq!(rc.borrow().payload, serde_json::to_vec(&payload).unwrap());
+        });
+
+        LambdaClient::default()
+            .send_response_with_codec(Ok(&payload), &JsonCodec)
+            .unwrap();
+        GG_LAMBDA_HANDLER_WRITE_RESPONSE.with(|rc| {
+            assert_eq!(*rc.borrow(), serde_json::to_vec(&payload).unwrap());
+        });
+    }
+
+    #[test]
     fn test_send_response() {
         let my_succesful_response = b"response is here";
         LambdaClient::default()
@@ -515,7 +831,6 @@ mod test {
     }
 
     #[test]
-    #[cfg(not(feature = "mock"))]
     fn test_send_err_response() {
         let my_err_response = "error response is here";
         LambdaClient::default()
@@ -36,15 +36,33 @@
 #![allow(unused_unsafe)] // because the test bindings will complain otherwise
 
 mod bindings;
+pub mod backend;
+pub mod batch;
+#[cfg(feature = "async")]
+pub mod blocking;
+pub mod codec;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compression;
 pub mod error;
+mod ffi;
 pub mod handler;
 pub mod iotdata;
+#[cfg(all(feature = "ipc", any(test, not(feature = "coverage"))))]
+mod ipc;
 pub mod lambda;
 pub mod log;
+pub mod middleware;
+pub mod outbox;
 pub mod request;
+pub mod retry;
+pub mod router;
 pub mod runtime;
 pub mod secret;
 pub mod shadow;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(all(unix, feature = "v2"))]
+pub mod v2;
 
 use crate::bindings::gg_global_init;
 use crate::error::GGError;
@@ -59,6 +77,10 @@ pub struct Initializer {
 }
 
 impl Initializer {
+    /// Initializes the Greengrass SDK and starts the runtime.
+    ///
+    /// A [`runtime::RuntimeOption::Sync`] runtime that can be shutdown, see [`Runtime::with_shutdown_handle`],
+    /// returns once the shutdown has completed.
     pub fn init(self) -> GGResult<()> {
         unsafe {
             // At this time there are no options for gg_global_init
@@ -125,6 +147,12 @@ pub mod test {
         calls: RefCell<Vec<T>>,
     }
 
+    impl<T> Default for CallHolder<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl<T> CallHolder<T> {
         pub fn new() -> Self {
             CallHolder {
@@ -138,7 +166,7 @@ pub mod test {
         }
 
         /// Return all the calls made
-        pub fn calls(&self) -> Ref<Vec<T>> {
+        pub fn calls(&self) -> Ref<'_, Vec<T>> {
             self.calls.borrow()
         }
     }
@@ -7,18 +7,14 @@
  */
 
 //! Provide a log crate log implementation that delegates to the the Greengrass logging infrastructure
-use crate::bindings::*;
-use lazy_static::lazy_static;
-use log::{self, Level, LevelFilter, Log, Metadata, Record};
-use std::ffi::CString;
-
-lazy_static! {
-    static ref LOGGER: GGLogger = GGLogger;
-}
+use crate::backend::{default_backend, Backend};
+use log::{self, LevelFilter, Log, Metadata, Record};
+use std::sync::Arc;
 
 /// A logger implementation that wraps the greengrass logging backend
-#[derive(Default)]
-struct GGLogger;
+struct GGLogger {
+    backend: Arc<dyn Backend>,
+}
 
 impl Log for GGLogger {
     fn enabled(&self, _: &Metadata) -> bool {
@@ -27,7 +23,9 @@ impl Log for GGLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            to_gg_log(record)
+            let formatted = format!("{} -- {}", record.target(), record.args());
+            // There is nowhere left to report a failure to log
+            let _ = self.backend.log(record.level(), &formatted);
         }
     }
 
@@ -44,37 +42,21 @@ impl Log for GGLogger {
 /// gglog::init_log(Level::Debug);
 /// ```
 pub fn init_log(max_level: LevelFilter) {
-    log::set_max_level(max_level);
-    log::set_logger(&*LOGGER).expect("GGLogger implementation could not be set as logger");
-}
-
-/// Converts a [`log::Record`] to a c log entry and sends it to gg_log
-fn to_gg_log(record: &Record) {
-    let formatted = format!("{} -- {}", record.target(), record.args());
-    let bytes = formatted.into_bytes();
-
-    let c_to_print = CString::new(bytes.as_slice()).expect("CString: new failed");
-    let level = to_gg_log_level(record.level());
-    unsafe {
-        gg_log(level, c_to_print.as_ptr());
-    }
+    init_log_with_backend(max_level, default_backend());
 }
 
-/// Coerces a [`log::Level`] into a green grass log level
-fn to_gg_log_level(l: Level) -> gg_log_level {
-    match l {
-        Level::Info => gg_log_level_GG_LOG_INFO,
-        Level::Warn => gg_log_level_GG_LOG_WARN,
-        Level::Error => gg_log_level_GG_LOG_ERROR,
-        _ => gg_log_level_GG_LOG_DEBUG,
-    }
+/// Initializes the Greengrass Logger with the specified run level, logging to the specified backend
+pub fn init_log_with_backend(max_level: LevelFilter, backend: Arc<dyn Backend>) {
+    log::set_max_level(max_level);
+    let logger: &'static GGLogger = Box::leak(Box::new(GGLogger { backend }));
+    log::set_logger(logger).expect("GGLogger implementation could not be set as logger");
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "mock")))]
 mod test {
     use super::*;
+    use crate::bindings::*;
 
-    #[cfg(not(feature = "mock"))]
     #[test]
     fn test_log() {
         init_log(LevelFilter::Trace);
@@ -0,0 +1,305 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides middleware that wraps a [`Handler`] with cross-cutting logic such as logging, timing and payload limits.
+//!
+//! Middleware is composed into a [`Stack`]. The first layer added is the outermost and sees each event first.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::handler::{Handler, LambdaContext};
+//! use aws_greengrass_core_rust::middleware::{
+//!     LoggingMiddleware, PayloadSizeMiddleware, Stack, TimingMiddleware,
+//! };
+//! use aws_greengrass_core_rust::runtime::Runtime;
+//!
+//! struct MyHandler;
+//!
+//! impl Handler for MyHandler {
+//!     fn handle(&self, ctx: LambdaContext) {
+//!         println!("Received an event! {:?}", ctx);
+//!     }
+//! }
+//!
+//! let handler = Stack::default()
+//!     .layer(LoggingMiddleware)
+//!     .layer(TimingMiddleware::default())
+//!     .layer(PayloadSizeMiddleware::new(64 * 1024))
+//!     .service(Box::new(MyHandler));
+//!
+//! Runtime::default().with_handler(Some(handler));
+//! ```
+use crate::handler::{Handler, LambdaContext};
+use crate::runtime::{self, ShareableHandler};
+use log::{error, info};
+use std::time::{Duration, Instant};
+
+/// Denotes a middleware that is thread safe
+pub type ShareableMiddleware = dyn Middleware + Send + Sync;
+
+/// Trait to implement for logic that wraps a [`Handler`].
+pub trait Middleware {
+    /// Handle the event, passing it on to `next` to continue the chain.
+    /// Returning an error without calling `next` short-circuits the chain and the error is
+    /// written back to the caller as the lambda error response.
+    fn handle(&self, ctx: LambdaContext, next: &dyn Handler) -> Result<(), String>;
+}
+
+/// Builds a [`Handler`] wrapped by a chain of [`Middleware`]
+#[derive(Default)]
+pub struct Stack {
+    layers: Vec<Box<ShareableMiddleware>>,
+}
+
+impl Stack {
+    /// Adds a middleware inside of the layers previously added
+    pub fn layer<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware + Send + Sync + 'static,
+    {
+        self.layers.push(Box::new(middleware));
+        self
+    }
+
+    /// Wraps the handler with the layers of the stack
+    pub fn service(self, handler: Box<ShareableHandler>) -> Box<ShareableHandler> {
+        Box::new(Layered {
+            layers: self.layers,
+            handler,
+        })
+    }
+}
+
+/// A handler wrapped by middleware, created by [`Stack::service`]
+struct Layered {
+    layers: Vec<Box<ShareableMiddleware>>,
+    handler: Box<ShareableHandler>,
+}
+
+impl Handler for Layered {
+    fn handle(&self, ctx: LambdaContext) {
+        Next {
+            layers: &self.layers,
+            handler: self.handler.as_ref(),
+        }
+        .handle(ctx)
+    }
+}
+
+/// The remainder of the chain that is passed to a middleware
+struct Next<'a> {
+    layers: &'a [Box<ShareableMiddleware>],
+    handler: &'a ShareableHandler,
+}
+
+impl<'a> Handler for Next<'a> {
+    fn handle(&self, ctx: LambdaContext) {
+        match self.layers.split_first() {
+            Some((middleware, layers)) => {
+                let next = Next {
+                    layers,
+                    handler: self.handler,
+                };
+                if let Err(msg) = middleware.handle(ctx, &next) {
+                    error!("Middleware short-circuited event: {}", msg);
+                    if let Err(e) = runtime::send_response(Err(&msg)) {
+                        error!("Error writing error response: {}", e);
+                    }
+                }
+            }
+            None => self.handler.handle(ctx),
+        }
+    }
+}
+
+/// Logs each event received and when its handling has completed
+pub struct LoggingMiddleware;
+
+impl Middleware for LoggingMiddleware {
+    fn handle(&self, ctx: LambdaContext, next: &dyn Handler) -> Result<(), String> {
+        let function_arn = ctx.function_arn.clone();
+        info!(
+            "Handling event for {} with subject {:?} and {} byte payload",
+            function_arn,
+            ctx.subject(),
+            ctx.message.len()
+        );
+        next.handle(ctx);
+        info!("Finished handling event for {}", function_arn);
+        Ok(())
+    }
+}
+
+/// Function that receives how long an event took to handle
+pub type TimingObserver = dyn Fn(&str, Duration) + Send + Sync;
+
+/// Logs how long each event took to handle. An observer can be provided to record the durations as metrics.
+#[derive(Default)]
+pub struct TimingMiddleware {
+    observer: Option<Box<TimingObserver>>,
+}
+
+impl TimingMiddleware {
+    /// Provide a function called with the function arn and duration after each event is handled
+    pub fn with_observer(self, observer: Option<Box<TimingObserver>>) -> Self {
+        TimingMiddleware { observer }
+    }
+}
+
+impl Middleware for TimingMiddleware {
+    fn handle(&self, ctx: LambdaContext, next: &dyn Handler) -> Result<(), String> {
+        let function_arn = ctx.function_arn.clone();
+        let start = Instant::now();
+        next.handle(ctx);
+        let elapsed = start.elapsed();
+        info!("Handled event for {} in {:?}", function_arn, elapsed);
+        if let Some(observer) = &self.observer {
+            observer(&function_arn, elapsed);
+        }
+        Ok(())
+    }
+}
+
+/// Rejects events with a payload larger than the specified number of bytes
+pub struct PayloadSizeMiddleware {
+    max_bytes: usize,
+}
+
+impl PayloadSizeMiddleware {
+    pub fn new(max_bytes: usize) -> Self {
+        PayloadSizeMiddleware { max_bytes }
+    }
+}
+
+impl Middleware for PayloadSizeMiddleware {
+    fn handle(&self, ctx: LambdaContext, next: &dyn Handler) -> Result<(), String> {
+        if ctx.message.len() > self.max_bytes {
+            return Err(format!(
+                "Payload of {} bytes exceeds the limit of {} bytes",
+                ctx.message.len(),
+                self.max_bytes
+            ));
+        }
+        next.handle(ctx);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    struct RecordingMiddleware {
+        name: &'static str,
+        calls: Calls,
+    }
+
+    impl Middleware for RecordingMiddleware {
+        fn handle(&self, ctx: LambdaContext, next: &dyn Handler) -> Result<(), String> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} before", self.name));
+            next.handle(ctx);
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} after", self.name));
+            Ok(())
+        }
+    }
+
+    struct RecordingHandler {
+        calls: Calls,
+    }
+
+    impl Handler for RecordingHandler {
+        fn handle(&self, _: LambdaContext) {
+            self.calls.lock().unwrap().push("handler".to_owned());
+        }
+    }
+
+    fn context(message: &[u8]) -> LambdaContext {
+        LambdaContext::new("my_func_arn".to_owned(), "".to_owned(), message.to_vec())
+    }
+
+    #[test]
+    fn test_layer_order() {
+        let calls = Calls::default();
+        let handler = Stack::default()
+            .layer(RecordingMiddleware {
+                name: "outer",
+                calls: Arc::clone(&calls),
+            })
+            .layer(RecordingMiddleware {
+                name: "inner",
+                calls: Arc::clone(&calls),
+            })
+            .service(Box::new(RecordingHandler {
+                calls: Arc::clone(&calls),
+            }));
+        handler.handle(context(b"hello"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "outer before",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timing_observer() {
+        let observed = Arc::new(Mutex::new(vec![]));
+        let observer_calls = Arc::clone(&observed);
+        let handler = Stack::default()
+            .layer(TimingMiddleware::default().with_observer(Some(Box::new(
+                move |arn: &str, _: Duration| observer_calls.lock().unwrap().push(arn.to_owned()),
+            ))))
+            .service(Box::new(RecordingHandler {
+                calls: Calls::default(),
+            }));
+        handler.handle(context(b"hello"));
+        assert_eq!(*observed.lock().unwrap(), vec!["my_func_arn"]);
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_payload_size_short_circuits() {
+        use crate::bindings::*;
+
+        let _lock = crate::runtime::test::runtime_lock();
+        reset_test_state();
+        let calls = Calls::default();
+        let handler = Stack::default()
+            .layer(LoggingMiddleware)
+            .layer(PayloadSizeMiddleware::new(5))
+            .service(Box::new(RecordingHandler {
+                calls: Arc::clone(&calls),
+            }));
+
+        handler.handle(context(b"small"));
+        assert_eq!(*calls.lock().unwrap(), vec!["handler"]);
+
+        handler.handle(context(b"too large"));
+        assert_eq!(*calls.lock().unwrap(), vec!["handler"]);
+        GG_LAMBDA_HANDLER_WRITE_ERROR.with(|rc| {
+            assert_eq!(
+                *rc.borrow(),
+                "Payload of 9 bytes exceeds the limit of 5 bytes"
+            )
+        });
+    }
+}
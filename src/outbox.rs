@@ -0,0 +1,649 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides [`OutboxPublisher`], which stores messages on disk before publishing them
+//! so that they aren't lost while the core can't accept them, e.g. when its queue is full or it is restarting.
+//!
+//! Messages are appended to `outbox.log` in the outbox directory and published in order by a background thread,
+//! which backs off while publishes fail. The last message that was published or dropped is recorded in `outbox.ack`,
+//! so the messages that were still waiting when the lambda stopped are published once it is started again.
+//! Messages are published at least once: a message published right before the lambda stopped may be published again.
+//!
+//! ```rust,no_run
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//! use aws_greengrass_core_rust::outbox::{OutboxOptions, OutboxPublisher};
+//! use std::time::Duration;
+//!
+//! let options = OutboxOptions::default().with_ttl(Some(Duration::from_secs(3600)));
+//! let outbox =
+//!     OutboxPublisher::open("/var/lib/my_lambda/outbox", IOTDataClient::default(), options).unwrap();
+//! outbox.publish("sensors/temperature", "21.5").unwrap();
+//! println!("{} messages waiting", outbox.stats().depth);
+//! ```
+use crate::error::GGError;
+use crate::iotdata::IotData;
+use crate::GGResult;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const LOG_FILE: &str = "outbox.log";
+const ACK_FILE: &str = "outbox.ack";
+
+/// Published messages are removed from the log once there are this many of them, and more than are waiting
+const COMPACT_THRESHOLD: usize = 1000;
+
+/// Configures an [`OutboxPublisher`]
+#[derive(Clone, Debug)]
+pub struct OutboxOptions {
+    /// The most bytes of messages to keep. The oldest messages are dropped to make room for new ones.
+    pub max_bytes: usize,
+    /// How long messages are kept before they are dropped without being published, forever if None
+    pub ttl: Option<Duration>,
+    /// The delay before retrying a failed publish, doubled after each failure
+    pub base_delay: Duration,
+    /// The longest delay between retries
+    pub max_delay: Duration,
+}
+
+impl Default for OutboxOptions {
+    /// Keeps up to 10 MiB of messages forever, retrying every 100ms up to every 30s
+    fn default() -> Self {
+        OutboxOptions {
+            max_bytes: 10 * 1024 * 1024,
+            ttl: None,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl OutboxOptions {
+    /// The most bytes of messages to keep
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        OutboxOptions { max_bytes, ..self }
+    }
+
+    /// How long messages are kept before they are dropped, forever if None
+    pub fn with_ttl(self, ttl: Option<Duration>) -> Self {
+        OutboxOptions { ttl, ..self }
+    }
+
+    /// The delay before retrying a failed publish
+    pub fn with_base_delay(self, base_delay: Duration) -> Self {
+        OutboxOptions { base_delay, ..self }
+    }
+
+    /// The longest delay between retries
+    pub fn with_max_delay(self, max_delay: Duration) -> Self {
+        OutboxOptions { max_delay, ..self }
+    }
+}
+
+/// The state of an outbox, returned by [`OutboxPublisher::stats`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutboxStats {
+    /// The number of messages waiting to be published
+    pub depth: usize,
+    /// The size of the topics and payloads of the messages waiting to be published
+    pub bytes: usize,
+    /// The number of messages published since the outbox was opened
+    pub published: u64,
+    /// The number of messages dropped because their TTL passed
+    pub expired: u64,
+    /// The number of messages dropped to stay within [`OutboxOptions::max_bytes`]
+    pub overflowed: u64,
+}
+
+impl OutboxStats {
+    /// The number of messages dropped without being published
+    pub fn dropped(&self) -> u64 {
+        self.expired + self.overflowed
+    }
+}
+
+/// A message as stored in the log, one JSON object per line
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Record {
+    seq: u64,
+    topic: String,
+    #[serde(with = "base64_payload")]
+    payload: Vec<u8>,
+    /// Milliseconds since the unix epoch, so that it holds across reboots
+    expires_at: Option<u64>,
+}
+
+impl Record {
+    fn size(&self) -> usize {
+        self.topic.len() + self.payload.len()
+    }
+}
+
+struct State {
+    pending: VecDeque<Record>,
+    next_seq: u64,
+    /// The last message that was published or dropped
+    acked: u64,
+    log: File,
+    /// The number of messages in the log that have been published or dropped
+    stale: usize,
+    stats: OutboxStats,
+    shutdown: bool,
+}
+
+/// The state shared with the thread draining the outbox
+struct Outbox {
+    dir: PathBuf,
+    options: OutboxOptions,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Publishes messages through a durable on-disk queue.
+/// See the [module documentation](self) for how messages are stored and published.
+///
+/// Dropping the publisher stops the background thread, once the publish in progress has completed.
+/// The messages that are still waiting are kept on disk.
+pub struct OutboxPublisher {
+    outbox: Arc<Outbox>,
+    drainer: Option<JoinHandle<()>>,
+}
+
+impl OutboxPublisher {
+    /// Opens the outbox in the directory, creating it if needed, and starts publishing the messages
+    /// waiting in it with the client.
+    pub fn open<P, C>(dir: P, client: C, options: OutboxOptions) -> GGResult<Self>
+    where
+        P: AsRef<Path>,
+        C: IotData + Send + 'static,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| storage_error("create the outbox directory", e))?;
+        let mut acked = read_ack(&dir)?;
+        let mut pending = read_log(&dir.join(LOG_FILE), acked)?;
+        let next_seq = pending.back().map_or(acked, |r| r.seq).max(acked) + 1;
+
+        let mut stats = OutboxStats::default();
+        let mut bytes: usize = pending.iter().map(Record::size).sum();
+        while bytes > options.max_bytes {
+            if let Some(dropped) = pending.pop_front() {
+                bytes -= dropped.size();
+                acked = dropped.seq;
+                stats.overflowed += 1;
+            }
+        }
+        write_ack(&dir, acked)?;
+        let log = rewrite_log(&dir, &pending)?;
+        stats.depth = pending.len();
+        stats.bytes = bytes;
+
+        let outbox = Arc::new(Outbox {
+            dir,
+            options,
+            state: Mutex::new(State {
+                pending,
+                next_seq,
+                acked,
+                log,
+                stale: 0,
+                stats,
+                shutdown: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let drainer = {
+            let outbox = Arc::clone(&outbox);
+            thread::Builder::new()
+                .name("gg-outbox".to_owned())
+                .spawn(move || outbox.drain(client))
+                .map_err(|e| GGError::Unknown(format!("Could not spawn outbox thread: {}", e)))?
+        };
+        Ok(OutboxPublisher {
+            outbox,
+            drainer: Some(drainer),
+        })
+    }
+
+    /// Stores the message to be published, with the TTL of the options
+    pub fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        self.publish_with_ttl(topic, message, self.outbox.options.ttl)
+    }
+
+    /// Stores the message to be published, dropping it if it hasn't been published within the TTL
+    pub fn publish_with_ttl<T: AsRef<[u8]>>(
+        &self,
+        topic: &str,
+        message: T,
+        ttl: Option<Duration>,
+    ) -> GGResult<()> {
+        let expires_at = ttl.map(|ttl| now_millis().saturating_add(ttl.as_millis() as u64));
+        self.outbox
+            .append(topic, message.as_ref().to_vec(), expires_at)
+    }
+
+    /// Stores anything that is a serializable serde object as JSON to be published
+    pub fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let bytes = serde_json::to_vec(&message).map_err(GGError::from)?;
+        self.publish(topic, bytes)
+    }
+
+    /// The number of messages waiting and the counts of published and dropped messages
+    pub fn stats(&self) -> OutboxStats {
+        self.outbox.lock().stats.clone()
+    }
+
+    /// Waits until every message has been published or dropped.
+    /// Returns false if messages are still waiting after the timeout.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.outbox.lock();
+        while !state.pending.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .outbox
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+}
+
+impl Drop for OutboxPublisher {
+    fn drop(&mut self) {
+        self.outbox.lock().shutdown = true;
+        self.outbox.changed.notify_all();
+        if let Some(drainer) = self.drainer.take() {
+            if drainer.join().is_err() {
+                error!("The outbox thread panicked");
+            }
+        }
+    }
+}
+
+impl IotData for OutboxPublisher {
+    fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        OutboxPublisher::publish(self, topic, message)
+    }
+}
+
+impl Outbox {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn append(&self, topic: &str, payload: Vec<u8>, expires_at: Option<u64>) -> GGResult<()> {
+        let mut state = self.lock();
+        let record = Record {
+            seq: state.next_seq,
+            topic: topic.to_owned(),
+            payload,
+            expires_at,
+        };
+        if record.size() > self.options.max_bytes {
+            return Err(GGError::InvalidParameter);
+        }
+        let mut line = serde_json::to_vec(&record).map_err(GGError::from)?;
+        line.push(b'\n');
+        state
+            .log
+            .write_all(&line)
+            .and_then(|_| state.log.sync_data())
+            .map_err(|e| storage_error("write to the outbox", e))?;
+        state.next_seq += 1;
+
+        while state.stats.bytes + record.size() > self.options.max_bytes {
+            match state.pending.pop_front() {
+                Some(dropped) => {
+                    warn!(
+                        "Dropping message {} to {} as the outbox is full",
+                        dropped.seq, dropped.topic
+                    );
+                    state.stats.overflowed += 1;
+                    self.remove(&mut state, &dropped);
+                }
+                None => break,
+            }
+        }
+        state.stats.depth += 1;
+        state.stats.bytes += record.size();
+        state.pending.push_back(record);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Publishes the messages in order until the outbox is shutdown
+    fn drain<C: IotData>(&self, client: C) {
+        let mut delay: Option<Duration> = None;
+        while let Some(record) = self.next() {
+            match client.publish(&record.topic, &record.payload) {
+                Ok(_) => {
+                    let mut state = self.lock();
+                    if state.pending.front().map(|r| r.seq) == Some(record.seq) {
+                        state.pending.pop_front();
+                    }
+                    state.stats.published += 1;
+                    self.remove(&mut state, &record);
+                    self.changed.notify_all();
+                    delay = None;
+                }
+                Err(e) => {
+                    let next = delay.map_or(self.options.base_delay, |d| {
+                        (d * 2).min(self.options.max_delay)
+                    });
+                    warn!(
+                        "Could not publish message {} to {}, retrying in {:?}: {}",
+                        record.seq, record.topic, next, e
+                    );
+                    delay = Some(next);
+                    if !self.wait(next) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The oldest message that hasn't expired, waiting for one if there is none.
+    /// None once the outbox is shutdown.
+    fn next(&self) -> Option<Record> {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return None;
+            }
+            let now = now_millis();
+            while let Some(expired) = state
+                .pending
+                .front()
+                .filter(|r| r.expires_at.is_some_and(|at| at <= now))
+                .cloned()
+            {
+                warn!(
+                    "Dropping message {} to {} as its ttl passed",
+                    expired.seq, expired.topic
+                );
+                state.pending.pop_front();
+                state.stats.expired += 1;
+                self.remove(&mut state, &expired);
+                self.changed.notify_all();
+            }
+            if let Some(record) = state.pending.front() {
+                return Some(record.clone());
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Waits for the delay. Returns false if the outbox was shutdown in the meantime.
+    fn wait(&self, delay: Duration) -> bool {
+        let deadline = Instant::now() + delay;
+        let mut state = self.lock();
+        while !state.shutdown {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        false
+    }
+
+    /// Records that the message, which was removed from pending, has been published or dropped
+    fn remove(&self, state: &mut State, record: &Record) {
+        state.stats.depth = state.pending.len();
+        state.stats.bytes = state.pending.iter().map(Record::size).sum();
+        if record.seq <= state.acked {
+            return;
+        }
+        state.acked = record.seq;
+        state.stale += 1;
+        if let Err(e) = write_ack(&self.dir, record.seq) {
+            error!("{}", e);
+        }
+
+        let compacted = if state.pending.is_empty() {
+            state
+                .log
+                .set_len(0)
+                .map_err(|e| storage_error("truncate the outbox", e))
+        } else if state.stale >= COMPACT_THRESHOLD && state.stale > state.pending.len() {
+            rewrite_log(&self.dir, &state.pending).map(|log| state.log = log)
+        } else {
+            return;
+        };
+        match compacted {
+            Ok(_) => state.stale = 0,
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+fn storage_error(action: &str, e: io::Error) -> GGError {
+    GGError::Unknown(format!("Could not {}: {}", action, e))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn read_ack(dir: &Path) -> GGResult<u64> {
+    match fs::read_to_string(dir.join(ACK_FILE)) {
+        Ok(ack) => ack.trim().parse().map_err(|_| {
+            GGError::Unknown(format!("Invalid outbox acknowledgement: {}", ack.trim()))
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(storage_error("read the outbox acknowledgement", e)),
+    }
+}
+
+/// Writes to a temporary file first so that the acknowledgement is never partially written
+fn write_ack(dir: &Path, seq: u64) -> GGResult<()> {
+    let temp = dir.join(format!("{}.tmp", ACK_FILE));
+    fs::write(&temp, seq.to_string())
+        .and_then(|_| fs::rename(&temp, dir.join(ACK_FILE)))
+        .map_err(|e| storage_error("write the outbox acknowledgement", e))
+}
+
+/// Reads the messages after the acknowledged one.
+/// Lines that can't be parsed, e.g. the last one if the lambda stopped while writing it, are skipped.
+fn read_log(path: &Path, acked: u64) -> GGResult<VecDeque<Record>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(VecDeque::new()),
+        Err(e) => return Err(storage_error("read the outbox", e)),
+    };
+    let mut records = VecDeque::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| storage_error("read the outbox", e))?;
+        match serde_json::from_str::<Record>(&line) {
+            Ok(record) if record.seq > acked => records.push_back(record),
+            Ok(_) => (),
+            Err(e) => warn!("Skipping invalid outbox record: {}", e),
+        }
+    }
+    Ok(records)
+}
+
+/// Replaces the log with the pending messages, returning the new log to append to
+fn rewrite_log(dir: &Path, pending: &VecDeque<Record>) -> GGResult<File> {
+    let path = dir.join(LOG_FILE);
+    let temp = dir.join(format!("{}.tmp", LOG_FILE));
+    let write = || -> io::Result<File> {
+        let mut file = File::create(&temp)?;
+        for record in pending {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_data()?;
+        fs::rename(&temp, &path)?;
+        OpenOptions::new().append(true).open(&path)
+    };
+    write().map_err(|e| storage_error("write the outbox", e))
+}
+
+/// Stores payloads as base64 strings
+mod base64_payload {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(payload: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(payload))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(&encoded).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Records publishes, failing while failures are left
+    #[derive(Clone, Default)]
+    struct FakeClient {
+        published: Arc<Mutex<Vec<Vec<u8>>>>,
+        failures: Arc<AtomicUsize>,
+    }
+
+    impl FakeClient {
+        fn failing(failures: usize) -> Self {
+            let client = FakeClient::default();
+            client.failures.store(failures, Ordering::SeqCst);
+            client
+        }
+
+        fn payloads(&self) -> Vec<Vec<u8>> {
+            self.published.lock().unwrap().clone()
+        }
+    }
+
+    impl IotData for FakeClient {
+        fn publish<T: AsRef<[u8]>>(&self, _topic: &str, message: T) -> GGResult<()> {
+            let failed = self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            if failed.is_ok() {
+                return Err(GGError::InvalidState);
+            }
+            self.published
+                .lock()
+                .unwrap()
+                .push(message.as_ref().to_vec());
+            Ok(())
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("gg-outbox-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn options() -> OutboxOptions {
+        OutboxOptions::default().with_base_delay(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn test_publishes_in_order_with_backoff() {
+        let dir = temp_dir();
+        let client = FakeClient::failing(3);
+        let outbox = OutboxPublisher::open(&dir, client.clone(), options()).unwrap();
+        for i in 0..5 {
+            outbox.publish("telemetry", format!("{}", i)).unwrap();
+        }
+        assert!(outbox.flush(Duration::from_secs(5)));
+        let expected: Vec<Vec<u8>> = (0..5).map(|i| format!("{}", i).into_bytes()).collect();
+        assert_eq!(client.payloads(), expected);
+        let stats = outbox.stats();
+        assert_eq!(stats.published, 5);
+        assert_eq!(stats.depth, 0);
+        assert_eq!(stats.bytes, 0);
+        assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_survives_restart() {
+        let dir = temp_dir();
+        let offline = FakeClient::failing(usize::MAX);
+        let outbox = OutboxPublisher::open(&dir, offline.clone(), options()).unwrap();
+        outbox.publish("telemetry", "first").unwrap();
+        outbox.publish_json("telemetry", 2).unwrap();
+        assert_eq!(outbox.stats().depth, 2);
+        drop(outbox);
+        assert!(offline.payloads().is_empty());
+
+        let online = FakeClient::default();
+        let outbox = OutboxPublisher::open(&dir, online.clone(), options()).unwrap();
+        assert!(outbox.flush(Duration::from_secs(5)));
+        assert_eq!(online.payloads(), vec![b"first".to_vec(), b"2".to_vec()]);
+        drop(outbox);
+
+        // published messages aren't published again
+        let restarted = FakeClient::default();
+        let outbox = OutboxPublisher::open(&dir, restarted.clone(), options()).unwrap();
+        outbox.publish("telemetry", "third").unwrap();
+        assert!(outbox.flush(Duration::from_secs(5)));
+        assert_eq!(restarted.payloads(), vec![b"third".to_vec()]);
+        drop(outbox);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_drops_expired_and_overflowing_messages() {
+        let dir = temp_dir();
+        let offline = FakeClient::failing(usize::MAX);
+        let options = options().with_max_bytes(20);
+        let outbox = OutboxPublisher::open(&dir, offline, options.clone()).unwrap();
+        // each message is 10 bytes
+        for payload in &["11111", "22222", "33333"] {
+            outbox.publish("topic", payload).unwrap();
+        }
+        let result = outbox.publish("topic", [0u8; 20]);
+        assert!(matches!(result, Err(GGError::InvalidParameter)));
+        let stats = outbox.stats();
+        assert_eq!((stats.depth, stats.bytes, stats.overflowed), (2, 20, 1));
+        drop(outbox);
+
+        let online = FakeClient::default();
+        let options = options.with_max_bytes(30);
+        let outbox = OutboxPublisher::open(&dir, online.clone(), options).unwrap();
+        outbox
+            .publish_with_ttl("topic", "44444", Some(Duration::from_millis(0)))
+            .unwrap();
+        assert!(outbox.flush(Duration::from_secs(5)));
+        assert_eq!(
+            online.payloads(),
+            vec![b"22222".to_vec(), b"33333".to_vec()]
+        );
+        let stats = outbox.stats();
+        assert_eq!(stats.expired, 1);
+        assert_eq!(stats.dropped(), 1);
+        drop(outbox);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
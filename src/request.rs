@@ -22,25 +22,26 @@
 //!     Ok(_) => println!("Yay, it worked!"),
 //!     Err(GGError::ErrorResponse(resp)) => {
 //!         match resp.request_status {
-//!             GGRequestStatus::Again => eprintln!("You should retry again because you were throttled"),
+//!             GGRequestStatus::Again => eprintln!("You should retry again because you were throttled, see the retry module"),
 //!             _ => eprintln!("An error that is probably unrecoverable happened."),
 //!         }
 //!     }
 //!     _ => eprintln!("Another greengrass system error occurred"),
 //! }
 //! ```
+use crate::backend::{Backend, RequestHandle};
 use crate::bindings::*;
 use crate::error::GGError;
 use crate::GGResult;
 use log::{error, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::default::Default;
-use std::ffi::c_void;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
 
-/// The size of buffer we will use when reading results
-/// from the C API
-const BUFFER_SIZE: usize = 512;
+/// The default size of the chunks read from the C API
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
 
 /// Greengrass SDK request status enum
 /// Maps to gg_request_status
@@ -104,7 +105,7 @@ impl GGRequestResponse {
 
     /// Ok(()) if there is no error, otherwise the error we found
     /// This is useful for requests that do not contain a body
-    pub(crate) fn to_error_result(&self, req: gg_request) -> GGResult<()> {
+    pub(crate) fn to_error_result(&self, req: &Request) -> GGResult<()> {
         match self.determine_error(req) {
             ErrorState::Error(e) => Err(e),
             _ => Ok(()), // Ignore the NotFoundError too
@@ -114,10 +115,12 @@ impl GGRequestResponse {
     /// Attempt to read the response body.
     /// If the response is an error the error will be returned else the body in bytes.
     /// This is useful for requests that contain a body
-    pub(crate) fn read(&self, req: gg_request) -> GGResult<Option<Vec<u8>>> {
+    #[cfg(not(all(test, feature = "mock")))]
+    pub(crate) fn read(&self, req: &Request) -> GGResult<Option<Vec<u8>>> {
         match self.determine_error(req) {
             ErrorState::None => {
-                let data = read_response_data(req)?;
+                let mut data = Vec::new();
+                ResponseReader::new(req).read_to_end(&mut data)?;
                 Ok(Some(data))
             }
             ErrorState::NotFoundError => Ok(None),
@@ -125,9 +128,31 @@ impl GGRequestResponse {
         }
     }
 
+    /// Like [`GGRequestResponse::read`], but returns a reader that streams the body.
+    /// The request is closed when the reader is dropped.
+    #[cfg(not(all(test, feature = "mock")))]
+    pub(crate) fn reader<'a>(&self, req: Request<'a>) -> GGResult<Option<ResponseReader<'a>>> {
+        match self.determine_error(&req) {
+            ErrorState::None => Ok(Some(ResponseReader::from_request(req))),
+            ErrorState::NotFoundError => req.close().map(|_| None),
+            ErrorState::Error(e) => Err(e),
+        }
+    }
+
+    /// Like [`GGRequestResponse::read`], but deserializes the JSON body as it is read
+    pub(crate) fn read_json<T: DeserializeOwned>(&self, req: &Request) -> GGResult<Option<T>> {
+        match self.determine_error(req) {
+            ErrorState::None => {
+                serde_json::from_reader(ResponseReader::new(req)).map_err(GGError::from)
+            }
+            ErrorState::NotFoundError => Ok(None),
+            ErrorState::Error(e) => Err(e),
+        }
+    }
+
     /// If the response is an error, return it as Some(GGError)
     /// None if it isn't an error
-    fn determine_error(&self, req: gg_request) -> ErrorState {
+    fn determine_error(&self, req: &Request) -> ErrorState {
         // If we know there isn't an error, return
         if !self.is_error() {
             return ErrorState::None;
@@ -135,24 +160,29 @@ impl GGRequestResponse {
 
         // If this is an error than try to read the response body
         // So we can see what kind of error it is
-        let response_data = match read_response_data(req) {
+        let mut response_data = Vec::new();
+        let response_data = match ResponseReader::new(req).read_to_end(&mut response_data) {
+            // a throttled request failed even without an error response, so that it can be retried
+            Ok(0) if self.request_status == GGRequestStatus::Again => {
+                return ErrorState::Error(GGError::ErrorResponse(self.clone()));
+            }
             // if the error response is empty we could have an UNKNOWN response
             // which might not be an error at all.
             // This best we can do is log
-            Ok(data) if data.is_empty() => {
+            Ok(0) => {
                 warn!(
                     "Could not find an error response for non Success request of {:?}",
                     self.request_status
                 );
                 return ErrorState::None;
             }
-            Ok(data) => data,
+            Ok(_) => response_data,
             Err(e) => {
                 error!(
                     "An error occurred attempting to read error response data: {}",
                     e
                 );
-                return ErrorState::Error(e);
+                return ErrorState::Error(GGError::from(e));
             }
         };
 
@@ -191,6 +221,15 @@ impl Default for GGRequestResponse {
     }
 }
 
+impl From<GGRequestStatus> for GGRequestResponse {
+    fn from(request_status: GGRequestStatus) -> Self {
+        GGRequestResponse {
+            request_status,
+            error_response: None,
+        }
+    }
+}
+
 impl TryFrom<&gg_request_result> for GGRequestResponse {
     type Error = GGError;
 
@@ -226,55 +265,191 @@ impl TryFrom<&[u8]> for ErrorResponse {
     }
 }
 
-/// Reads the response data from the gg_request_reqd call
-fn read_response_data(req_to_read: gg_request) -> Result<Vec<u8>, GGError> {
-    let mut bytes: Vec<u8> = Vec::new();
-
-    unsafe {
-        loop {
-            let mut buffer = [0u8; BUFFER_SIZE];
-            let mut read: usize = 0;
-            let raw_read = &mut read as *mut usize;
-
-            let read_res = gg_request_read(
-                req_to_read,
-                buffer.as_mut_ptr() as *mut c_void,
-                BUFFER_SIZE,
-                raw_read,
-            );
-            GGError::from_code(read_res)?;
-
-            if read > 0 {
-                bytes.extend_from_slice(&buffer[..read]);
-            } else {
-                break;
+/// A request created with a backend that is closed when it is dropped.
+/// [`Request::close`] closes it explicitly so that an error closing it can be handled.
+pub(crate) struct Request<'a> {
+    backend: &'a dyn Backend,
+    handle: RequestHandle,
+    closed: bool,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(backend: &'a dyn Backend) -> GGResult<Self> {
+        let handle = backend.request_init()?;
+        Ok(Request {
+            backend,
+            handle,
+            closed: false,
+        })
+    }
+
+    /// The handle to pass to the backend for this request.
+    /// It is open until the request is closed or dropped.
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn handle(&self) -> RequestHandle {
+        self.handle
+    }
+
+    /// Reads the next chunk of the response body into the buffer, returning the number of bytes read
+    pub fn read(&self, buffer: &mut [u8]) -> GGResult<usize> {
+        // The request was created by the backend and is closed only once it is consumed
+        unsafe { self.backend.request_read(self.handle, buffer) }
+    }
+
+    pub fn close(mut self) -> GGResult<()> {
+        self.closed = true;
+        unsafe { self.backend.request_close(self.handle) }
+    }
+}
+
+impl Drop for Request<'_> {
+    fn drop(&mut self) {
+        if !self.closed {
+            if let Err(e) = unsafe { self.backend.request_close(self.handle) } {
+                error!("Could not close request: {}", e);
             }
         }
     }
+}
+
+/// Streams the response body of a request.
+///
+/// Returned by [`crate::shadow::ShadowClient::get_thing_shadow_reader`] and
+/// [`crate::lambda::LambdaClient::invoke_sync_reader`], so that large responses can be processed
+/// without buffering them. The request is closed when the reader is dropped.
+///
+/// The body is read from the backend in chunks of [`DEFAULT_CHUNK_SIZE`] bytes unless another size is set.
+/// [`Read::read_to_end`] reads the chunks into the vec without copying them through the buffer,
+/// reserving the size hint up front.
+pub struct ResponseReader<'a> {
+    inner: BufReader<RawResponse<'a>>,
+    size_hint: usize,
+}
+
+impl<'a> ResponseReader<'a> {
+    pub(crate) fn new(req: &'a Request<'a>) -> Self {
+        Self::with_raw(RawResponse::Borrowed(req))
+    }
+
+    /// Creates a reader that owns the request, closing it when dropped
+    #[cfg(not(all(test, feature = "mock")))]
+    pub(crate) fn from_request(req: Request<'a>) -> Self {
+        Self::with_raw(RawResponse::Owned(req))
+    }
+
+    fn with_raw(raw: RawResponse<'a>) -> Self {
+        ResponseReader {
+            inner: BufReader::with_capacity(DEFAULT_CHUNK_SIZE, raw),
+            size_hint: 0,
+        }
+    }
 
-    Ok(bytes)
+    /// The number of bytes to read from the backend at a time.
+    /// Anything already buffered is discarded, so this should be set before reading.
+    pub fn with_chunk_size(self, chunk_size: usize) -> Self {
+        ResponseReader {
+            inner: BufReader::with_capacity(chunk_size.max(1), self.inner.into_inner()),
+            ..self
+        }
+    }
+
+    /// The expected size of the body, reserved when it is read to the end
+    pub fn with_size_hint(self, size_hint: usize) -> Self {
+        ResponseReader { size_hint, ..self }
+    }
 }
 
-#[macro_export]
-macro_rules! with_request {
-    ($req:expr, $expr:block) => {{
-        let req_init = gg_request_init(&mut $req);
-        GGError::from_code(req_init)?;
-        let wrapper = move || {
-            //this will catch the return
-            $expr
+impl Read for ResponseReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        read_chunks(&mut self.inner, buf, self.size_hint)
+    }
+}
+
+impl BufRead for ResponseReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Reads the response body from the backend without buffering
+enum RawResponse<'a> {
+    Borrowed(&'a Request<'a>),
+    #[cfg(not(all(test, feature = "mock")))]
+    Owned(Request<'a>),
+}
+
+impl Read for RawResponse<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let req = match self {
+            Self::Borrowed(req) => req,
+            #[cfg(not(all(test, feature = "mock")))]
+            Self::Owned(req) => &*req,
         };
-        let output = wrapper();
-        let close_res = gg_request_close($req);
-        GGError::from_code(close_res)?;
-        output
-    }};
+        req.read(buf).map_err(io::Error::from)
+    }
+}
+
+/// Reads the reader to its end, reading chunks of up to the reader's capacity into the vec past what is buffered.
+/// Readers may only be given initialized memory, so each chunk of the vec is zeroed before it is first read into.
+/// Once the vec is full a small probe read checks whether there is more to read before it is grown.
+pub(crate) fn read_chunks<R: Read>(
+    reader: &mut BufReader<R>,
+    buf: &mut Vec<u8>,
+    size_hint: usize,
+) -> io::Result<usize> {
+    let start = buf.len();
+    let chunk_size = reader.capacity();
+    buf.reserve(size_hint.max(chunk_size));
+    let buffered = reader.buffer().len();
+    buf.extend_from_slice(reader.buffer());
+    reader.consume(buffered);
+
+    let reader = reader.get_mut();
+    let mut filled = buf.len();
+    let result = loop {
+        let spare = buf.capacity() - filled;
+        if spare == 0 {
+            let mut probe = [0u8; 32];
+            match reader.read(&mut probe) {
+                Ok(0) => break Ok(filled - start),
+                Ok(read) => {
+                    buf.extend_from_slice(&probe[..read]);
+                    filled += read;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => break Err(e),
+            }
+            continue;
+        }
+
+        // Bytes past what has been filled are only zeroed once, the first time they are read into
+        let end = filled + spare.min(chunk_size);
+        if buf.len() < end {
+            buf.resize(end, 0);
+        }
+        match reader.read(&mut buf[filled..end]) {
+            Ok(0) => break Ok(filled - start),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => break Err(e),
+        }
+    };
+    buf.truncate(filled);
+    result
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::ptr;
+    use crate::backend::CBackend;
 
     const READ_DATA: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Malesuada fames ac turpis egestas maecenas pharetra. Ornare massa eget egestas purus viverra accumsan in nisl nisi. Dolor morbi non arcu risus. Vehicula ipsum a arcu cursus vitae. Luctus accumsan tortor posuere ac ut consequat semper viverra. At tempor commodo ullamcorper a lacus vestibulum sed. Dui ut ornare lectus sit amet. Tristique magna sit amet purus gravida quis blandit turpis. Duis at consectetur lorem donec. Amet cursus sit amet dictum sit. Lacus viverra vitae congue eu consequat ac felis donec et.
 
@@ -299,13 +474,69 @@ Parturient montes nascetur ridiculus mus mauris vitae ultricies. Suspendisse sed
     #[test]
     fn test_read_response_data() {
         GG_REQUEST_READ_BUFFER.with(|buffer| buffer.replace(READ_DATA.to_owned()));
-        let mut req: gg_request = ptr::null_mut();
-        let result = gg_request_init(&mut req);
-        assert_eq!(result, gg_error_GGE_SUCCESS);
-
-        let result = read_response_data(req).unwrap();
-        assert!(!result.is_empty());
+        let backend = CBackend;
+        let req = Request::new(&backend).unwrap();
+
+        let mut result = Vec::new();
+        let read = ResponseReader::new(&req)
+            .with_size_hint(READ_DATA.len())
+            .read_to_end(&mut result)
+            .unwrap();
+        assert_eq!(read, READ_DATA.len());
         assert_eq!(result, READ_DATA);
+        assert_eq!(result.capacity(), READ_DATA.len());
+    }
+
+    #[test]
+    fn test_response_reader_chunks() {
+        GG_REQUEST_READ_BUFFER.with(|buffer| buffer.replace(READ_DATA.to_owned()));
+        let backend = CBackend;
+        let req = Request::new(&backend).unwrap();
+        let mut reader = ResponseReader::new(&req).with_chunk_size(100);
+
+        let mut first = [0u8; 10];
+        reader.read_exact(&mut first).unwrap();
+        assert_eq!(reader.fill_buf().unwrap().len(), 90);
+        let mut rest = first.to_vec();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, READ_DATA);
+    }
+
+    #[test]
+    fn test_read_json() {
+        GG_REQUEST_READ_BUFFER.with(|buffer| buffer.replace(br#"{"foo": [1, 2, 3]}"#.to_vec()));
+        let backend = CBackend;
+        let req = Request::new(&backend).unwrap();
+        let value: Option<serde_json::Value> =
+            GGRequestResponse::default().read_json(&req).unwrap();
+        assert_eq!(value, Some(serde_json::json!({"foo": [1, 2, 3]})));
+    }
+
+    #[test]
+    fn test_throttled_without_error_response() {
+        reset_test_state();
+        let backend = CBackend;
+        let req = Request::new(&backend).unwrap();
+        let throttled = GGRequestResponse::from(GGRequestStatus::Again);
+        assert!(matches!(
+            throttled.to_error_result(&req),
+            Err(GGError::ErrorResponse(_))
+        ));
+        let unknown = GGRequestResponse::from(GGRequestStatus::Unknown);
+        assert!(unknown.to_error_result(&req).is_ok());
+    }
+
+    #[test]
+    fn test_request_closed_on_drop() {
+        reset_test_state();
+        let backend = CBackend;
+        {
+            let _req = Request::new(&backend).unwrap();
+        }
+        GG_CLOSE_REQUEST_COUNT.with(|rc| assert_eq!(*rc.borrow(), 1));
+
+        Request::new(&backend).unwrap().close().unwrap();
+        GG_CLOSE_REQUEST_COUNT.with(|rc| assert_eq!(*rc.borrow(), 2));
     }
 
     #[test]
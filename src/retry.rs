@@ -0,0 +1,247 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides [`RetryPolicy`], which retries the requests of a client with exponential backoff.
+//!
+//! By default only requests throttled by Greengrass are retried, which fail with a [`GGError::ErrorResponse`]
+//! with the [`GGRequestStatus::Again`] status.
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//! use aws_greengrass_core_rust::retry::RetryPolicy;
+//! use std::time::Duration;
+//!
+//! let policy = RetryPolicy::default()
+//!     .with_max_attempts(5)
+//!     .with_base_delay(Duration::from_millis(50));
+//! let client = IOTDataClient::default().with_retry_policy(Some(policy));
+//! ```
+use crate::error::GGError;
+use crate::request::GGRequestStatus;
+use crate::GGResult;
+use log::{error, warn};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Decides whether a request that failed with the error should be retried
+pub type Retryable = dyn Fn(&GGError) -> bool + Send + Sync;
+
+/// How failed requests are retried.
+/// The delay before each retry doubles, starting at the base delay, up to the max delay.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    pub base_delay: Duration,
+    /// The longest delay between two attempts
+    pub max_delay: Duration,
+    /// Randomizes each delay between half and all of it, so that clients throttled together don't retry together
+    pub jitter: bool,
+    retryable: Arc<Retryable>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts of throttled requests, with delays from 100ms up to 5s and jitter
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retryable: Arc::new(is_throttled),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The maximum number of attempts, including the first one. At least one attempt is always made.
+    pub fn with_max_attempts(self, max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// The delay before the first retry
+    pub fn with_base_delay(self, base_delay: Duration) -> Self {
+        RetryPolicy { base_delay, ..self }
+    }
+
+    /// The longest delay between two attempts
+    pub fn with_max_delay(self, max_delay: Duration) -> Self {
+        RetryPolicy { max_delay, ..self }
+    }
+
+    /// Whether delays are randomized
+    pub fn with_jitter(self, jitter: bool) -> Self {
+        RetryPolicy { jitter, ..self }
+    }
+
+    /// Retry the requests that failed with errors matching the predicate instead of only throttled ones
+    pub fn with_retryable<F>(self, retryable: F) -> Self
+    where
+        F: Fn(&GGError) -> bool + Send + Sync + 'static,
+    {
+        RetryPolicy {
+            retryable: Arc::new(retryable),
+            ..self
+        }
+    }
+
+    /// Calls the operation until it succeeds, fails with an error that isn't retryable or the attempts run out.
+    /// An error after more than one attempt is returned as [`GGError::RetryFailed`] with the number of attempts.
+    ///
+    /// * `operation` - Describes the operation in the log messages
+    pub fn run<T, F>(&self, operation: &str, mut call: F) -> GGResult<T>
+    where
+        F: FnMut() -> GGResult<T>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_attempts && (self.retryable)(&e) => {
+                    let delay = self.delay(attempt);
+                    warn!(
+                        "{} failed on attempt {} of {}, retrying in {:?}: {}",
+                        operation, attempt, self.max_attempts, delay, e
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => {
+                    error!("{} failed after {} attempts: {}", operation, attempt, e);
+                    return Err(GGError::RetryFailed(attempt, Box::new(e)));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The delay before the retry following the attempt
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = 2u32
+            .checked_pow(attempt - 1)
+            .and_then(|factor| self.base_delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        if self.jitter {
+            let half = delay / 2;
+            let range = (delay - half).as_nanos() as u64;
+            half + Duration::from_nanos(random() % range.saturating_add(1))
+        } else {
+            delay
+        }
+    }
+}
+
+/// True if the request was throttled by Greengrass, which is what the default policy retries
+pub fn is_throttled(error: &GGError) -> bool {
+    matches!(error, GGError::ErrorResponse(response) if response.request_status == GGRequestStatus::Again)
+}
+
+/// Runs the call with the policy, or once if there is none
+#[cfg(not(all(test, feature = "mock")))]
+pub(crate) fn with_retries<T, F>(
+    policy: Option<&RetryPolicy>,
+    operation: &str,
+    mut call: F,
+) -> GGResult<T>
+where
+    F: FnMut() -> GGResult<T>,
+{
+    match policy {
+        Some(policy) => policy.run(operation, call),
+        None => call(),
+    }
+}
+
+/// A random number from the randomly keyed hasher of the standard library, good enough for jitter
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::GGRequestResponse;
+
+    fn throttled() -> GGError {
+        GGError::ErrorResponse(GGRequestResponse::from(GGRequestStatus::Again))
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(1))
+            .with_max_delay(Duration::from_millis(2))
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let mut calls = 0;
+        let result = policy().run("test", || {
+            calls += 1;
+            if calls < 3 {
+                Err(throttled())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_keeps_attempt_count() {
+        let mut calls = 0;
+        let result: GGResult<()> = policy().with_max_attempts(4).run("test", || {
+            calls += 1;
+            Err(throttled())
+        });
+        assert_eq!(calls, 4);
+        match result {
+            Err(GGError::RetryFailed(4, e)) => assert!(is_throttled(&e)),
+            _ => panic!("Expected RetryFailed"),
+        }
+    }
+
+    #[test]
+    fn test_not_retryable() {
+        let mut calls = 0;
+        let result: GGResult<()> = policy().run("test", || {
+            calls += 1;
+            Err(GGError::InvalidParameter)
+        });
+        assert_eq!(calls, 1);
+        assert!(matches!(result, Err(GGError::InvalidParameter)));
+
+        let policy = policy().with_retryable(|e| matches!(e, GGError::InvalidParameter));
+        let result: GGResult<()> = policy.run("test", || Err(GGError::InvalidParameter));
+        assert!(matches!(result, Err(GGError::RetryFailed(3, _))));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(300))
+            .with_jitter(false);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(300));
+        assert_eq!(policy.delay(40), Duration::from_millis(300));
+
+        let policy = policy.with_jitter(true);
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(300));
+        }
+    }
+}
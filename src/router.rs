@@ -0,0 +1,420 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides a [`Handler`] that dispatches events to other handlers based on the MQTT topic they were received on.
+//!
+//! The topic is read from the subject of the client context (see [`LambdaContext::subject`]).
+//! Topic filters support the MQTT `+` (single level) and `#` (multi level) wildcards.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::handler::{Handler, LambdaContext};
+//! use aws_greengrass_core_rust::router::{Captures, Router};
+//! use aws_greengrass_core_rust::runtime::Runtime;
+//!
+//! struct AlarmHandler;
+//!
+//! impl Handler for AlarmHandler {
+//!     fn handle(&self, ctx: LambdaContext) {
+//!         println!("Alarm! {:?}", ctx);
+//!     }
+//! }
+//!
+//! struct UnknownTopicHandler;
+//!
+//! impl Handler for UnknownTopicHandler {
+//!     fn handle(&self, ctx: LambdaContext) {
+//!         println!("Received event on unknown topic {:?}", ctx.subject());
+//!     }
+//! }
+//!
+//! let router = Router::default()
+//!     .with_route("alarms/#", Box::new(AlarmHandler))
+//!     .unwrap()
+//!     .with_capturing_route("sensors/+/temperature", |ctx: LambdaContext, captures: Captures| {
+//!         println!("Temperature from sensor {:?}", captures.get(0));
+//!     })
+//!     .unwrap()
+//!     .with_fallback(Some(Box::new(UnknownTopicHandler)));
+//!
+//! Runtime::default().with_handler(Some(Box::new(router)));
+//! ```
+use crate::error::GGError;
+use crate::handler::{Handler, LambdaContext};
+use crate::runtime::ShareableHandler;
+use crate::GGResult;
+use log::warn;
+
+/// Trait to implement for handlers that need the topic segments matched by wildcards.
+/// Implemented for closures accepting a [`LambdaContext`] and [`Captures`].
+pub trait RouteHandler {
+    fn handle(&self, ctx: LambdaContext, captures: Captures);
+}
+
+impl<F> RouteHandler for F
+where
+    F: Fn(LambdaContext, Captures),
+{
+    fn handle(&self, ctx: LambdaContext, captures: Captures) {
+        self(ctx, captures)
+    }
+}
+
+/// Adapts a [`Handler`] to a [`RouteHandler`] by ignoring the captures
+struct IgnoreCaptures(Box<ShareableHandler>);
+
+impl RouteHandler for IgnoreCaptures {
+    fn handle(&self, ctx: LambdaContext, _: Captures) {
+        self.0.handle(ctx)
+    }
+}
+
+/// The topic segments matched by the wildcards of a [`TopicFilter`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Captures {
+    /// The segments matched by each `+` wildcard, in order
+    pub segments: Vec<String>,
+    /// The remainder of the topic matched by a trailing `#` wildcard, if there was one.
+    /// This will be an empty string if `#` matched the parent level.
+    pub rest: Option<String>,
+}
+
+impl Captures {
+    /// The segment matched by the `+` wildcard at the specified position
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.segments.get(index).map(String::as_str)
+    }
+
+    /// The remainder of the topic matched by a trailing `#` wildcard
+    pub fn rest(&self) -> Option<&str> {
+        self.rest.as_deref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Level {
+    Literal(String),
+    SingleWildcard,
+    MultiWildcard,
+}
+
+/// An MQTT topic filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicFilter {
+    filter: String,
+    levels: Vec<Level>,
+}
+
+impl TopicFilter {
+    /// Parses a topic filter. Returns GGError::InvalidParameter if the filter is not a valid MQTT topic filter.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::router::TopicFilter;
+    ///
+    /// let filter = TopicFilter::new("sensors/+/temperature").unwrap();
+    /// let captures = filter.matches("sensors/kitchen/temperature").unwrap();
+    /// assert_eq!(captures.get(0), Some("kitchen"));
+    /// assert!(filter.matches("sensors/kitchen/humidity").is_none());
+    /// ```
+    pub fn new(filter: &str) -> GGResult<Self> {
+        if filter.is_empty() {
+            return Err(GGError::InvalidParameter);
+        }
+        let parts: Vec<&str> = filter.split('/').collect();
+        let mut levels = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let level = match *part {
+                "+" => Level::SingleWildcard,
+                "#" if i == parts.len() - 1 => Level::MultiWildcard,
+                p if p.contains('+') || p.contains('#') => {
+                    warn!("Invalid topic filter: {}", filter);
+                    return Err(GGError::InvalidParameter);
+                }
+                p => Level::Literal(p.to_owned()),
+            };
+            levels.push(level);
+        }
+        Ok(TopicFilter {
+            filter: filter.to_owned(),
+            levels,
+        })
+    }
+
+    /// The filter as it was specified
+    pub fn as_str(&self) -> &str {
+        &self.filter
+    }
+
+    /// Returns the captured segments if the topic matches this filter
+    pub fn matches(&self, topic: &str) -> Option<Captures> {
+        // Wildcards at the first level do not match topics starting with $ (e.g. $aws/things/...)
+        if topic.starts_with('$') && !matches!(self.levels.first(), Some(Level::Literal(_))) {
+            return None;
+        }
+
+        let topic_levels: Vec<&str> = topic.split('/').collect();
+        let mut captures = Captures::default();
+        for (i, level) in self.levels.iter().enumerate() {
+            match level {
+                Level::MultiWildcard => {
+                    captures.rest = Some(topic_levels[i.min(topic_levels.len())..].join("/"));
+                    return Some(captures);
+                }
+                Level::SingleWildcard => captures.segments.push((*topic_levels.get(i)?).to_owned()),
+                Level::Literal(literal) => {
+                    if topic_levels.get(i)? != literal {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        if topic_levels.len() == self.levels.len() {
+            Some(captures)
+        } else {
+            None
+        }
+    }
+}
+
+struct Route {
+    filter: TopicFilter,
+    handler: Box<dyn RouteHandler + Send + Sync>,
+}
+
+/// A [`Handler`] that dispatches events to handlers registered by MQTT topic filter.
+///
+/// Routes are tried in the order they were registered and the first matching route handles the event.
+/// Events that match no route, or that were not received from a subscription, are passed to the fallback handler.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Option<Box<ShareableHandler>>,
+}
+
+impl Router {
+    /// Register a handler for events received on topics matching the filter
+    pub fn with_route(self, filter: &str, handler: Box<ShareableHandler>) -> GGResult<Self> {
+        self.push_route(filter, Box::new(IgnoreCaptures(handler)))
+    }
+
+    /// Register a handler for events received on topics matching the filter.
+    /// The handler will also receive the segments matched by the wildcards of the filter.
+    pub fn with_capturing_route<H>(self, filter: &str, handler: H) -> GGResult<Self>
+    where
+        H: RouteHandler + Send + Sync + 'static,
+    {
+        self.push_route(filter, Box::new(handler))
+    }
+
+    /// Provide a handler for events that match no route. If no fallback is provided, these events are logged and dropped.
+    pub fn with_fallback(self, fallback: Option<Box<ShareableHandler>>) -> Self {
+        Router { fallback, ..self }
+    }
+
+    fn push_route(
+        mut self,
+        filter: &str,
+        handler: Box<dyn RouteHandler + Send + Sync>,
+    ) -> GGResult<Self> {
+        let filter = TopicFilter::new(filter)?;
+        self.routes.push(Route { filter, handler });
+        Ok(self)
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, ctx: LambdaContext) {
+        let matched = ctx.subject().and_then(|subject| {
+            self.routes.iter().find_map(|route| {
+                route
+                    .filter
+                    .matches(subject)
+                    .map(|captures| (route, captures))
+            })
+        });
+        if let Some((route, captures)) = matched {
+            route.handler.handle(ctx, captures);
+            return;
+        }
+
+        match &self.fallback {
+            Some(fallback) => fallback.handle(ctx),
+            None => warn!(
+                "No route found for event with subject {:?}, dropping event",
+                ctx.subject()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn captures(segments: &[&str], rest: Option<&str>) -> Captures {
+        Captures {
+            segments: segments.iter().map(|s| (*s).to_owned()).collect(),
+            rest: rest.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        assert!(TopicFilter::new("").is_err());
+        assert!(TopicFilter::new("a/#/b").is_err());
+        assert!(TopicFilter::new("a/b+").is_err());
+        assert!(TopicFilter::new("a/#b").is_err());
+        assert!(TopicFilter::new("a/+/#").is_ok());
+    }
+
+    #[test]
+    fn test_literal_match() {
+        let filter = TopicFilter::new("a/b/c").unwrap();
+        assert_eq!(filter.matches("a/b/c"), Some(Captures::default()));
+        assert!(filter.matches("a/b").is_none());
+        assert!(filter.matches("a/b/c/d").is_none());
+        assert!(filter.matches("a/x/c").is_none());
+    }
+
+    #[test]
+    fn test_single_wildcard() {
+        let filter = TopicFilter::new("a/+/c/+").unwrap();
+        assert_eq!(filter.matches("a/b/c/d"), Some(captures(&["b", "d"], None)));
+        assert_eq!(filter.matches("a//c/"), Some(captures(&["", ""], None)));
+        assert!(filter.matches("a/b/c").is_none());
+        assert!(filter.matches("a/b/c/d/e").is_none());
+    }
+
+    #[test]
+    fn test_multi_wildcard() {
+        let filter = TopicFilter::new("a/+/#").unwrap();
+        assert_eq!(
+            filter.matches("a/b/c/d"),
+            Some(captures(&["b"], Some("c/d")))
+        );
+        // # also matches the parent level
+        assert_eq!(filter.matches("a/b"), Some(captures(&["b"], Some(""))));
+        assert!(filter.matches("a").is_none());
+
+        let all = TopicFilter::new("#").unwrap();
+        assert_eq!(all.matches("x/y"), Some(captures(&[], Some("x/y"))));
+    }
+
+    #[test]
+    fn test_dollar_topics() {
+        assert!(TopicFilter::new("#")
+            .unwrap()
+            .matches("$aws/things")
+            .is_none());
+        assert!(TopicFilter::new("+/things")
+            .unwrap()
+            .matches("$aws/things")
+            .is_none());
+        assert!(TopicFilter::new("$aws/#")
+            .unwrap()
+            .matches("$aws/things/foo")
+            .is_some());
+    }
+
+    type Calls = Arc<Mutex<Vec<(&'static str, Option<Captures>)>>>;
+
+    struct RecordingHandler {
+        name: &'static str,
+        calls: Calls,
+    }
+
+    impl Handler for RecordingHandler {
+        fn handle(&self, _: LambdaContext) {
+            self.calls.lock().unwrap().push((self.name, None));
+        }
+    }
+
+    fn context_for(topic: Option<&str>) -> LambdaContext {
+        let client_context = topic
+            .map(|t| base64::encode(format!(r#"{{"custom":{{"subject":"{}"}}}}"#, t)))
+            .unwrap_or_default();
+        LambdaContext::new("arn".to_owned(), client_context, vec![])
+    }
+
+    #[test]
+    fn test_router() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let capturing_calls = Arc::clone(&calls);
+        let router = Router::default()
+            .with_route(
+                "alarms/#",
+                Box::new(RecordingHandler {
+                    name: "alarms",
+                    calls: Arc::clone(&calls),
+                }),
+            )
+            .unwrap()
+            .with_capturing_route(
+                "sensors/+/temperature",
+                move |_: LambdaContext, captures: Captures| {
+                    capturing_calls
+                        .lock()
+                        .unwrap()
+                        .push(("sensors", Some(captures)));
+                },
+            )
+            .unwrap()
+            .with_fallback(Some(Box::new(RecordingHandler {
+                name: "fallback",
+                calls: Arc::clone(&calls),
+            })));
+
+        router.handle(context_for(Some("alarms/fire")));
+        router.handle(context_for(Some("sensors/kitchen/temperature")));
+        router.handle(context_for(Some("sensors/kitchen/humidity")));
+        router.handle(context_for(None));
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                ("alarms", None),
+                ("sensors", Some(captures(&["kitchen"], None))),
+                ("fallback", None),
+                ("fallback", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_router_first_match_wins() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let router = Router::default()
+            .with_route(
+                "a/b",
+                Box::new(RecordingHandler {
+                    name: "first",
+                    calls: Arc::clone(&calls),
+                }),
+            )
+            .unwrap()
+            .with_route(
+                "a/+",
+                Box::new(RecordingHandler {
+                    name: "second",
+                    calls: Arc::clone(&calls),
+                }),
+            )
+            .unwrap();
+        router.handle(context_for(Some("a/b")));
+        router.handle(context_for(Some("a/c")));
+        // no fallback, this should just be dropped
+        router.handle(context_for(Some("x")));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![("first", None), ("second", None)]
+        );
+    }
+}
@@ -6,29 +6,131 @@
  * the LICENSE file in the root of this source tree.
  */
 
+use crate::backend::{default_backend, Backend, InvocationHandle};
 use crate::bindings::*;
 use crate::error::GGError;
-use crate::handler::{Handler, LambdaContext};
+#[cfg(feature = "async")]
+use crate::handler::AsyncHandler;
+use crate::handler::{Handler, HandlerInput, LambdaContext, ResponseHandler, StreamingHandler};
+use crate::request::DEFAULT_CHUNK_SIZE;
 use crate::GGResult;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{
+    bounded, unbounded, Receiver, RecvTimeoutError, Select, SendError, Sender, TrySendError,
+};
+#[cfg(feature = "async")]
+use futures::channel::mpsc::{unbounded as stream_unbounded, UnboundedReceiver, UnboundedSender};
+#[cfg(feature = "async")]
+use futures::future::{poll_fn, Future, FutureExt};
+#[cfg(feature = "async")]
+use futures::stream::Stream;
 use lazy_static::lazy_static;
-use log::{error, info};
+use log::{debug, error, info, warn};
+#[cfg(feature = "signals")]
+use signal_hook::consts::{SIGINT, SIGTERM};
+#[cfg(feature = "signals")]
+use signal_hook::iterator::Signals;
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
 use std::default::Default;
 use std::ffi::CStr;
-use std::os::raw::c_void;
-use std::sync::Arc;
-use std::thread;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, RwLock};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{process, thread};
+#[cfg(feature = "async")]
+use tokio::runtime::Handle;
 
-/// The size of the buffer for reading content received via the C SDK
-const BUFFER_SIZE: usize = 100;
+/// How long handlers are given to finish the queued events after a shutdown was requested
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Denotes a handler that is thread safe
 pub type ShareableHandler = dyn Handler + Send + Sync;
 
+/// Function used to derive the ordering key of an event.
+/// Events with the same key are handled in the order they were received.
+pub type OrderingKey = dyn Fn(&LambdaContext) -> Option<String> + Send + Sync;
+
+/// Denotes a response handler that is thread safe
+pub type ShareableResponseHandler<E> = dyn ResponseHandler<Error = E> + Send + Sync;
+
+/// Denotes a streaming handler that is thread safe
+pub type ShareableStreamingHandler = dyn StreamingHandler + Send + Sync;
+
+/// A [`ResponseHandler`] with its error type converted to a String
+type ErasedResponseHandler = dyn Fn(LambdaContext) -> Result<Vec<u8>, String> + Send + Sync;
+
+/// A function called once during the lifecycle of the runtime
+pub type LifecycleHook = dyn FnOnce() + Send;
+
+/// Denotes an async handler that is thread safe
+#[cfg(feature = "async")]
+pub type ShareableAsyncHandler = dyn AsyncHandler + Send + Sync;
+
 lazy_static! {
     // This establishes a thread safe global channel that can
-    // be acquired from the callback function we register with the C Api
-    static ref CHANNEL: Arc<ChannelHolder> = ChannelHolder::new();
+    // be acquired from the callback function we register with the C Api.
+    // A new channel is installed every time a runtime is started, which disconnects
+    // the dispatch thread of any previously started runtime.
+    static ref CHANNEL: RwLock<Arc<ChannelHolder>> =
+        RwLock::new(ChannelHolder::new(None, OverflowPolicy::default()));
+
+    // The response handler that is called directly from the callback function we register with the C Api
+    static ref RESPONSE_HANDLER: RwLock<Option<Arc<ErasedResponseHandler>>> = RwLock::new(None);
+
+    // The streaming handler that is called directly from the callback function we register with the C Api
+    static ref STREAMING_HANDLER: RwLock<Option<Arc<ShareableStreamingHandler>>> = RwLock::new(None);
+
+    // What to do after a handler panicked, set when the runtime is started
+    static ref PANIC_POLICY: RwLock<PanicPolicy> = RwLock::new(PanicPolicy::default());
+
+    // The backend events are read from and responses written to, set when the runtime is started
+    static ref BACKEND: RwLock<Arc<dyn Backend>> = RwLock::new(default_backend());
+}
+
+thread_local! {
+    // The backtrace of the last panic on this thread, captured by the panic hook
+    static PANIC_BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+
+    // Whether a handler is being called on this thread, so the panic hook always captures the backtrace
+    static CATCHING_HANDLER_PANIC: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Ensures the panic hook capturing backtraces is only installed once
+static PANIC_HOOK: Once = Once::new();
+
+/// Count of the panics caught while handling events
+static HANDLER_PANICS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether events received from the C SDK are passed on, false once a shutdown has started
+static ACCEPTING_EVENTS: AtomicBool = AtomicBool::new(true);
+
+/// Whether events are detached from the C SDK callback, as they are handled and completed on another thread
+static DETACH_INVOCATIONS: AtomicBool = AtomicBool::new(false);
+
+/// The number of bytes read from the backend at a time when reading the message of an event
+static READ_CHUNK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_CHUNK_SIZE);
+
+/// Count of the events that were dropped because the handler queue was full
+static DROPPED_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of events that have been dropped or rejected because the handler queue was full
+pub fn dropped_events() -> usize {
+    DROPPED_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of panics that have been caught while handling events
+pub fn handler_panics() -> usize {
+    HANDLER_PANICS.load(Ordering::Relaxed)
 }
 
 /// Type of runtime. Currently only one, Async exits
@@ -52,44 +154,241 @@ impl RuntimeOption {
     }
 }
 
+/// What should happen to an event received when the handler queue is full.
+/// This only applies when a queue capacity has been specified with [`Runtime::with_queue_capacity`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OverflowPolicy {
+    /// Block the C SDK callback until there is room in the queue.
+    /// This is the default option.
+    #[default]
+    Block,
+    /// Drop the oldest event in the queue to make room for the new one
+    DropOldest,
+    /// Drop the event that was just received
+    DropNewest,
+    /// Drop the event that was just received and write an error response back to the caller
+    Reject,
+}
+
+/// What should happen after a handler panicked while handling an event.
+/// In either case the panic is logged and counted, and an error response is written back to the caller.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum PanicPolicy {
+    /// Keep handling events with the same handler thread.
+    /// This is the default option.
+    #[default]
+    Continue,
+    /// Terminate the process with the specified exit code so that Greengrass restarts the lambda
+    Exit(i32),
+}
+
+/// Describes how the events received from the C SDK are dispatched
+enum Dispatch {
+    /// Events are passed to a blocking [`Handler`]
+    Handler(Box<ShareableHandler>),
+    /// Events are passed to a [`ResponseHandler`] on the thread of the C callback
+    Response(Arc<ErasedResponseHandler>),
+    /// Events are passed with a reader of their message to a [`StreamingHandler`] on the thread of the C callback
+    Streaming(Arc<ShareableStreamingHandler>),
+    /// Events are passed to an [`AsyncHandler`] and the resulting future is spawned
+    #[cfg(feature = "async")]
+    AsyncHandler(Box<ShareableAsyncHandler>),
+    /// Events are forwarded to a [`ContextStream`]
+    #[cfg(feature = "async")]
+    Stream(UnboundedSender<LambdaContext>),
+}
+
 /// Configures and instantiates the green grass core runtime
 /// Runtime can only be started by the Initializer. You must pass the runtime into the [`Initializer::with_runtime`] method.
 pub struct Runtime {
     runtime_option: RuntimeOption,
-    handler: Option<Box<ShareableHandler>>,
+    dispatch: Option<Dispatch>,
+    queue_capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    workers: usize,
+    ordering_key: Option<Box<OrderingKey>>,
+    panic_policy: PanicPolicy,
+    on_start: Option<Box<LifecycleHook>>,
+    on_shutdown: Option<Box<LifecycleHook>>,
+    shutdown: Option<Arc<ShutdownState>>,
+    #[cfg(feature = "signals")]
+    handle_signals: bool,
+    shutdown_timeout: Duration,
+    backend: Arc<dyn Backend>,
+    read_chunk_size: usize,
+    #[cfg(feature = "async")]
+    executor: Option<Handle>,
 }
 
 impl Default for Runtime {
     fn default() -> Self {
         Runtime {
             runtime_option: RuntimeOption::Sync,
-            handler: None,
+            dispatch: None,
+            queue_capacity: None,
+            overflow_policy: OverflowPolicy::default(),
+            workers: 1,
+            ordering_key: None,
+            panic_policy: PanicPolicy::default(),
+            on_start: None,
+            on_shutdown: None,
+            shutdown: None,
+            #[cfg(feature = "signals")]
+            handle_signals: false,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            backend: default_backend(),
+            read_chunk_size: DEFAULT_CHUNK_SIZE,
+            #[cfg(feature = "async")]
+            executor: None,
         }
     }
 }
 
 impl Runtime {
-    /// Start the green grass core runtime
+    /// Start the green grass core runtime.
+    ///
+    /// If a shutdown can be requested (see [`Runtime::with_shutdown_handle`]) a [`RuntimeOption::Sync`]
+    /// runtime blocks until the shutdown has completed, while a [`RuntimeOption::Async`] runtime
+    /// performs the shutdown in its own thread.
     pub(crate) fn start(self) -> GGResult<()> {
-        unsafe {
-            // If there is a handler defined, then register the
-            // the c delegating handler and start a thread that
-            // monitors the channel for messages from the c handler
-            let c_handler = if let Some(handler) = self.handler {
-                thread::spawn(move || loop {
-                    match ChannelHolder::recv() {
-                        Ok(context) => handler.handle(context),
-                        Err(e) => error!("{}", e),
+        let Runtime {
+            runtime_option,
+            dispatch,
+            queue_capacity,
+            overflow_policy,
+            workers,
+            ordering_key,
+            panic_policy,
+            on_start,
+            on_shutdown,
+            shutdown,
+            #[cfg(feature = "signals")]
+            handle_signals,
+            shutdown_timeout,
+            backend,
+            read_chunk_size,
+            #[cfg(feature = "async")]
+            executor,
+        } = self;
+
+        install_panic_hook();
+        *PANIC_POLICY.write().expect("panic policy lock poisoned") = panic_policy;
+        *BACKEND.write().expect("backend lock poisoned") = backend;
+        READ_CHUNK_SIZE.store(read_chunk_size.max(1), Ordering::Relaxed);
+        ACCEPTING_EVENTS.store(true, Ordering::SeqCst);
+        DETACH_INVOCATIONS.store(false, Ordering::SeqCst);
+
+        if let Some(on_start) = on_start {
+            on_start();
+        }
+
+        // Every thread handling events holds a sender, the receiver disconnects once they have all exited
+        let (done_sender, done_receiver) = bounded::<()>(0);
+
+        // If there is a handler defined, then register the
+        // the c delegating handler and start a thread that
+        // monitors the channel for messages from the c handler
+        let c_handler: extern "C" fn(*const gg_lambda_context) =
+            if let Some(Dispatch::Response(handler)) = dispatch {
+                *RESPONSE_HANDLER
+                    .write()
+                    .expect("response handler lock poisoned") = Some(handler);
+                // Response handlers run on the C SDK thread, no thread needs to finish
+                drop(done_sender);
+                responding_handler
+            } else if let Some(Dispatch::Streaming(handler)) = dispatch {
+                *STREAMING_HANDLER
+                    .write()
+                    .expect("streaming handler lock poisoned") = Some(handler);
+                // Streaming handlers run on the C SDK thread, no thread needs to finish
+                drop(done_sender);
+                streaming_handler
+            } else if let Some(dispatch) = dispatch {
+                let receiver = ChannelHolder::install(queue_capacity, overflow_policy.clone());
+                match dispatch {
+                    Dispatch::Handler(handler) => {
+                        DETACH_INVOCATIONS.store(true, Ordering::SeqCst);
+                        spawn_workers(
+                            Arc::from(handler),
+                            workers,
+                            ordering_key,
+                            queue_capacity,
+                            overflow_policy,
+                            receiver,
+                            done_sender,
+                        )
                     }
-                });
+                    // Handled above
+                    Dispatch::Response(_) | Dispatch::Streaming(_) => drop(done_sender),
+                    #[cfg(feature = "async")]
+                    Dispatch::AsyncHandler(handler) => {
+                        DETACH_INVOCATIONS.store(true, Ordering::SeqCst);
+                        spawn_async_dispatcher(handler, executor, receiver, done_sender)?
+                    }
+                    #[cfg(feature = "async")]
+                    Dispatch::Stream(sender) => {
+                        thread::spawn(move || {
+                            let _done = done_sender;
+                            while let Some(context) = ChannelHolder::recv(&receiver) {
+                                if sender.unbounded_send(context).is_err() {
+                                    info!(
+                                        "Context stream was dropped, no longer forwarding events"
+                                    );
+                                    break;
+                                }
+                            }
+                        });
+                    }
+                }
 
                 delgating_handler
             } else {
+                drop(done_sender);
                 no_op_handler
             };
 
-            let start_res = gg_runtime_start(Some(c_handler), self.runtime_option.as_opt());
-            GGError::from_code(start_res)?;
+        let shutdown = match shutdown {
+            Some(shutdown) => shutdown,
+            None => unsafe {
+                let start_res = gg_runtime_start(Some(c_handler), runtime_option.as_opt());
+                return GGError::from_code(start_res);
+            },
+        };
+
+        #[cfg(feature = "signals")]
+        {
+            if handle_signals {
+                listen_for_signals(&shutdown)?;
+            }
+        }
+
+        // The shutdown is managed here, so the C runtime always runs in its own thread
+        let start_res = unsafe { gg_runtime_start(Some(c_handler), RuntimeOption::Async.as_opt()) };
+        match GGError::from_code(start_res) {
+            Ok(_) => (),
+            Err(GGError::Terminate) => {
+                info!("Terminate received when starting the runtime");
+                shutdown.advance(ShutdownStatus::Requested);
+            }
+            Err(e) => return Err(e),
+        }
+
+        let lifecycle = Lifecycle {
+            shutdown,
+            on_shutdown,
+            shutdown_timeout,
+            done_receiver,
+        };
+        match runtime_option {
+            RuntimeOption::Sync => lifecycle.run(),
+            RuntimeOption::Async => {
+                thread::Builder::new()
+                    .name("gg-shutdown".to_owned())
+                    .spawn(move || lifecycle.run())
+                    .map_err(|e| {
+                        GGError::Unknown(format!("Could not spawn shutdown thread: {}", e))
+                    })?;
+            }
         }
         Ok(())
     }
@@ -102,6 +401,161 @@ impl Runtime {
         }
     }
 
+    /// Bound the queue of events waiting to be handled to the specified capacity.
+    /// By default the queue is unbounded, which can allow memory to grow without limit if the handler is slow.
+    /// A capacity of 0 is treated as 1, as a queue that can't hold an event can never apply the [`OverflowPolicy`].
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::runtime::{OverflowPolicy, Runtime};
+    ///
+    /// Runtime::default()
+    ///     .with_queue_capacity(Some(100))
+    ///     .with_overflow_policy(OverflowPolicy::DropOldest);
+    /// ```
+    pub fn with_queue_capacity(self, queue_capacity: Option<usize>) -> Self {
+        Runtime {
+            queue_capacity: queue_capacity.map(|cap| cap.max(1)),
+            ..self
+        }
+    }
+
+    /// Provide the policy used when the queue specified by [`Runtime::with_queue_capacity`] is full.
+    pub fn with_overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        Runtime {
+            overflow_policy,
+            ..self
+        }
+    }
+
+    /// Dispatch events to a pool of threads that share the [`Handler`].
+    /// By default all events are handled by one thread.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    ///
+    /// Runtime::default().with_workers(4);
+    /// ```
+    pub fn with_workers(self, workers: usize) -> Self {
+        Runtime {
+            workers: workers.max(1),
+            ..self
+        }
+    }
+
+    /// Provide a function that derives an ordering key for events when using multiple workers.
+    /// Events with the same key are handled in order by the same worker, while events with different
+    /// keys or no key are handled in parallel.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    ///
+    /// Runtime::default()
+    ///     .with_workers(4)
+    ///     .with_ordering_key(Some(Box::new(|ctx| Some(ctx.function_arn.clone()))));
+    /// ```
+    pub fn with_ordering_key(self, ordering_key: Option<Box<OrderingKey>>) -> Self {
+        Runtime {
+            ordering_key,
+            ..self
+        }
+    }
+
+    /// Provide the policy applied when a handler panics while handling an event.
+    /// Panics are always caught, so by default the handler keeps receiving events.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::runtime::{PanicPolicy, Runtime};
+    ///
+    /// Runtime::default().with_panic_policy(PanicPolicy::Exit(1));
+    /// ```
+    pub fn with_panic_policy(self, panic_policy: PanicPolicy) -> Self {
+        Runtime {
+            panic_policy,
+            ..self
+        }
+    }
+
+    /// Provide a function that is called when the runtime is started, before any events are received
+    pub fn with_on_start(self, on_start: Option<Box<LifecycleHook>>) -> Self {
+        Runtime { on_start, ..self }
+    }
+
+    /// Provide a function that is called during shutdown, once the handlers have finished or the
+    /// shutdown timeout has passed. Providing this hook allows the runtime to be shutdown.
+    pub fn with_on_shutdown(self, on_shutdown: Option<Box<LifecycleHook>>) -> Self {
+        let shutdown = self.shutdown.clone().or_else(|| Some(Arc::default()));
+        Runtime {
+            on_shutdown,
+            shutdown,
+            ..self
+        }
+    }
+
+    /// Request a shutdown when SIGTERM or SIGINT is received.
+    /// Requires the `signals` feature, signal handlers are only registered when this is enabled.
+    #[cfg(feature = "signals")]
+    pub fn with_signal_handling(self, handle_signals: bool) -> Self {
+        let shutdown = self.shutdown.clone().or_else(|| Some(Arc::default()));
+        Runtime {
+            handle_signals,
+            shutdown,
+            ..self
+        }
+    }
+
+    /// Read events and write responses with the specified backend instead of the C SDK
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        Runtime { backend, ..self }
+    }
+
+    /// The number of bytes read from the backend at a time when reading the message of an event.
+    /// The default is 4096.
+    pub fn with_read_chunk_size(self, read_chunk_size: usize) -> Self {
+        Runtime {
+            read_chunk_size,
+            ..self
+        }
+    }
+
+    /// How long handlers are given to finish the events already queued when a shutdown is requested.
+    /// The default is 10 seconds.
+    pub fn with_shutdown_timeout(self, shutdown_timeout: Duration) -> Self {
+        Runtime {
+            shutdown_timeout,
+            ..self
+        }
+    }
+
+    /// Returns a [`ShutdownHandle`] that can be used to shutdown the runtime.
+    ///
+    /// Once a shutdown is requested, events are no longer accepted, the queued events are handled until the
+    /// shutdown timeout, the shutdown hook is called and the logger is flushed.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    /// use std::thread;
+    ///
+    /// let (runtime, shutdown) = Runtime::default()
+    ///     .with_on_shutdown(Some(Box::new(|| println!("Goodbye"))))
+    ///     .with_shutdown_handle();
+    ///
+    /// thread::spawn(move || {
+    ///     // Do something here, then stop the lambda
+    ///     shutdown.shutdown();
+    /// });
+    /// ```
+    pub fn with_shutdown_handle(self) -> (Self, ShutdownHandle) {
+        let shutdown = self.shutdown.clone().unwrap_or_default();
+        let handle = ShutdownHandle {
+            state: Arc::clone(&shutdown),
+        };
+        let runtime = Runtime {
+            shutdown: Some(shutdown),
+            ..self
+        };
+        (runtime, handle)
+    }
+
     /// Provide a handler. If no handler is provided the runtime will register a no-op handler
     ///
     /// ```rust
@@ -119,112 +573,890 @@ impl Runtime {
     /// Runtime::default().with_handler(Some(Box::new(MyHandler)));
     /// ```
     pub fn with_handler(self, handler: Option<Box<ShareableHandler>>) -> Self {
-        Runtime { handler, ..self }
+        Runtime {
+            dispatch: handler.map(Dispatch::Handler),
+            ..self
+        }
+    }
+
+    /// Provide a handler for request-response invocations. This replaces any handler previously configured.
+    ///
+    /// The value returned by the handler is written back to the caller as the lambda response.
+    /// An error response is written if the handler returns an error or panics.
+    ///
+    /// Response handlers are called on the thread the C SDK delivers events on, so the response is
+    /// written before the SDK moves on to the next event. Because of this the queue and worker options
+    /// do not apply to response handlers.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::handler::{LambdaContext, ResponseHandler};
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl ResponseHandler for MyHandler {
+    ///     type Error = String;
+    ///
+    ///     fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
+    ///         Ok(b"my response".to_vec())
+    ///     }
+    /// }
+    ///
+    /// Runtime::default().with_response_handler(Some(Box::new(MyHandler)));
+    /// ```
+    pub fn with_response_handler<E: Display + 'static>(
+        self,
+        handler: Option<Box<ShareableResponseHandler<E>>>,
+    ) -> Self {
+        let dispatch = handler.map(|h| {
+            let erased: Arc<ErasedResponseHandler> =
+                Arc::new(move |ctx| h.handle(ctx).map_err(|e| format!("{}", e)));
+            Dispatch::Response(erased)
+        });
+        Runtime { dispatch, ..self }
+    }
+
+    /// Provide a handler that streams the message of each event. This replaces any handler previously configured.
+    ///
+    /// Streaming handlers are called on the thread the C SDK delivers events on, which is the only thread
+    /// the message can be read from. Like response handlers, the queue and worker options do not apply to them.
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::handler::{HandlerInput, LambdaContext, StreamingHandler};
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    /// use std::io;
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl StreamingHandler for MyHandler {
+    ///     fn handle(&self, ctx: LambdaContext, mut input: HandlerInput) {
+    ///         // Process the message without holding all of it in memory
+    ///         io::copy(&mut input, &mut io::sink()).expect("Could not read message");
+    ///     }
+    /// }
+    ///
+    /// Runtime::default().with_streaming_handler(Some(Box::new(MyHandler)));
+    /// ```
+    pub fn with_streaming_handler(self, handler: Option<Box<ShareableStreamingHandler>>) -> Self {
+        Runtime {
+            dispatch: handler.map(|h| Dispatch::Streaming(Arc::from(h))),
+            ..self
+        }
+    }
+
+    /// Provide an async handler. This replaces any handler or stream previously configured.
+    ///
+    /// The futures returned by the handler will be spawned on the executor provided by
+    /// [`Runtime::with_executor`]. If no executor was provided, the runtime will create its own.
+    ///
+    /// ```ignore
+    /// use aws_greengrass_core_rust::handler::{AsyncHandler, LambdaContext};
+    /// use aws_greengrass_core_rust::runtime::Runtime;
+    /// use futures::future::{BoxFuture, FutureExt};
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl AsyncHandler for MyHandler {
+    ///     fn handle(&self, ctx: LambdaContext) -> BoxFuture<'static, ()> {
+    ///         async move {
+    ///             // Do something here
+    ///         }
+    ///         .boxed()
+    ///     }
+    /// }
+    ///
+    /// Runtime::default().with_async_handler(Some(Box::new(MyHandler)));
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn with_async_handler(self, handler: Option<Box<ShareableAsyncHandler>>) -> Self {
+        Runtime {
+            dispatch: handler.map(Dispatch::AsyncHandler),
+            ..self
+        }
+    }
+
+    /// Provide the tokio executor that futures returned by an [`AsyncHandler`] will be spawned on.
+    /// This is useful for long lived lambdas that already run their own tokio runtime.
+    #[cfg(feature = "async")]
+    pub fn with_executor(self, executor: Option<Handle>) -> Self {
+        Runtime { executor, ..self }
+    }
+
+    /// Exposes events received by the runtime as a [`ContextStream`].
+    /// This replaces any handler previously configured.
+    ///
+    /// ```ignore
+    /// use aws_greengrass_core_rust::runtime::{Runtime, RuntimeOption};
+    /// use aws_greengrass_core_rust::Initializer;
+    /// use futures::StreamExt;
+    ///
+    /// # async fn run() {
+    /// let (runtime, mut stream) = Runtime::default()
+    ///     .with_runtime_option(RuntimeOption::Async)
+    ///     .with_context_stream();
+    /// Initializer::default().with_runtime(runtime).init().unwrap();
+    /// while let Some(ctx) = stream.next().await {
+    ///     println!("Received an event! {:?}", ctx);
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn with_context_stream(self) -> (Self, ContextStream) {
+        let (sender, receiver) = stream_unbounded();
+        let runtime = Runtime {
+            dispatch: Some(Dispatch::Stream(sender)),
+            ..self
+        };
+        (runtime, ContextStream { receiver })
     }
 }
 
-/// c handler that performs a no op
-extern "C" fn no_op_handler(_: *const gg_lambda_context) {
-    info!("No opt handler called!");
+/// Spawns the worker threads that handle events with a [`Handler`].
+///
+/// Without an ordering key all workers receive from the handler channel directly.
+/// With an ordering key a dispatch thread routes keyed events to the channel of the worker
+/// that owns that key, all other events go to a channel shared by every worker.
+/// These channels have the same capacity and overflow policy as the handler channel.
+fn spawn_workers(
+    handler: Arc<ShareableHandler>,
+    workers: usize,
+    ordering_key: Option<Box<OrderingKey>>,
+    queue_capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    receiver: Receiver<LambdaContext>,
+    done: Sender<()>,
+) {
+    let new_channel = || match queue_capacity {
+        Some(cap) => bounded(cap),
+        None => unbounded(),
+    };
+
+    let ordering_key = match ordering_key {
+        Some(ordering_key) => ordering_key,
+        None => {
+            for i in 0..workers {
+                let handler = Arc::clone(&handler);
+                let receiver = receiver.clone();
+                let done = done.clone();
+                spawn_worker(i, move || {
+                    let _done = done;
+                    while let Some(context) = ChannelHolder::recv(&receiver) {
+                        handle_isolated(handler.as_ref(), context);
+                    }
+                });
+            }
+            return;
+        }
+    };
+
+    let shared = new_channel();
+    let mut keyed = Vec::with_capacity(workers);
+    for i in 0..workers {
+        let (keyed_sender, keyed_receiver) = new_channel();
+        keyed.push((keyed_sender, keyed_receiver.clone()));
+        let handler = Arc::clone(&handler);
+        let receivers = [keyed_receiver, shared.1.clone()];
+        let done = done.clone();
+        spawn_worker(i, move || {
+            let _done = done;
+            let mut select = Select::new();
+            for receiver in receivers.iter() {
+                select.recv(receiver);
+            }
+            // Keep draining until the dispatch thread has exited and both channels are empty
+            let mut open = receivers.len();
+            while open > 0 {
+                let operation = select.select();
+                let index = operation.index();
+                match operation.recv(&receivers[index]) {
+                    Ok(context) => handle_isolated(handler.as_ref(), context),
+                    Err(_) => {
+                        select.remove(index);
+                        open -= 1;
+                    }
+                }
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        while let Some(context) = ChannelHolder::recv(&receiver) {
+            let (sender, receiver) = match ordering_key(&context) {
+                Some(key) => {
+                    let mut hasher = DefaultHasher::new();
+                    key.hash(&mut hasher);
+                    &keyed[hasher.finish() as usize % keyed.len()]
+                }
+                None => &shared,
+            };
+            if let Err(e) = ChannelHolder::send_to(sender, receiver, &overflow_policy, context) {
+                error!("Error dispatching event to handler worker: {}", e);
+            }
+        }
+    });
 }
 
-/// c handler that utilizes ChannelHandler in order to pass
-/// information to the Handler implementation provided
-extern "C" fn delgating_handler(c_ctx: *const gg_lambda_context) {
-    info!("delegating_handler called!");
-    unsafe {
-        let result = build_context(c_ctx).and_then(ChannelHolder::send);
-        if let Err(e) = result {
-            error!("{}", e);
+fn spawn_worker<F: FnOnce() + Send + 'static>(index: usize, f: F) {
+    let spawn_result = thread::Builder::new()
+        .name(format!("gg-handler-{}", index))
+        .spawn(f);
+    if let Err(e) = spawn_result {
+        error!("Could not spawn handler worker {}: {}", index, e);
+    }
+}
+
+/// Spawns the thread that spawns the futures returned by the async handler.
+/// If no executor was provided a tokio runtime is created and owned by the thread
+#[cfg(feature = "async")]
+fn spawn_async_dispatcher(
+    handler: Box<ShareableAsyncHandler>,
+    executor: Option<Handle>,
+    receiver: Receiver<LambdaContext>,
+    done: Sender<()>,
+) -> GGResult<()> {
+    let runtime = if executor.is_none() {
+        let rt = tokio::runtime::Builder::new()
+            .threaded_scheduler()
+            .thread_name("gg-async-handler")
+            .build()
+            .map_err(|e| GGError::Unknown(format!("Could not create tokio runtime: {}", e)))?;
+        Some(rt)
+    } else {
+        None
+    };
+
+    thread::spawn(move || {
+        let _done = done;
+        let handle = executor.unwrap_or_else(|| {
+            runtime
+                .as_ref()
+                .map(|rt| rt.handle().clone())
+                .expect("runtime must exist when no executor is provided")
+        });
+        while let Some(context) = ChannelHolder::recv(&receiver) {
+            let invocation = context.invocation().cloned();
+            let future = with_invocation(invocation.as_ref(), || {
+                catch_handler_panic(|| handler.handle(context))
+            });
+            let future = match future {
+                Ok(future) => future,
+                Err(msg) => {
+                    with_invocation(invocation.as_ref(), || respond_to_panic(&msg));
+                    complete_invocation(invocation.as_ref());
+                    continue;
+                }
+            };
+            handle.spawn(async move {
+                // The future may be polled on any thread of the executor, so the invocation is attached for each poll
+                let mut future = AssertUnwindSafe(future).catch_unwind();
+                let result = poll_fn(|cx| {
+                    calling_handler(|| {
+                        with_invocation(invocation.as_ref(), || Pin::new(&mut future).poll(cx))
+                    })
+                })
+                .await;
+                if let Err(payload) = result {
+                    let msg = format!("Handler panicked: {}", panic_message(&payload));
+                    record_handler_panic(&msg);
+                    with_invocation(invocation.as_ref(), || respond_to_panic(&msg));
+                }
+                complete_invocation(invocation.as_ref());
+            });
         }
+        // keep the runtime alive for as long as events can be received
+        drop(runtime);
+    });
+    Ok(())
+}
+
+/// A [`Stream`] of the events received by the runtime.
+/// Created with [`Runtime::with_context_stream`].
+#[cfg(feature = "async")]
+pub struct ContextStream {
+    receiver: UnboundedReceiver<LambdaContext>,
+}
+
+#[cfg(feature = "async")]
+impl Stream for ContextStream {
+    type Item = LambdaContext;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
     }
 }
 
-/// Converts the c context to our rust native context
-unsafe fn build_context(c_ctx: *const gg_lambda_context) -> GGResult<LambdaContext> {
-    let message = handler_read_message()?;
-    let function_arn = CStr::from_ptr((*c_ctx).function_arn)
-        .to_string_lossy()
-        .to_owned()
-        .to_string();
-    let client_context = CStr::from_ptr((*c_ctx).client_context)
-        .to_string_lossy()
-        .to_owned()
-        .to_string();
-    Ok(LambdaContext::new(function_arn, client_context, message))
+#[cfg(feature = "async")]
+impl Drop for ContextStream {
+    fn drop(&mut self) {
+        // The stream is dropped while unwinding when the task consuming it panics
+        if thread::panicking() {
+            record_handler_panic("Context stream consumer panicked");
+            apply_panic_policy();
+        }
+    }
 }
 
-/// Wraps the C gg_lambda_handler_read call
-unsafe fn handler_read_message() -> GGResult<Vec<u8>> {
-    let mut collected: Vec<u8> = Vec::new();
-    loop {
-        let mut buffer = [0u8; BUFFER_SIZE];
-        let mut read: usize = 0;
+/// Progress of the shutdown of a runtime
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+enum ShutdownStatus {
+    #[default]
+    Running,
+    Requested,
+    Complete,
+}
 
-        let raw_read = &mut read as *mut usize;
+/// Shutdown state shared between the runtime and its [`ShutdownHandle`]s
+#[derive(Default)]
+struct ShutdownState {
+    status: Mutex<ShutdownStatus>,
+    changed: Condvar,
+}
 
-        let pub_res =
-            gg_lambda_handler_read(buffer.as_mut_ptr() as *mut c_void, BUFFER_SIZE, raw_read);
+impl ShutdownState {
+    fn status(&self) -> ShutdownStatus {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-        GGError::from_code(pub_res)?;
+    /// Moves to the specified status unless it has already been reached
+    fn advance(&self, status: ShutdownStatus) {
+        let mut current = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        if *current < status {
+            *current = status;
+            self.changed.notify_all();
+        }
+    }
 
-        if read > 0 {
-            collected.extend_from_slice(&buffer[..read]);
-        } else {
-            break;
+    /// Blocks until the specified status has been reached
+    fn wait_for(&self, status: ShutdownStatus) {
+        let mut current = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        while *current < status {
+            current = self
+                .changed
+                .wait(current)
+                .unwrap_or_else(|e| e.into_inner());
         }
     }
-    Ok(collected)
 }
 
-/// Wraps a Channel.
-/// This is mostly needed as there is no way to instantiate a static ref with a tuple (see CHANNEL above)
-struct ChannelHolder {
-    sender: Sender<LambdaContext>,
-    receiver: Receiver<LambdaContext>,
+/// Used to shutdown a running runtime. Created with [`Runtime::with_shutdown_handle`].
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
 }
 
-impl ChannelHolder {
-    pub fn new() -> Arc<Self> {
-        let (sender, receiver) = unbounded();
-        let holder = ChannelHolder { sender, receiver };
-        Arc::new(holder)
+impl ShutdownHandle {
+    /// Requests the runtime to shutdown. This returns immediately, use [`ShutdownHandle::wait`]
+    /// to block until the shutdown has completed.
+    pub fn shutdown(&self) {
+        self.state.advance(ShutdownStatus::Requested);
     }
 
-    /// Performs a send with CHANNEL and coerces the error type
-    fn send(context: LambdaContext) -> GGResult<()> {
-        Arc::clone(&CHANNEL)
-            .sender
-            .send(context)
-            .map_err(GGError::from)
+    /// Whether a shutdown has been requested, by this handle or a signal
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.state.status() >= ShutdownStatus::Requested
     }
 
-    /// Performs a recv with CHANNEL and coerces the error type
-    fn recv() -> GGResult<LambdaContext> {
-        Arc::clone(&CHANNEL).receiver.recv().map_err(GGError::from)
+    /// Blocks until the runtime has shutdown
+    pub fn wait(&self) {
+        self.state.wait_for(ShutdownStatus::Complete);
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::handler::{Handler, LambdaContext};
-    use crate::Initializer;
-    use crossbeam_channel::{bounded, Sender};
-    use std::ffi::CString;
-    use std::time::Duration;
+/// Performs the shutdown of a runtime once requested
+struct Lifecycle {
+    shutdown: Arc<ShutdownState>,
+    on_shutdown: Option<Box<LifecycleHook>>,
+    shutdown_timeout: Duration,
+    done_receiver: Receiver<()>,
+}
 
-    #[test]
-    fn test_build_context() {
-        unsafe {
-            let my_message = b"My handlers message";
-            GG_LAMBDA_HANDLER_READ_BUFFER.with(|b| b.replace(my_message.to_owned().to_vec()));
+impl Lifecycle {
+    fn run(self) {
+        self.shutdown.wait_for(ShutdownStatus::Requested);
+        info!("Shutting down runtime");
 
-            let my_function_arn = "this is a function arn";
-            let client_ctx = "this is my client context";
+        ACCEPTING_EVENTS.store(false, Ordering::SeqCst);
+        // Replacing the channel disconnects the handler threads once they have drained the queue
+        ChannelHolder::install(None, OverflowPolicy::default());
+        *RESPONSE_HANDLER
+            .write()
+            .expect("response handler lock poisoned") = None;
+        *STREAMING_HANDLER
+            .write()
+            .expect("streaming handler lock poisoned") = None;
 
-            let my_function_arn_c = CString::new(my_function_arn).unwrap();
-            let client_ctx_c = CString::new(client_ctx).unwrap();
+        match self.done_receiver.recv_timeout(self.shutdown_timeout) {
+            Err(RecvTimeoutError::Timeout) => warn!(
+                "Handlers did not finish within {:?}, continuing shutdown",
+                self.shutdown_timeout
+            ),
+            _ => info!("All handlers finished"),
+        }
+
+        if let Some(on_shutdown) = self.on_shutdown {
+            on_shutdown();
+        }
+        log::logger().flush();
+        self.shutdown.advance(ShutdownStatus::Complete);
+    }
+}
+
+/// Spawns a thread that requests a shutdown when SIGTERM or SIGINT is received
+#[cfg(feature = "signals")]
+fn listen_for_signals(shutdown: &Arc<ShutdownState>) -> GGResult<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])
+        .map_err(|e| GGError::Unknown(format!("Could not register signal handler: {}", e)))?;
+    let shutdown = Arc::clone(shutdown);
+    thread::Builder::new()
+        .name("gg-signals".to_owned())
+        .spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                info!("Received signal {}, shutting down", signal);
+                shutdown.advance(ShutdownStatus::Requested);
+            }
+        })
+        .map_err(|e| GGError::Unknown(format!("Could not spawn signal thread: {}", e)))?;
+    Ok(())
+}
+
+/// c handler that performs a no op
+extern "C" fn no_op_handler(_: *const gg_lambda_context) {
+    info!("No opt handler called!");
+}
+
+/// c handler that utilizes ChannelHandler in order to pass
+/// information to the Handler implementation provided
+extern "C" fn delgating_handler(c_ctx: *const gg_lambda_context) {
+    info!("delegating_handler called!");
+    if !ACCEPTING_EVENTS.load(Ordering::SeqCst) {
+        reject_during_shutdown();
+        return;
+    }
+    let context = match unsafe { build_context(c_ctx) } {
+        Ok(context) => context,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    // Handlers run on another thread, so the invocation is detached to be completed once they have returned
+    let context = if DETACH_INVOCATIONS.load(Ordering::SeqCst) {
+        context.with_invocation(backend().handler_detach())
+    } else {
+        context
+    };
+    let invocation = context.invocation().cloned();
+    if let Err(e) = ChannelHolder::send(context) {
+        error!("{}", e);
+        complete_invocation(invocation.as_ref());
+    }
+}
+
+/// c handler that calls the registered response handler and writes its result
+/// as the lambda response
+extern "C" fn responding_handler(c_ctx: *const gg_lambda_context) {
+    info!("responding_handler called!");
+    if !ACCEPTING_EVENTS.load(Ordering::SeqCst) {
+        reject_during_shutdown();
+        return;
+    }
+    let handler = RESPONSE_HANDLER
+        .read()
+        .expect("response handler lock poisoned")
+        .clone();
+    let handler = match handler {
+        Some(handler) => handler,
+        None => {
+            error!("No response handler registered");
+            return;
+        }
+    };
+
+    unsafe {
+        let context = match build_context(c_ctx) {
+            Ok(context) => context,
+            Err(e) => {
+                error!("{}", e);
+                if let Err(e) = backend().handler_write_error(&format!("{}", e)) {
+                    error!("Error writing error response: {}", e);
+                }
+                return;
+            }
+        };
+
+        let (result, panicked) = match catch_handler_panic(|| handler(context)) {
+            Ok(result) => (result, false),
+            Err(msg) => (Err(msg), true),
+        };
+
+        let write_result = match result {
+            Ok(bytes) => backend().handler_write_response(&bytes),
+            Err(msg) => {
+                error!("Response handler failed: {}", msg);
+                backend().handler_write_error(&msg)
+            }
+        };
+        if let Err(e) = write_result {
+            error!("Error writing response: {}", e);
+        }
+        if panicked {
+            apply_panic_policy();
+        }
+    }
+}
+
+/// c handler that calls the registered streaming handler with a reader of the message
+extern "C" fn streaming_handler(c_ctx: *const gg_lambda_context) {
+    info!("streaming_handler called!");
+    if !ACCEPTING_EVENTS.load(Ordering::SeqCst) {
+        reject_during_shutdown();
+        return;
+    }
+    let handler = STREAMING_HANDLER
+        .read()
+        .expect("streaming handler lock poisoned")
+        .clone();
+    let handler = match handler {
+        Some(handler) => handler,
+        None => {
+            error!("No streaming handler registered");
+            return;
+        }
+    };
+
+    let context = unsafe { context_with_message(c_ctx, Vec::new()) };
+    if let Err(msg) = catch_handler_panic(|| handler.handle(context, handler_input())) {
+        respond_to_panic(&msg);
+    }
+}
+
+/// Writes an error response for an event received after a shutdown was requested
+fn reject_during_shutdown() {
+    warn!("Event received while shutting down, rejecting");
+    if let Err(e) = backend().handler_write_error("Event rejected, lambda is shutting down") {
+        error!("Error writing error response: {}", e);
+    }
+}
+
+/// Calls the handler, isolating the calling thread from any panic.
+/// If the handler panics an error response is written and the panic policy is applied.
+/// The invocation of the event is completed once the handler has returned.
+fn handle_isolated(handler: &ShareableHandler, context: LambdaContext) {
+    let invocation = context.invocation().cloned();
+    with_invocation(invocation.as_ref(), || {
+        if let Err(msg) = catch_handler_panic(|| handler.handle(context)) {
+            respond_to_panic(&msg);
+        }
+    });
+    complete_invocation(invocation.as_ref());
+}
+
+/// Runs the function with the detached invocation attached to this thread,
+/// so that the responses it writes are written for the invocation
+fn with_invocation<R, F: FnOnce() -> R>(invocation: Option<&InvocationHandle>, f: F) -> R {
+    match invocation {
+        Some(invocation) => {
+            let backend = backend();
+            backend.handler_attach(Some(invocation));
+            let result = f();
+            backend.handler_attach(None);
+            result
+        }
+        None => f(),
+    }
+}
+
+/// Completes the detached invocation of an event that has been handled or dropped
+fn complete_invocation(invocation: Option<&InvocationHandle>) {
+    if let Some(invocation) = invocation {
+        backend().handler_complete(invocation);
+    }
+}
+
+/// Writes the error response for an event whose handler panicked and applies the panic policy.
+/// Called on the thread the invocation of the event is attached to.
+fn respond_to_panic(msg: &str) {
+    // The invocation may not expect a response, in which case this will fail
+    if let Err(e) = send_response(Err(msg)) {
+        warn!("Could not write error response after handler panic: {}", e);
+    }
+    apply_panic_policy();
+}
+
+/// Runs the function, catching any panic. The panic is logged with its backtrace and counted,
+/// and the message for the error response is returned.
+fn catch_handler_panic<R, F: FnOnce() -> R>(f: F) -> Result<R, String> {
+    calling_handler(|| panic::catch_unwind(AssertUnwindSafe(f))).map_err(|payload| {
+        let message = format!("Handler panicked: {}", panic_message(&payload));
+        record_handler_panic(&message);
+        message
+    })
+}
+
+/// Runs the function with the backtrace of any panic on this thread captured regardless of RUST_BACKTRACE.
+/// The function must not unwind.
+fn calling_handler<R, F: FnOnce() -> R>(f: F) -> R {
+    let calling = CATCHING_HANDLER_PANIC.with(|c| c.replace(true));
+    let result = f();
+    CATCHING_HANDLER_PANIC.with(|c| c.set(calling));
+    result
+}
+
+/// Logs a caught panic with the backtrace recorded by the panic hook and counts it
+fn record_handler_panic(message: &str) {
+    let backtrace = PANIC_BACKTRACE
+        .with(|rc| rc.borrow_mut().take())
+        .unwrap_or_else(|| "no backtrace captured".to_owned());
+    let panics = HANDLER_PANICS.fetch_add(1, Ordering::Relaxed) + 1;
+    error!(
+        "{}. Total handler panics: {}\n{}",
+        message, panics, backtrace
+    );
+}
+
+/// Exits the process if the runtime was configured with [`PanicPolicy::Exit`]
+fn apply_panic_policy() {
+    let policy = PANIC_POLICY
+        .read()
+        .expect("panic policy lock poisoned")
+        .clone();
+    if let PanicPolicy::Exit(code) = policy {
+        error!("Exiting with code {} after handler panic", code);
+        log::logger().flush();
+        process::exit(code);
+    }
+}
+
+/// Installs a panic hook that records the backtrace of a panic so it can be logged once caught.
+/// Backtraces of panics in handlers are always captured, other panics only as enabled by RUST_BACKTRACE.
+/// The previously installed hook is still called.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let backtrace = if CATCHING_HANDLER_PANIC.try_with(Cell::get).unwrap_or(false) {
+                Backtrace::force_capture()
+            } else {
+                Backtrace::capture()
+            };
+            let backtrace = match backtrace.status() {
+                BacktraceStatus::Captured => Some(backtrace.to_string()),
+                _ => None,
+            };
+            let _ = PANIC_BACKTRACE.try_with(|rc| *rc.borrow_mut() = backtrace);
+            previous(info);
+        }));
+    });
+}
+
+/// Extracts the message from a panic payload
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+/// Converts the c context to our rust native context
+unsafe fn build_context(c_ctx: *const gg_lambda_context) -> GGResult<LambdaContext> {
+    let message = handler_read_message()?;
+    Ok(context_with_message(c_ctx, message))
+}
+
+/// Converts the c context to our rust native context with the message that was read
+unsafe fn context_with_message(c_ctx: *const gg_lambda_context, message: Vec<u8>) -> LambdaContext {
+    let function_arn = CStr::from_ptr((*c_ctx).function_arn)
+        .to_string_lossy()
+        .to_string();
+    let client_context = CStr::from_ptr((*c_ctx).client_context)
+        .to_string_lossy()
+        .to_string();
+    let context = LambdaContext::new(function_arn, client_context, message);
+    if let Some(e) = context.client_context_error() {
+        debug!("Could not decode client context: {}", e);
+    }
+    context
+}
+
+/// Returns the backend of the runtime that was last started
+fn backend() -> Arc<dyn Backend> {
+    Arc::clone(&BACKEND.read().expect("backend lock poisoned"))
+}
+
+/// Writes the response to the event being handled with the backend of the runtime.
+/// Handlers are called on the thread that owns the invocation of their event, so they can respond with this.
+pub(crate) fn send_response(result: Result<&[u8], &str>) -> GGResult<()> {
+    let backend = backend();
+    match result {
+        Ok(bytes) => backend.handler_write_response(bytes),
+        Err(e) => backend.handler_write_error(e),
+    }
+}
+
+/// Reads the message of the event being handled from the backend
+fn handler_read_message() -> GGResult<Vec<u8>> {
+    let mut message = Vec::new();
+    handler_input().read_to_end(&mut message)?;
+    Ok(message)
+}
+
+/// Returns a reader of the message of the event being handled, reading chunks of the configured size
+fn handler_input() -> HandlerInput {
+    HandlerInput::new(backend()).with_chunk_size(READ_CHUNK_SIZE.load(Ordering::Relaxed))
+}
+
+/// Wraps a Channel.
+/// This is mostly needed as there is no way to instantiate a static ref with a tuple (see CHANNEL above)
+struct ChannelHolder {
+    sender: Sender<LambdaContext>,
+    receiver: Receiver<LambdaContext>,
+    overflow_policy: OverflowPolicy,
+}
+
+impl ChannelHolder {
+    pub fn new(capacity: Option<usize>, overflow_policy: OverflowPolicy) -> Arc<Self> {
+        let (sender, receiver) = match capacity {
+            Some(cap) => bounded(cap),
+            None => unbounded(),
+        };
+        let holder = ChannelHolder {
+            sender,
+            receiver,
+            overflow_policy,
+        };
+        Arc::new(holder)
+    }
+
+    /// Replaces CHANNEL with a new channel and returns the receiver for it
+    fn install(
+        capacity: Option<usize>,
+        overflow_policy: OverflowPolicy,
+    ) -> Receiver<LambdaContext> {
+        let holder = Self::new(capacity, overflow_policy);
+        let receiver = holder.receiver.clone();
+        *CHANNEL.write().expect("handler channel lock poisoned") = holder;
+        receiver
+    }
+
+    /// Performs a send with CHANNEL, applying the overflow policy if the channel is full,
+    /// and coerces the error type
+    fn send(context: LambdaContext) -> GGResult<()> {
+        let holder = Self::current();
+        Self::send_to(
+            &holder.sender,
+            &holder.receiver,
+            &holder.overflow_policy,
+            context,
+        )
+    }
+
+    /// Sends to a channel, applying the overflow policy if the channel is full.
+    /// The receiver is used to make room when the policy is [`OverflowPolicy::DropOldest`]
+    fn send_to(
+        sender: &Sender<LambdaContext>,
+        receiver: &Receiver<LambdaContext>,
+        overflow_policy: &OverflowPolicy,
+        context: LambdaContext,
+    ) -> GGResult<()> {
+        if *overflow_policy == OverflowPolicy::Block {
+            return sender.send(context).map_err(GGError::from);
+        }
+
+        let mut context = context;
+        loop {
+            match sender.try_send(context) {
+                Ok(_) => return Ok(()),
+                Err(TrySendError::Disconnected(ctx)) => return Err(GGError::from(SendError(ctx))),
+                Err(TrySendError::Full(ctx)) => match overflow_policy {
+                    OverflowPolicy::DropOldest => {
+                        // make room by removing the oldest event, the queue may have drained in the meantime
+                        if let Ok(oldest) = receiver.try_recv() {
+                            Self::record_drop(&oldest, overflow_policy);
+                            complete_invocation(oldest.invocation());
+                        }
+                        context = ctx;
+                    }
+                    OverflowPolicy::Reject => {
+                        Self::record_drop(&ctx, overflow_policy);
+                        let result = with_invocation(ctx.invocation(), || {
+                            backend().handler_write_error("Event rejected, handler queue is full")
+                        });
+                        complete_invocation(ctx.invocation());
+                        return result;
+                    }
+                    _ => {
+                        Self::record_drop(&ctx, overflow_policy);
+                        complete_invocation(ctx.invocation());
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    }
+
+    /// Counts and logs an event that will not be handled
+    fn record_drop(context: &LambdaContext, overflow_policy: &OverflowPolicy) {
+        let dropped = DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "Handler queue full, dropped event for {} with policy {:?}. Total dropped events: {}",
+            context.function_arn, overflow_policy, dropped
+        );
+    }
+
+    /// Performs a recv on a receiver returned by install.
+    /// Returns None once the channel has been replaced by another runtime
+    fn recv(receiver: &Receiver<LambdaContext>) -> Option<LambdaContext> {
+        match receiver.recv().map_err(GGError::from) {
+            Ok(context) => Some(context),
+            Err(e) => {
+                info!("Handler channel closed: {}", e);
+                None
+            }
+        }
+    }
+
+    fn current() -> Arc<Self> {
+        Arc::clone(&CHANNEL.read().expect("handler channel lock poisoned"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::handler::{Handler, LambdaContext};
+    #[cfg(not(feature = "mock"))]
+    use crate::handler::ResponseHandler;
+    #[cfg(not(feature = "mock"))]
+    use crate::Initializer;
+    use crossbeam_channel::{bounded, Sender};
+    use std::ffi::CString;
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
+
+    lazy_static! {
+        // Tests that start a runtime share the global handler and channel, so they must not run concurrently
+        static ref RUNTIME_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    pub(crate) fn runtime_lock() -> MutexGuard<'static, ()> {
+        RUNTIME_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn test_build_context() {
+        let _lock = runtime_lock();
+        unsafe {
+            let my_message = b"My handlers message";
+            GG_LAMBDA_HANDLER_READ_BUFFER.with(|b| b.replace(my_message.to_owned().to_vec()));
+
+            let my_function_arn = "this is a function arn";
+            let client_ctx = "this is my client context";
+
+            let my_function_arn_c = CString::new(my_function_arn).unwrap();
+            let client_ctx_c = CString::new(client_ctx).unwrap();
 
             let lambda_context_c = Box::new(gg_lambda_context {
                 function_arn: my_function_arn_c.as_ptr(),
@@ -242,17 +1474,20 @@ mod test {
         }
     }
 
+    #[cfg(not(feature = "mock"))]
     #[derive(Clone)]
     struct TestHandler {
         sender: Sender<LambdaContext>,
     }
 
+    #[cfg(not(feature = "mock"))]
     impl TestHandler {
         fn new(sender: Sender<LambdaContext>) -> Self {
             TestHandler { sender }
         }
     }
 
+    #[cfg(not(feature = "mock"))]
     impl Handler for TestHandler {
         fn handle(&self, ctx: LambdaContext) {
             self.sender.send(ctx).expect("Could not send context");
@@ -262,6 +1497,7 @@ mod test {
     #[cfg(not(feature = "mock"))]
     #[test]
     fn test_handler() {
+        let _lock = runtime_lock();
         reset_test_state();
         let (sender, receiver) = bounded(1);
         let handler = TestHandler::new(sender);
@@ -284,4 +1520,629 @@ mod test {
             .expect("Context was sent within the timeout period");
         assert_eq!(ctx, context);
     }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    struct TestAsyncHandler {
+        sender: Sender<LambdaContext>,
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    impl AsyncHandler for TestAsyncHandler {
+        fn handle(&self, ctx: LambdaContext) -> futures::future::BoxFuture<'static, ()> {
+            let sender = self.sender.clone();
+            Box::pin(async move {
+                sender.send(ctx).expect("Could not send context");
+            })
+        }
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    #[test]
+    fn test_async_handler() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(1);
+        let runtime =
+            Runtime::default().with_async_handler(Some(Box::new(TestAsyncHandler { sender })));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+        let context = LambdaContext::new(
+            "my_async_function_arn".to_owned(),
+            "my_context".to_owned(),
+            b"my async bytes".to_vec(),
+        );
+        send_to_handler(context.clone());
+        let ctx = receiver
+            .recv_timeout(Duration::from_secs(120))
+            .expect("Context was sent within the timeout period");
+        assert_eq!(ctx, context);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    #[test]
+    fn test_async_handler_with_executor() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let rt = tokio::runtime::Builder::new()
+            .threaded_scheduler()
+            .build()
+            .unwrap();
+        let (sender, receiver) = bounded(1);
+        let runtime = Runtime::default()
+            .with_executor(Some(rt.handle().clone()))
+            .with_async_handler(Some(Box::new(TestAsyncHandler { sender })));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+        let context = LambdaContext::new(
+            "my_executor_function_arn".to_owned(),
+            "my_context".to_owned(),
+            b"my executor bytes".to_vec(),
+        );
+        send_to_handler(context.clone());
+        let ctx = receiver
+            .recv_timeout(Duration::from_secs(120))
+            .expect("Context was sent within the timeout period");
+        assert_eq!(ctx, context);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    #[test]
+    fn test_context_stream() {
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (runtime, mut stream) = Runtime::default().with_context_stream();
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+        let context = LambdaContext::new(
+            "my_stream_function_arn".to_owned(),
+            "my_context".to_owned(),
+            b"my stream bytes".to_vec(),
+        );
+        send_to_handler(context.clone());
+        let ctx = block_on(stream.next()).expect("Stream should yield the context");
+        assert_eq!(ctx, context);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    struct PanickingAsyncHandler {
+        sender: Sender<LambdaContext>,
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    impl AsyncHandler for PanickingAsyncHandler {
+        fn handle(&self, ctx: LambdaContext) -> futures::future::BoxFuture<'static, ()> {
+            let sender = self.sender.clone();
+            Box::pin(async move {
+                if ctx.message == b"panic" {
+                    panic!("I was asked to panic");
+                }
+                sender.send(ctx).expect("Could not send context");
+            })
+        }
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    #[test]
+    fn test_async_handler_panic_is_isolated() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(1);
+        let runtime = Runtime::default()
+            .with_async_handler(Some(Box::new(PanickingAsyncHandler { sender })));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        let panics = handler_panics();
+        send_to_handler(test_context("panic"));
+        send_to_handler(test_context("after panic"));
+        let ctx = receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("Handler stopped receiving events after panic");
+        assert_eq!(ctx.message, b"after panic");
+        // The futures run concurrently, so the panic may be counted after the other event is handled
+        for _ in 0..100 {
+            if handler_panics() > panics {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(handler_panics() > panics);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "mock")))]
+    #[test]
+    fn test_context_stream_consumer_panic_is_counted() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (runtime, stream) = Runtime::default().with_context_stream();
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        let panics = handler_panics();
+        let consumer = thread::spawn(move || {
+            let _stream = stream;
+            panic!("Consumer panicked");
+        });
+        assert!(consumer.join().is_err());
+        assert!(handler_panics() > panics);
+    }
+
+    fn test_context(msg: &str) -> LambdaContext {
+        LambdaContext::new(
+            "overflow_function_arn".to_owned(),
+            "my_context".to_owned(),
+            msg.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_overflow_drop_newest() {
+        let _lock = runtime_lock();
+        let receiver = ChannelHolder::install(Some(1), OverflowPolicy::DropNewest);
+        let dropped_before = dropped_events();
+        ChannelHolder::send(test_context("first")).unwrap();
+        ChannelHolder::send(test_context("second")).unwrap();
+        assert_eq!(dropped_events() - dropped_before, 1);
+        assert_eq!(receiver.try_recv().unwrap(), test_context("first"));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_overflow_drop_oldest() {
+        let _lock = runtime_lock();
+        let receiver = ChannelHolder::install(Some(1), OverflowPolicy::DropOldest);
+        let dropped_before = dropped_events();
+        ChannelHolder::send(test_context("first")).unwrap();
+        ChannelHolder::send(test_context("second")).unwrap();
+        assert_eq!(dropped_events() - dropped_before, 1);
+        assert_eq!(receiver.try_recv().unwrap(), test_context("second"));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_zero_queue_capacity_drop_oldest() {
+        let _lock = runtime_lock();
+        let runtime = Runtime::default()
+            .with_queue_capacity(Some(0))
+            .with_overflow_policy(OverflowPolicy::DropOldest);
+        assert_eq!(runtime.queue_capacity, Some(1));
+        let receiver = ChannelHolder::install(runtime.queue_capacity, runtime.overflow_policy);
+        let dropped_before = dropped_events();
+        ChannelHolder::send(test_context("first")).unwrap();
+        ChannelHolder::send(test_context("second")).unwrap();
+        assert_eq!(dropped_events() - dropped_before, 1);
+        assert_eq!(receiver.try_recv().unwrap(), test_context("second"));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_overflow_reject() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let receiver = ChannelHolder::install(Some(1), OverflowPolicy::Reject);
+        let dropped_before = dropped_events();
+        ChannelHolder::send(test_context("first")).unwrap();
+        ChannelHolder::send(test_context("second")).unwrap();
+        assert_eq!(dropped_events() - dropped_before, 1);
+        assert_eq!(receiver.try_recv().unwrap(), test_context("first"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR.with(|rc| {
+            assert_eq!(*rc.borrow(), "Event rejected, handler queue is full");
+        });
+    }
+
+    #[test]
+    fn test_channel_replaced_disconnects_receiver() {
+        let _lock = runtime_lock();
+        let receiver = ChannelHolder::install(None, OverflowPolicy::Block);
+        ChannelHolder::install(None, OverflowPolicy::Block);
+        assert!(ChannelHolder::recv(&receiver).is_none());
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_workers_run_in_parallel() {
+        use std::sync::Barrier;
+
+        struct BarrierHandler {
+            barrier: Arc<Barrier>,
+            sender: Sender<LambdaContext>,
+        }
+
+        impl Handler for BarrierHandler {
+            fn handle(&self, ctx: LambdaContext) {
+                // Both events must be handled at the same time for the barrier to be released
+                self.barrier.wait();
+                self.sender.send(ctx).expect("Could not send context");
+            }
+        }
+
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(2);
+        let handler = BarrierHandler {
+            barrier: Arc::new(Barrier::new(2)),
+            sender,
+        };
+        let runtime = Runtime::default()
+            .with_workers(2)
+            .with_handler(Some(Box::new(handler)));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+        send_to_handler(test_context("first"));
+        send_to_handler(test_context("second"));
+        let mut received = vec![
+            receiver.recv_timeout(Duration::from_secs(120)).unwrap(),
+            receiver.recv_timeout(Duration::from_secs(120)).unwrap(),
+        ];
+        received.sort_by(|a, b| a.message.cmp(&b.message));
+        assert_eq!(
+            received,
+            vec![test_context("first"), test_context("second")]
+        );
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_workers_preserve_order_by_key() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(40);
+        let runtime = Runtime::default()
+            .with_workers(4)
+            .with_ordering_key(Some(Box::new(|ctx| Some(ctx.function_arn.clone()))))
+            .with_handler(Some(Box::new(TestHandler::new(sender))));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        let keyed = |arn: &str, i: usize| {
+            LambdaContext::new(arn.to_owned(), "".to_owned(), i.to_string().into_bytes())
+        };
+        for i in 0..20 {
+            send_to_handler(keyed("arn_a", i));
+            send_to_handler(keyed("arn_b", i));
+        }
+
+        let mut a = vec![];
+        let mut b = vec![];
+        for _ in 0..40 {
+            let ctx = receiver.recv_timeout(Duration::from_secs(120)).unwrap();
+            if ctx.function_arn == "arn_a" {
+                a.push(ctx);
+            } else {
+                b.push(ctx);
+            }
+        }
+        assert_eq!(a, (0..20).map(|i| keyed("arn_a", i)).collect::<Vec<_>>());
+        assert_eq!(b, (0..20).map(|i| keyed("arn_b", i)).collect::<Vec<_>>());
+    }
+
+    struct GatedHandler {
+        gate: Receiver<()>,
+        sender: Sender<LambdaContext>,
+    }
+
+    impl Handler for GatedHandler {
+        fn handle(&self, ctx: LambdaContext) {
+            self.gate.recv().expect("Could not receive from gate");
+            self.sender.send(ctx).expect("Could not send context");
+        }
+    }
+
+    fn keyed_by_prefix() -> Box<OrderingKey> {
+        Box::new(|ctx| {
+            if ctx.message.starts_with(b"keyed") {
+                Some(ctx.function_arn.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    #[test]
+    fn test_workers_drain_keyed_and_shared_events() {
+        let _lock = runtime_lock();
+        let (gate_sender, gate) = unbounded();
+        let (sender, handled) = unbounded();
+        let (input, receiver) = unbounded();
+        let (done, done_receiver) = bounded::<()>(0);
+        spawn_workers(
+            Arc::new(GatedHandler { gate, sender }),
+            1,
+            Some(keyed_by_prefix()),
+            None,
+            OverflowPolicy::Block,
+            receiver,
+            done,
+        );
+        for i in 0..20 {
+            input.send(test_context(&format!("keyed {}", i))).unwrap();
+            input.send(test_context(&format!("shared {}", i))).unwrap();
+            gate_sender.send(()).unwrap();
+            gate_sender.send(()).unwrap();
+        }
+        // Disconnects the dispatch thread, the worker must still handle every queued event
+        drop(input);
+        assert_eq!(
+            done_receiver.recv_timeout(Duration::from_secs(120)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        assert_eq!(handled.try_iter().count(), 40);
+    }
+
+    #[test]
+    fn test_keyed_worker_queue_applies_overflow_policy() {
+        let _lock = runtime_lock();
+        let (gate_sender, gate) = unbounded();
+        let (sender, handled) = unbounded();
+        let (input, receiver) = unbounded();
+        let (done, done_receiver) = bounded::<()>(0);
+        spawn_workers(
+            Arc::new(GatedHandler { gate, sender }),
+            1,
+            Some(keyed_by_prefix()),
+            Some(1),
+            OverflowPolicy::DropNewest,
+            receiver,
+            done,
+        );
+        let dropped_before = dropped_events();
+        for i in 0..5 {
+            input.send(test_context(&format!("keyed {}", i))).unwrap();
+        }
+        drop(input);
+        // At most one event is being handled and one is queued, the rest are dropped
+        let start = Instant::now();
+        while dropped_events() - dropped_before < 3 && start.elapsed() < Duration::from_secs(120) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        for _ in 0..5 {
+            gate_sender.send(()).unwrap();
+        }
+        assert_eq!(
+            done_receiver.recv_timeout(Duration::from_secs(120)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        let dropped = dropped_events() - dropped_before;
+        assert!(dropped >= 3);
+        assert_eq!(handled.try_iter().count() + dropped, 5);
+    }
+
+    #[cfg(not(feature = "mock"))]
+    struct TestResponseHandler;
+
+    #[cfg(not(feature = "mock"))]
+    impl ResponseHandler for TestResponseHandler {
+        type Error = String;
+
+        fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
+            match ctx.message.as_slice() {
+                b"panic" => panic!("I was asked to panic"),
+                b"error" => Err("I was asked to fail".to_owned()),
+                msg => Ok([b"echo: ", msg].concat()),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_response_handler() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let runtime = Runtime::default().with_response_handler(Some(Box::new(TestResponseHandler)));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        send_to_handler(test_context("hello"));
+        GG_LAMBDA_HANDLER_WRITE_RESPONSE.with(|rc| assert_eq!(*rc.borrow(), b"echo: hello"));
+
+        send_to_handler(test_context("error"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR.with(|rc| assert_eq!(*rc.borrow(), "I was asked to fail"));
+
+        send_to_handler(test_context("panic"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR
+            .with(|rc| assert_eq!(*rc.borrow(), "Handler panicked: I was asked to panic"));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    struct LineHandler {
+        sender: Sender<(LambdaContext, Vec<String>)>,
+    }
+
+    #[cfg(not(feature = "mock"))]
+    impl StreamingHandler for LineHandler {
+        fn handle(&self, ctx: LambdaContext, input: HandlerInput) {
+            use std::io::BufRead;
+
+            let lines = input.lines().collect::<Result<Vec<_>, _>>().unwrap();
+            if lines == ["panic"] {
+                panic!("I was asked to panic");
+            }
+            self.sender.send((ctx, lines)).expect("Could not send lines");
+        }
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_streaming_handler() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(1);
+        let runtime = Runtime::default()
+            .with_read_chunk_size(4)
+            .with_streaming_handler(Some(Box::new(LineHandler { sender })));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        // The handler is called on this thread, reading the message as it goes
+        send_to_handler(test_context("first line\nsecond line"));
+        let (ctx, lines) = receiver.try_recv().expect("Handler was not called");
+        assert!(ctx.message.is_empty());
+        assert_eq!(lines, vec!["first line", "second line"]);
+
+        send_to_handler(test_context("panic"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR
+            .with(|rc| assert_eq!(*rc.borrow(), "Handler panicked: I was asked to panic"));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    struct PanickingHandler {
+        sender: Sender<LambdaContext>,
+    }
+
+    #[cfg(not(feature = "mock"))]
+    impl Handler for PanickingHandler {
+        fn handle(&self, ctx: LambdaContext) {
+            if ctx.message == b"panic" {
+                panic!("I was asked to panic");
+            }
+            self.sender.send(ctx).expect("Could not send context");
+        }
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_handler_panic_is_isolated() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, receiver) = bounded(1);
+        let runtime = Runtime::default().with_handler(Some(Box::new(PanickingHandler { sender })));
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        let panics = handler_panics();
+        send_to_handler(test_context("panic"));
+        send_to_handler(test_context("after panic"));
+        let ctx = receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("Handler stopped receiving events after panic");
+        assert_eq!(ctx.message, b"after panic");
+        assert!(handler_panics() > panics);
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_handler_panic_writes_error_response() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let (sender, _receiver) = bounded(1);
+        handle_isolated(&PanickingHandler { sender }, test_context("panic"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR
+            .with(|rc| assert_eq!(*rc.borrow(), "Handler panicked: I was asked to panic"));
+    }
+
+    #[test]
+    fn test_panic_hook_only_forces_backtraces_of_handlers() {
+        install_panic_hook();
+        let take_backtrace = || PANIC_BACKTRACE.with(|rc| rc.borrow_mut().take());
+
+        let _ = calling_handler(|| panic::catch_unwind(|| panic!("handler panic")));
+        assert!(take_backtrace().is_some());
+
+        let _ = panic::catch_unwind(|| panic!("other panic"));
+        let enabled = std::env::var("RUST_LIB_BACKTRACE")
+            .or_else(|_| std::env::var("RUST_BACKTRACE"))
+            .is_ok_and(|v| v != "0");
+        assert_eq!(take_backtrace().is_some(), enabled);
+    }
+
+    #[cfg(not(feature = "mock"))]
+    struct SlowHandler {
+        handled: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    #[cfg(not(feature = "mock"))]
+    impl Handler for SlowHandler {
+        fn handle(&self, ctx: LambdaContext) {
+            thread::sleep(Duration::from_millis(20));
+            self.handled.lock().unwrap().push(ctx.message);
+        }
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_shutdown_drains_queue() {
+        let _lock = runtime_lock();
+        reset_test_state();
+        let handled = Arc::new(Mutex::new(vec![]));
+        let hooks = Arc::new(Mutex::new(vec![]));
+        let (start_hooks, shutdown_hooks) = (Arc::clone(&hooks), Arc::clone(&hooks));
+        let (runtime, shutdown) = Runtime::default()
+            .with_runtime_option(RuntimeOption::Async)
+            .with_handler(Some(Box::new(SlowHandler {
+                handled: Arc::clone(&handled),
+            })))
+            .with_on_start(Some(Box::new(move || {
+                start_hooks.lock().unwrap().push("start")
+            })))
+            .with_on_shutdown(Some(Box::new(move || {
+                shutdown_hooks.lock().unwrap().push("shutdown")
+            })))
+            .with_shutdown_handle();
+        Initializer::default()
+            .with_runtime(runtime)
+            .init()
+            .expect("Initialization failed");
+
+        for msg in &["one", "two", "three"] {
+            send_to_handler(test_context(msg));
+        }
+        shutdown.shutdown();
+        shutdown.wait();
+
+        assert_eq!(
+            *handled.lock().unwrap(),
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+        assert_eq!(*hooks.lock().unwrap(), vec!["start", "shutdown"]);
+
+        send_to_handler(test_context("too late"));
+        GG_LAMBDA_HANDLER_WRITE_ERROR
+            .with(|rc| assert_eq!(*rc.borrow(), "Event rejected, lambda is shutting down"));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_sync_runtime_returns_after_shutdown() {
+        let _lock = runtime_lock();
+        // Without handler threads to wait for, the shutdown should not wait for the timeout
+        let (runtime, shutdown) = Runtime::default()
+            .with_shutdown_timeout(Duration::from_secs(60))
+            .with_shutdown_handle();
+        let (sender, receiver) = bounded(1);
+        thread::spawn(move || {
+            let result = Initializer::default().with_runtime(runtime).init();
+            sender.send(result).unwrap();
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        assert!(!shutdown.is_shutdown_requested());
+        shutdown.shutdown();
+        let result = receiver
+            .recv_timeout(Duration::from_secs(2))
+            .expect("Initializer::init did not return promptly after shutdown");
+        assert!(result.is_ok());
+    }
 }
@@ -9,25 +9,29 @@
 //! Provides the ability to acquire secrets that have been registered with the Greengrass group
 //! that the lambda function has been configured to run in.
 
-use crate::bindings::*;
-use crate::error::GGError;
-use crate::request::GGRequestResponse;
-use crate::with_request;
+#[cfg(any(feature = "async", not(all(test, feature = "mock"))))]
+use crate::backend::default_backend;
+use crate::backend::Backend;
+#[cfg(feature = "async")]
+use crate::blocking::BlockingPool;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::request::{GGRequestResponse, Request};
+#[cfg(not(all(test, feature = "mock")))]
+use crate::retry::with_retries;
+use crate::retry::RetryPolicy;
 use crate::GGResult;
-use serde::Deserialize;
-use std::convert::From;
-use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
 use std::default::Default;
-use std::ffi::CString;
-use std::os::raw::c_char;
-use std::ptr;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::time::Duration;
 
 #[cfg(all(test, feature = "mock"))]
 use self::mock::*;
 #[cfg(all(test, feature = "mock"))]
 use std::rc::Rc;
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct Secret {
     #[serde(rename = "ARN")]
@@ -43,7 +47,7 @@ pub struct Secret {
 impl Secret {
     /// For testing purposes.
     /// Can be called with default() to provide a string value
-    #[cfg(test)]
+    #[cfg(any(test, feature = "testing"))]
     pub fn with_secret_string(self, secret_string: Option<String>) -> Self {
         Secret {
             secret_string,
@@ -64,16 +68,54 @@ impl Secret {
 /// ```
 #[derive(Clone)]
 pub struct SecretClient {
+    #[cfg(not(all(test, feature = "mock")))]
+    backend: Arc<dyn Backend>,
+    #[cfg(not(all(test, feature = "mock")))]
+    retry_policy: Option<RetryPolicy>,
     #[cfg(all(test, feature = "mock"))]
     pub mocks: Rc<MockHolder>,
 }
 
+#[allow(clippy::derivable_impls)]
+impl Default for SecretClient {
+    fn default() -> Self {
+        SecretClient {
+            #[cfg(not(all(test, feature = "mock")))]
+            backend: default_backend(),
+            #[cfg(not(all(test, feature = "mock")))]
+            retry_policy: None,
+            #[cfg(all(test, feature = "mock"))]
+            mocks: Rc::default(),
+        }
+    }
+}
+
 impl SecretClient {
+    /// Use the specified backend instead of the C SDK
+    #[cfg(not(all(test, feature = "mock")))]
+    #[allow(clippy::needless_update)]
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        SecretClient { backend, ..self }
+    }
+
+    /// Optionally define a policy to retry failed requests with
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn with_retry_policy(self, retry_policy: Option<RetryPolicy>) -> Self {
+        SecretClient {
+            retry_policy,
+            ..self
+        }
+    }
+
     /// Creates a new SecretRequestBuilder using the specified secret_id
     ///
     /// * `secret_id` - The full arn or simple name of the secret
     pub fn for_secret_id(&self, secret_id: &str) -> SecretRequestBuilder {
         SecretRequestBuilder {
+            #[cfg(not(all(test, feature = "mock")))]
+            backend: Arc::clone(&self.backend),
+            #[cfg(not(all(test, feature = "mock")))]
+            retry_policy: self.retry_policy.clone(),
             secret_id: secret_id.to_owned(),
             secret_version: None,
             secret_version_stage: None,
@@ -85,22 +127,29 @@ impl SecretClient {
     /// Use the specified mock holder
     #[cfg(all(test, feature = "mock"))]
     pub fn with_mocks(self, mocks: Rc<MockHolder>) -> Self {
-        SecretClient { mocks, ..self }
+        SecretClient { mocks }
     }
-}
 
-impl Default for SecretClient {
-    fn default() -> Self {
-        SecretClient {
-            #[cfg(all(test, feature = "mock"))]
-            mocks: Rc::new(MockHolder::default()),
-        }
+    /// Mocks don't use a backend, so it is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_backend(self, _backend: Arc<dyn Backend>) -> Self {
+        self
+    }
+
+    /// Mocks aren't retried, so the policy is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_retry_policy(self, _retry_policy: Option<RetryPolicy>) -> Self {
+        self
     }
 }
 
 /// Used to construct a request to send to acquire a secret from Greengrass
 #[derive(Clone)]
 pub struct SecretRequestBuilder {
+    #[cfg(not(all(test, feature = "mock")))]
+    backend: Arc<dyn Backend>,
+    #[cfg(not(all(test, feature = "mock")))]
+    retry_policy: Option<RetryPolicy>,
     pub secret_id: String,
     pub secret_version: Option<String>,
     pub secret_version_stage: Option<String>,
@@ -128,15 +177,22 @@ impl SecretRequestBuilder {
     /// Executes the request and returns the secret
     #[cfg(not(all(test, feature = "mock")))]
     pub fn request(&self) -> GGResult<Option<Secret>> {
-        if let Some(response) = read_secret(self)? {
-            Ok(Some(self.parse_response(&response)?))
-        } else {
-            Ok(None)
-        }
-    }
-
-    fn parse_response(&self, response: &[u8]) -> GGResult<Secret> {
-        serde_json::from_slice::<Secret>(response).map_err(GGError::from)
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Get secret", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe {
+                backend.get_secret_value(
+                    req.handle(),
+                    &self.secret_id,
+                    self.secret_version.as_deref(),
+                    self.secret_version_stage.as_deref(),
+                )
+            }?;
+            let response = GGRequestResponse::from(status).read_json(&req)?;
+            req.close()?;
+            Ok(response)
+        })
     }
 
     // -----------------------------------
@@ -155,49 +211,114 @@ impl SecretRequestBuilder {
         }
     }
 }
-/// Fetch the specified secrete from the green grass secret store
-fn read_secret(builder: &SecretRequestBuilder) -> GGResult<Option<Vec<u8>>> {
-    unsafe {
-        let secret_name_c = CString::new(builder.secret_id.as_str()).map_err(GGError::from)?;
-        let maybe_secret_version_c = if let Some(secret_version) = &builder.secret_version {
-            Some(CString::new(secret_version.as_str()).map_err(GGError::from)?)
-        } else {
-            None
-        };
 
-        let maybe_secret_stage_c = if let Some(stage) = &builder.secret_version_stage {
-            Some(CString::new(stage.as_str()).map_err(GGError::from)?)
-        } else {
-            None
-        };
-
-        let mut req: gg_request = ptr::null_mut();
-        with_request!(req, {
-            let mut res = gg_request_result {
-                request_status: gg_request_status_GG_REQUEST_SUCCESS,
-            };
-
-            let fetch_res = gg_get_secret_value(
-                req,
-                secret_name_c.as_ptr(),
-                maybe_secret_version_c
-                    .map(|c| c.as_ptr())
-                    .unwrap_or(ptr::null() as *const c_char),
-                maybe_secret_stage_c
-                    .map(|c| c.as_ptr())
-                    .unwrap_or(ptr::null() as *const c_char),
-                &mut res,
-            );
-            GGError::from_code(fetch_res)?;
-            let response = GGRequestResponse::try_from(&res)?;
-            response.read(req)
-        })
+/// Secret retrieval as provided by [`SecretClient`], with the same signature whether or not the mock feature is enabled.
+///
+/// Business logic can be generic over this trait so that it can be tested with a fake client.
+pub trait Secrets {
+    /// Gets the secret with the optional version and stage. None if the secret does not exist.
+    ///
+    /// * `secret_id` - The full arn or simple name of the secret
+    fn get_secret(
+        &self,
+        secret_id: &str,
+        secret_version: Option<&str>,
+        secret_version_stage: Option<&str>,
+    ) -> GGResult<Option<Secret>>;
+}
+
+impl Secrets for SecretClient {
+    fn get_secret(
+        &self,
+        secret_id: &str,
+        secret_version: Option<&str>,
+        secret_version_stage: Option<&str>,
+    ) -> GGResult<Option<Secret>> {
+        self.for_secret_id(secret_id)
+            .with_secret_version(secret_version.map(str::to_owned))
+            .with_secret_version_stage(secret_version_stage.map(str::to_owned))
+            .request()
+    }
+}
+
+/// Acquires secrets without blocking the executor by running the requests of a [`SecretClient`] on a [`BlockingPool`].
+///
+/// ```rust,no_run
+/// use aws_greengrass_core_rust::secret::AsyncSecretClient;
+///
+/// # async fn run() {
+/// let secret_result = AsyncSecretClient::default()
+///     .get_secret("mysecret", None, Some("AWSCURRENT"))
+///     .await;
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[derive(Clone)]
+pub struct AsyncSecretClient {
+    backend: Arc<dyn Backend>,
+    pool: BlockingPool,
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "async")]
+impl Default for AsyncSecretClient {
+    fn default() -> Self {
+        AsyncSecretClient {
+            backend: default_backend(),
+            pool: BlockingPool::default(),
+            timeout: None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncSecretClient {
+    /// Gets the secret with the optional version and stage. None if the secret does not exist.
+    ///
+    /// * `secret_id` - The full arn or simple name of the secret
+    pub async fn get_secret(
+        &self,
+        secret_id: &str,
+        secret_version: Option<&str>,
+        secret_version_stage: Option<&str>,
+    ) -> GGResult<Option<Secret>> {
+        // The client is created on the pool as it isn't Send when mocked
+        let backend = Arc::clone(&self.backend);
+        let secret_id = secret_id.to_owned();
+        let secret_version = secret_version.map(str::to_owned);
+        let secret_version_stage = secret_version_stage.map(str::to_owned);
+        self.pool
+            .run_with_timeout(self.timeout, move || {
+                SecretClient::default().with_backend(backend).get_secret(
+                    &secret_id,
+                    secret_version.as_deref(),
+                    secret_version_stage.as_deref(),
+                )
+            })
+            .await
+    }
+
+    /// Use the specified backend instead of the C SDK
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        AsyncSecretClient { backend, ..self }
+    }
+
+    /// Run the requests on the specified pool instead of the shared one
+    pub fn with_pool(self, pool: BlockingPool) -> Self {
+        AsyncSecretClient { pool, ..self }
+    }
+
+    /// Fail requests that have not completed within the timeout with an [`std::io::ErrorKind::TimedOut`] error.
+    /// No timeout is applied by default.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        AsyncSecretClient { timeout, ..self }
     }
 }
 
 #[cfg(all(test, feature = "mock"))]
 mod mock {
     use super::*;
+    use crate::error::GGError;
     use crate::secret::SecretRequestBuilder;
     use std::cell::RefCell;
 
@@ -268,13 +389,17 @@ mod mock {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "mock"))]
+    use crate::bindings::*;
+    #[cfg(not(feature = "mock"))]
+    use crate::error::GGError;
 
-    const ARN: &'static str = "arn:aws:secretsmanager:us-west-2:701603852992:secret:greengrass-vendor-adapter-tls-secret-EZB0nM";
-    const VERSION_ID: &'static str = "55acd8c0-ff58-4197-9b69-8772ea761ed4";
-    const SECRET_STRING: &'static str = "foo";
-    const NAME: &'static str = "greengrass-vendor-adapter-tls-secret";
+    const ARN: &str = "arn:aws:secretsmanager:us-west-2:701603852992:secret:greengrass-vendor-adapter-tls-secret-EZB0nM";
+    const VERSION_ID: &str = "55acd8c0-ff58-4197-9b69-8772ea761ed4";
+    const SECRET_STRING: &str = "foo";
+    const NAME: &str = "greengrass-vendor-adapter-tls-secret";
     const CREATION_DATE: i64 = 1580414897159;
-    const VERSION_STAGE: &'static str = "AWSCURRENT";
+    const VERSION_STAGE: &str = "AWSCURRENT";
 
     fn version_stages() -> Vec<String> {
         vec![VERSION_STAGE.to_owned()]
@@ -7,14 +7,21 @@
  */
 
 use serde_json;
-use std::convert::TryFrom;
-use std::ffi::CString;
-use std::ptr;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::time::Duration;
 
-use crate::bindings::*;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::backend::default_backend;
+use crate::backend::Backend;
+#[cfg(feature = "async")]
+use crate::blocking::BlockingPool;
 use crate::error::GGError;
-use crate::request::GGRequestResponse;
-use crate::with_request;
+#[cfg(not(all(test, feature = "mock")))]
+use crate::request::{GGRequestResponse, Request, ResponseReader};
+#[cfg(not(all(test, feature = "mock")))]
+use crate::retry::with_retries;
+use crate::retry::RetryPolicy;
 use crate::GGResult;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -28,13 +35,47 @@ use self::mock::*;
 /// Information on shadow documents can be found at: https://docs.aws.amazon.com/iot/latest/developerguide/device-shadow-document.html#device-shadow-example
 #[derive(Clone)]
 pub struct ShadowClient {
+    #[cfg(not(all(test, feature = "mock")))]
+    backend: Arc<dyn Backend>,
+    #[cfg(not(all(test, feature = "mock")))]
+    retry_policy: Option<RetryPolicy>,
     /// When the mock feature is turned on this field will contain captured input
     /// and values to be returned
     #[cfg(all(test, feature = "mock"))]
     pub mocks: MockHolder,
 }
 
+#[allow(clippy::derivable_impls)]
+impl Default for ShadowClient {
+    fn default() -> Self {
+        ShadowClient {
+            #[cfg(not(all(test, feature = "mock")))]
+            backend: default_backend(),
+            #[cfg(not(all(test, feature = "mock")))]
+            retry_policy: None,
+            #[cfg(all(test, feature = "mock"))]
+            mocks: MockHolder::default(),
+        }
+    }
+}
+
 impl ShadowClient {
+    /// Use the specified backend instead of the C SDK
+    #[cfg(not(all(test, feature = "mock")))]
+    #[allow(clippy::needless_update)]
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        ShadowClient { backend, ..self }
+    }
+
+    /// Optionally define a policy to retry failed requests with
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn with_retry_policy(self, retry_policy: Option<RetryPolicy>) -> Self {
+        ShadowClient {
+            retry_policy,
+            ..self
+        }
+    }
+
     /// Get thing shadow for thing name.
     ///
     /// # Arguments
@@ -53,12 +94,47 @@ impl ShadowClient {
     /// ```
     #[cfg(not(all(test, feature = "mock")))]
     pub fn get_thing_shadow<T: DeserializeOwned>(&self, thing_name: &str) -> GGResult<Option<T>> {
-        if let Some(bytes) = read_thing_shadow(thing_name)? {
-            let json: T = serde_json::from_slice(&bytes).map_err(GGError::from)?;
-            Ok(Some(json))
-        } else {
-            Ok(None)
-        }
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Get thing shadow", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe { backend.get_thing_shadow(req.handle(), thing_name) }?;
+            let response = GGRequestResponse::from(status).read_json(&req)?;
+            req.close()?;
+            Ok(response)
+        })
+    }
+
+    /// Get thing shadow for thing name as a reader that streams the shadow document,
+    /// instead of deserializing it as [`ShadowClient::get_thing_shadow`] does.
+    ///
+    /// Returns Ok(None) if the shadow doesn't exist.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use aws_greengrass_core_rust::shadow::ShadowClient;
+    /// use std::io::Read;
+    ///
+    /// let client = ShadowClient::default();
+    /// if let Ok(Some(mut reader)) = client.get_thing_shadow_reader("my_thing") {
+    ///     let mut doc = String::new();
+    ///     reader.read_to_string(&mut doc).unwrap();
+    ///     println!("Retrieved: {}", doc);
+    /// };
+    /// ```
+    #[cfg(not(all(test, feature = "mock")))]
+    pub fn get_thing_shadow_reader(
+        &self,
+        thing_name: &str,
+    ) -> GGResult<Option<ResponseReader<'_>>> {
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Get thing shadow", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe { backend.get_thing_shadow(req.handle(), thing_name) }?;
+            GGRequestResponse::from(status).reader(req)
+        })
     }
 
     /// Updates a shadow thing with the specified document.
@@ -81,24 +157,15 @@ impl ShadowClient {
     #[cfg(not(all(test, feature = "mock")))]
     pub fn update_thing_shadow<T: Serialize>(&self, thing_name: &str, doc: &T) -> GGResult<()> {
         let json_string = serde_json::to_string(doc).map_err(GGError::from)?;
-        unsafe {
-            let thing_name_c = CString::new(thing_name).map_err(GGError::from)?;
-            let json_string_c = CString::new(json_string).map_err(GGError::from)?;
-            let mut req: gg_request = ptr::null_mut();
-            with_request!(req, {
-                let mut res = gg_request_result {
-                    request_status: gg_request_status_GG_REQUEST_SUCCESS,
-                };
-                let update_res = gg_update_thing_shadow(
-                    req,
-                    thing_name_c.as_ptr(),
-                    json_string_c.as_ptr(),
-                    &mut res,
-                );
-                GGError::from_code(update_res)?;
-                GGRequestResponse::try_from(&res)?.to_error_result(req)
-            })
-        }
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Update thing shadow", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status =
+                unsafe { backend.update_thing_shadow(req.handle(), thing_name, &json_string) }?;
+            GGRequestResponse::from(status).to_error_result(&req)?;
+            req.close()
+        })
     }
 
     /// Deletes thing shadow for thing name.
@@ -117,18 +184,14 @@ impl ShadowClient {
     /// ```
     #[cfg(not(all(test, feature = "mock")))]
     pub fn delete_thing_shadow(&self, thing_name: &str) -> GGResult<()> {
-        unsafe {
-            let thing_name_c = CString::new(thing_name).map_err(GGError::from)?;
-            let mut req: gg_request = ptr::null_mut();
-            with_request!(req, {
-                let mut res_c = gg_request_result {
-                    request_status: gg_request_status_GG_REQUEST_SUCCESS,
-                };
-                let delete_res = gg_delete_thing_shadow(req, thing_name_c.as_ptr(), &mut res_c);
-                GGError::from_code(delete_res)?;
-                GGRequestResponse::try_from(&res_c)?.to_error_result(req)
-            })
-        }
+        let backend = self.backend.as_ref();
+        with_retries(self.retry_policy.as_ref(), "Delete thing shadow", || {
+            let req = Request::new(backend)?;
+            // The request was created above with the same backend and is still open
+            let status = unsafe { backend.delete_thing_shadow(req.handle(), thing_name) }?;
+            GGRequestResponse::from(status).to_error_result(&req)?;
+            req.close()
+        })
     }
 
     // -----------------------------------
@@ -136,10 +199,7 @@ impl ShadowClient {
     // -----------------------------------
 
     #[cfg(all(test, feature = "mock"))]
-    pub fn get_thing_shadow<'a, T: DeserializeOwned>(
-        &self,
-        thing_name: &str,
-    ) -> GGResult<Option<T>> {
+    pub fn get_thing_shadow<T: DeserializeOwned>(&self, thing_name: &str) -> GGResult<Option<T>> {
         self.mocks
             .get_shadow_thing_inputs
             .borrow_mut()
@@ -177,36 +237,132 @@ impl ShadowClient {
             Ok(())
         }
     }
+
+    /// Mocks don't use a backend, so it is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_backend(self, _backend: Arc<dyn Backend>) -> Self {
+        self
+    }
+
+    /// Mocks aren't retried, so the policy is ignored
+    #[cfg(all(test, feature = "mock"))]
+    pub fn with_retry_policy(self, _retry_policy: Option<RetryPolicy>) -> Self {
+        self
+    }
 }
 
-impl Default for ShadowClient {
-    fn default() -> Self {
-        ShadowClient {
-            #[cfg(all(test, feature = "mock"))]
-            mocks: MockHolder::default(),
-        }
+/// The shadow operations of [`ShadowClient`], with the same signatures whether or not the mock feature is enabled.
+///
+/// Business logic can be generic over this trait so that it can be tested with a fake client.
+pub trait Shadow {
+    /// Get the thing shadow for the thing name, None if no shadow exists
+    fn get_thing_shadow<T: DeserializeOwned>(&self, thing_name: &str) -> GGResult<Option<T>>;
+
+    /// Updates the thing shadow for the thing name
+    fn update_thing_shadow<T: Serialize>(&self, thing_name: &str, doc: &T) -> GGResult<()>;
+
+    /// Deletes the thing shadow for the thing name
+    fn delete_thing_shadow(&self, thing_name: &str) -> GGResult<()>;
+}
+
+impl Shadow for ShadowClient {
+    fn get_thing_shadow<T: DeserializeOwned>(&self, thing_name: &str) -> GGResult<Option<T>> {
+        ShadowClient::get_thing_shadow(self, thing_name)
+    }
+
+    fn update_thing_shadow<T: Serialize>(&self, thing_name: &str, doc: &T) -> GGResult<()> {
+        ShadowClient::update_thing_shadow(self, thing_name, doc)
+    }
+
+    fn delete_thing_shadow(&self, thing_name: &str) -> GGResult<()> {
+        ShadowClient::delete_thing_shadow(self, thing_name)
     }
 }
 
-fn read_thing_shadow(thing_name: &str) -> GGResult<Option<Vec<u8>>> {
-    unsafe {
-        let thing_name_c = CString::new(thing_name).map_err(GGError::from)?;
-        let mut req: gg_request = ptr::null_mut();
-        with_request!(req, {
-            let mut res = gg_request_result {
-                request_status: gg_request_status_GG_REQUEST_SUCCESS,
-            };
-            let fetch_res = gg_get_thing_shadow(req, thing_name_c.as_ptr(), &mut res);
-            GGError::from_code(fetch_res)?;
-            GGRequestResponse::try_from(&res)?.read(req)
-        })
+/// Interacts with shadow documents without blocking the executor by running the calls of a [`ShadowClient`] on a [`BlockingPool`].
+///
+/// ```rust,no_run
+/// use aws_greengrass_core_rust::shadow::AsyncShadowClient;
+/// use serde_json::Value;
+///
+/// # async fn run() {
+/// if let Ok(maybe_json) = AsyncShadowClient::default().get_thing_shadow::<Value>("my_thing").await {
+///     println!("Retrieved: {:?}", maybe_json);
+/// }
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[derive(Clone, Default)]
+pub struct AsyncShadowClient {
+    client: ShadowClient,
+    pool: BlockingPool,
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncShadowClient {
+    /// Get the thing shadow for the thing name, None if no shadow exists
+    pub async fn get_thing_shadow<T>(&self, thing_name: &str) -> GGResult<Option<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let client = self.client.clone();
+        let thing_name = thing_name.to_owned();
+        self.pool
+            .run_with_timeout(self.timeout, move || client.get_thing_shadow(&thing_name))
+            .await
+    }
+
+    /// Updates the thing shadow for the thing name
+    pub async fn update_thing_shadow<T: Serialize>(
+        &self,
+        thing_name: &str,
+        doc: &T,
+    ) -> GGResult<()> {
+        let doc = serde_json::to_value(doc).map_err(GGError::from)?;
+        let client = self.client.clone();
+        let thing_name = thing_name.to_owned();
+        self.pool
+            .run_with_timeout(self.timeout, move || {
+                client.update_thing_shadow(&thing_name, &doc)
+            })
+            .await
+    }
+
+    /// Deletes the thing shadow for the thing name
+    pub async fn delete_thing_shadow(&self, thing_name: &str) -> GGResult<()> {
+        let client = self.client.clone();
+        let thing_name = thing_name.to_owned();
+        self.pool
+            .run_with_timeout(self.timeout, move || {
+                client.delete_thing_shadow(&thing_name)
+            })
+            .await
+    }
+
+    /// Use the specified backend instead of the C SDK
+    pub fn with_backend(self, backend: Arc<dyn Backend>) -> Self {
+        AsyncShadowClient {
+            client: self.client.with_backend(backend),
+            ..self
+        }
+    }
+
+    /// Run the calls on the specified pool instead of the shared one
+    pub fn with_pool(self, pool: BlockingPool) -> Self {
+        AsyncShadowClient { pool, ..self }
+    }
+
+    /// Fail calls that have not completed within the timeout with an [`std::io::ErrorKind::TimedOut`] error.
+    /// No timeout is applied by default.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        AsyncShadowClient { timeout, ..self }
     }
 }
 
 #[cfg(all(test, feature = "mock"))]
 pub mod mock {
     use crate::GGResult;
-    use serde::Serialize;
     use std::cell::RefCell;
 
     #[derive(Debug, Clone)]
@@ -265,10 +421,14 @@ pub mod mock {
 
 #[cfg(test)]
 pub mod test {
+    #[cfg(not(feature = "mock"))]
     use super::*;
+    #[cfg(not(feature = "mock"))]
+    use crate::bindings::*;
+    #[cfg(not(feature = "mock"))]
     use serde_json::Value;
 
-    pub const DEFAULT_SHADOW_DOC: &'static str = r#"{
+    pub const DEFAULT_SHADOW_DOC: &str = r#"{
     "state" : {
         "desired" : {
           "color" : "RED",
@@ -317,6 +477,25 @@ pub mod test {
         GG_REQUEST.with(|rc| assert!(!rc.borrow().is_default()));
     }
 
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_get_shadow_thing_reader() {
+        use std::io::Read;
+
+        reset_test_state();
+        GG_REQUEST_READ_BUFFER.with(|rc| rc.replace(DEFAULT_SHADOW_DOC.as_bytes().to_vec()));
+        let thing_name = "my_thing_get_reader";
+        let client = ShadowClient::default();
+        let mut reader = client.get_thing_shadow_reader(thing_name).unwrap().unwrap();
+        GG_SHADOW_THING_ARG.with(|rc| assert_eq!(*rc.borrow(), thing_name));
+        let mut doc = String::new();
+        reader.read_to_string(&mut doc).unwrap();
+        assert_eq!(doc, DEFAULT_SHADOW_DOC);
+        GG_CLOSE_REQUEST_COUNT.with(|rc| assert_eq!(*rc.borrow(), 0));
+        drop(reader);
+        GG_CLOSE_REQUEST_COUNT.with(|rc| assert_eq!(*rc.borrow(), 1));
+    }
+
     #[cfg(not(feature = "mock"))]
     #[test]
     fn test_delete_shadow_thing() {
@@ -0,0 +1,874 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides [`SimulatedCore`], an in-process stand in for a Greengrass core that can be used to test lambdas
+//! end to end without a device. Requires the `testing` feature.
+//!
+//! The simulated core is a [`Backend`], so it is injected into the clients with `with_backend`
+//! and into the logger with [`crate::log::init_log_with_backend`].
+//!
+//! * Published messages are recorded and passed to the handlers subscribed to a matching topic filter
+//! * Shadows are kept in memory with their versions and deltas. Updates are published to the
+//!   `$aws/things/<thing>/shadow/update/accepted` and `.../update/delta` topics.
+//! * Secrets are served from fixtures
+//! * Lambdas are invoked on the registered in-process handlers
+//! * Requests can be throttled with [`SimulatedCore::throttle`] to test retries
+//! * Log messages and handler responses are captured for assertions
+//!
+//! # Examples
+//!
+//! ```rust
+//! use aws_greengrass_core_rust::handler::{Handler, LambdaContext};
+//! use aws_greengrass_core_rust::iotdata::IOTDataClient;
+//! use aws_greengrass_core_rust::testing::SimulatedCore;
+//! use std::sync::Arc;
+//!
+//! struct MyHandler;
+//!
+//! impl Handler for MyHandler {
+//!     fn handle(&self, ctx: LambdaContext) {
+//!         println!("Received {:?} on {:?}", ctx.message, ctx.subject());
+//!     }
+//! }
+//!
+//! let core = Arc::new(SimulatedCore::default());
+//! core.subscribe("sensors/#", Box::new(MyHandler)).unwrap();
+//!
+//! let client = IOTDataClient::default().with_backend(core.clone());
+//! client.publish("sensors/kitchen", "21.5").unwrap();
+//! assert_eq!(core.published()[0].payload, b"21.5");
+//! ```
+use crate::backend::{Backend, InvokeArgs, RequestHandle};
+use crate::error::GGError;
+use crate::handler::{LambdaContext, ResponseHandler};
+use crate::iotdata::PublishOptions;
+use crate::lambda::InvokeType;
+use crate::request::{ErrorResponse, GGRequestStatus};
+use crate::router::TopicFilter;
+use crate::runtime::ShareableHandler;
+use crate::secret::Secret;
+use crate::GGResult;
+use log::Level;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The function ARN in the context of events delivered to subscribed handlers
+pub const SIMULATED_FUNCTION_ARN: &str =
+    "arn:aws:lambda:us-east-1:000000000000:function:simulated:1";
+
+/// A lambda registered with [`SimulatedCore::register_lambda`], with its error converted to a String
+type LambdaFn = dyn Fn(LambdaContext) -> Result<Vec<u8>, String> + Send + Sync;
+
+/// A message published through the simulated core
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishedMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// A message logged through the simulated core
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub message: String,
+}
+
+/// A response written with [`crate::lambda::LambdaClient::send_response`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerResponse {
+    Response(Vec<u8>),
+    Error(String),
+}
+
+#[derive(Default)]
+struct Shadow {
+    desired: Map<String, Value>,
+    reported: Map<String, Value>,
+    version: u64,
+}
+
+impl Shadow {
+    fn document(&self) -> Value {
+        let mut state = Map::new();
+        if !self.desired.is_empty() {
+            state.insert("desired".to_owned(), Value::Object(self.desired.clone()));
+        }
+        if !self.reported.is_empty() {
+            state.insert("reported".to_owned(), Value::Object(self.reported.clone()));
+        }
+        let delta = self.delta();
+        if !delta.is_empty() {
+            state.insert("delta".to_owned(), Value::Object(delta));
+        }
+        json!({ "state": state, "version": self.version, "timestamp": timestamp() })
+    }
+
+    fn delta(&self) -> Map<String, Value> {
+        delta(&self.desired, &self.reported)
+    }
+}
+
+struct Subscription {
+    filter: TopicFilter,
+    handler: Arc<ShareableHandler>,
+}
+
+/// An in-process simulation of a Greengrass core.
+/// See the [module documentation](self) for what is simulated.
+#[derive(Default)]
+pub struct SimulatedCore {
+    next_request: AtomicUsize,
+    /// The number of requests still to be throttled
+    throttle_count: AtomicUsize,
+    /// The unread response body of each open request
+    requests: Mutex<HashMap<usize, Vec<u8>>>,
+    subscriptions: Mutex<Vec<Subscription>>,
+    lambdas: Mutex<HashMap<String, Arc<LambdaFn>>>,
+    shadows: Mutex<HashMap<String, Shadow>>,
+    secrets: Mutex<HashMap<String, Secret>>,
+    published: Mutex<Vec<PublishedMessage>>,
+    logs: Mutex<Vec<LogRecord>>,
+    responses: Mutex<Vec<HandlerResponse>>,
+}
+
+impl SimulatedCore {
+    /// Delivers messages published to topics matching the filter to the handler.
+    /// Returns GGError::InvalidParameter if the filter is not a valid MQTT topic filter.
+    pub fn subscribe(&self, filter: &str, handler: Box<ShareableHandler>) -> GGResult<()> {
+        let filter = TopicFilter::new(filter)?;
+        lock(&self.subscriptions).push(Subscription {
+            filter,
+            handler: Arc::from(handler),
+        });
+        Ok(())
+    }
+
+    /// Registers a lambda to be run when its function ARN is invoked.
+    /// The response returned by the handler is returned to the caller of invoke_sync.
+    pub fn register_lambda<H>(&self, function_arn: &str, handler: H)
+    where
+        H: ResponseHandler + Send + Sync + 'static,
+    {
+        let lambda: Arc<LambdaFn> =
+            Arc::new(move |ctx| handler.handle(ctx).map_err(|e| format!("{}", e)));
+        lock(&self.lambdas).insert(function_arn.to_owned(), lambda);
+    }
+
+    /// Adds a secret that can be requested by its id, name or arn
+    pub fn put_secret(&self, secret_id: &str, secret: Secret) {
+        lock(&self.secrets).insert(secret_id.to_owned(), secret);
+    }
+
+    /// Throttles the next requests, which respond with [`GGRequestStatus::Again`] and a 429 error response
+    /// like Greengrass does when it can't keep up. See [`crate::retry::RetryPolicy`].
+    pub fn throttle(&self, requests: usize) {
+        self.throttle_count.store(requests, Ordering::SeqCst);
+    }
+
+    /// Every message published so far, including shadow updates
+    pub fn published(&self) -> Vec<PublishedMessage> {
+        lock(&self.published).clone()
+    }
+
+    /// The current shadow document of the thing, as returned by get_thing_shadow
+    pub fn shadow(&self, thing_name: &str) -> Option<Value> {
+        lock(&self.shadows).get(thing_name).map(Shadow::document)
+    }
+
+    /// Every message logged so far
+    pub fn logs(&self) -> Vec<LogRecord> {
+        lock(&self.logs).clone()
+    }
+
+    /// Every response written by handlers so far
+    pub fn responses(&self) -> Vec<HandlerResponse> {
+        lock(&self.responses).clone()
+    }
+
+    /// Records the message and passes it to the matching subscriptions.
+    /// Handlers are called without holding any locks so they can use the core themselves.
+    fn dispatch(&self, topic: &str, payload: &[u8]) {
+        lock(&self.published).push(PublishedMessage {
+            topic: topic.to_owned(),
+            payload: payload.to_vec(),
+        });
+        let handlers: Vec<Arc<ShareableHandler>> = lock(&self.subscriptions)
+            .iter()
+            .filter(|s| s.filter.matches(topic).is_some())
+            .map(|s| Arc::clone(&s.handler))
+            .collect();
+        let client_context = base64::encode(json!({ "custom": { "subject": topic } }).to_string());
+        for handler in handlers {
+            handler.handle(LambdaContext::new(
+                SIMULATED_FUNCTION_ARN.to_owned(),
+                client_context.clone(),
+                payload.to_vec(),
+            ));
+        }
+    }
+
+    fn respond(
+        &self,
+        request: RequestHandle,
+        status: GGRequestStatus,
+        body: Vec<u8>,
+    ) -> GGResult<GGRequestStatus> {
+        match lock(&self.requests).get_mut(&request.0) {
+            Some(buffer) => {
+                *buffer = body;
+                Ok(status)
+            }
+            None => Err(GGError::InvalidParameter),
+        }
+    }
+
+    fn respond_json(&self, request: RequestHandle, body: &Value) -> GGResult<GGRequestStatus> {
+        self.respond(
+            request,
+            GGRequestStatus::Success,
+            body.to_string().into_bytes(),
+        )
+    }
+
+    /// Responds with an error response body
+    fn fail(
+        &self,
+        request: RequestHandle,
+        status: GGRequestStatus,
+        code: u16,
+        message: String,
+    ) -> GGResult<GGRequestStatus> {
+        let error = ErrorResponse {
+            code,
+            message,
+            timestamp: timestamp(),
+        };
+        let body = serde_json::to_vec(&error).map_err(GGError::from)?;
+        self.respond(request, status, body)
+    }
+
+    /// Responds to the request as throttled if requests are being throttled
+    fn throttled(&self, request: RequestHandle) -> Option<GGResult<GGRequestStatus>> {
+        self.throttle_count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .ok()?;
+        let message = "Too many requests".to_owned();
+        Some(self.fail(request, GGRequestStatus::Again, 429, message))
+    }
+
+    fn shadow_not_found(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus> {
+        let message = format!("No shadow exists with name: '{}'", thing_name);
+        self.fail(request, GGRequestStatus::Handled, 404, message)
+    }
+
+    fn invalid_document(&self, request: RequestHandle, message: &str) -> GGResult<GGRequestStatus> {
+        self.fail(request, GGRequestStatus::Handled, 400, message.to_owned())
+    }
+}
+
+impl Backend for SimulatedCore {
+    fn request_init(&self) -> GGResult<RequestHandle> {
+        let id = self.next_request.fetch_add(1, Ordering::SeqCst) + 1;
+        lock(&self.requests).insert(id, vec![]);
+        Ok(RequestHandle(id))
+    }
+
+    unsafe fn request_read(&self, request: RequestHandle, buffer: &mut [u8]) -> GGResult<usize> {
+        let mut requests = lock(&self.requests);
+        let body = requests
+            .get_mut(&request.0)
+            .ok_or(GGError::InvalidParameter)?;
+        let read = buffer.len().min(body.len());
+        buffer[..read].copy_from_slice(&body[..read]);
+        body.drain(..read);
+        Ok(read)
+    }
+
+    unsafe fn request_close(&self, request: RequestHandle) -> GGResult<()> {
+        lock(&self.requests)
+            .remove(&request.0)
+            .map(|_| ())
+            .ok_or(GGError::InvalidParameter)
+    }
+
+    unsafe fn publish(
+        &self,
+        request: RequestHandle,
+        topic: &str,
+        payload: &[u8],
+        _: Option<&PublishOptions>,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        self.dispatch(topic, payload);
+        self.respond(request, GGRequestStatus::Success, vec![])
+    }
+
+    unsafe fn invoke(
+        &self,
+        request: RequestHandle,
+        args: &InvokeArgs,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        let lambda = match lock(&self.lambdas).get(args.function_arn) {
+            Some(lambda) => Arc::clone(lambda),
+            None => {
+                let message = format!("Function not found: {}", args.function_arn);
+                return self.fail(request, GGRequestStatus::Unhandled, 500, message);
+            }
+        };
+
+        // The core places the customer context in the custom section of the client context
+        let client_context = match base64::decode(args.customer_context)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+        {
+            Some(custom) => base64::encode(json!({ "custom": custom }).to_string()),
+            None => args.customer_context.to_owned(),
+        };
+        let ctx = LambdaContext::new(
+            args.function_arn.to_owned(),
+            client_context,
+            args.payload.map(<[u8]>::to_vec).unwrap_or_default(),
+        );
+        match (lambda(ctx), &args.invoke_type) {
+            (Ok(response), InvokeType::InvokeRequestResponse) => {
+                self.respond(request, GGRequestStatus::Success, response)
+            }
+            (Err(e), InvokeType::InvokeRequestResponse) => {
+                self.fail(request, GGRequestStatus::Handled, 500, e)
+            }
+            (_, InvokeType::InvokeEvent) => self.respond(request, GGRequestStatus::Success, vec![]),
+        }
+    }
+
+    unsafe fn get_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        match self.shadow(thing_name) {
+            Some(document) => self.respond_json(request, &document),
+            None => self.shadow_not_found(request, thing_name),
+        }
+    }
+
+    unsafe fn update_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+        document: &str,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        let document: Value = match serde_json::from_str(document) {
+            Ok(document) => document,
+            Err(_) => return self.invalid_document(request, "Payload contains invalid json"),
+        };
+        let state = match document.get("state").and_then(Value::as_object) {
+            Some(state) => state,
+            None => return self.invalid_document(request, "Missing required node: state"),
+        };
+        if ["desired", "reported"].iter().any(|s| {
+            state
+                .get(*s)
+                .is_some_and(|v| !v.is_object() && !v.is_null())
+        }) {
+            return self.invalid_document(request, "State node must be an object");
+        }
+
+        let (accepted, delta) = {
+            let mut shadows = lock(&self.shadows);
+            let shadow = shadows.entry(thing_name.to_owned()).or_default();
+            if let Some(version) = document.get("version").and_then(Value::as_u64) {
+                if version != shadow.version {
+                    drop(shadows);
+                    let message = "Version conflict".to_owned();
+                    return self.fail(request, GGRequestStatus::Handled, 409, message);
+                }
+            }
+            for (name, section) in [
+                ("desired", &mut shadow.desired),
+                ("reported", &mut shadow.reported),
+            ] {
+                match state.get(name) {
+                    Some(Value::Object(patch)) => merge(section, patch),
+                    Some(_) => section.clear(),
+                    None => {}
+                }
+            }
+            shadow.version += 1;
+            let accepted =
+                json!({ "state": state, "version": shadow.version, "timestamp": timestamp() });
+            let delta = shadow.delta();
+            let delta = if delta.is_empty() {
+                None
+            } else {
+                Some(json!({ "state": delta, "version": shadow.version, "timestamp": timestamp() }))
+            };
+            (accepted, delta)
+        };
+
+        let status = self.respond_json(request, &accepted)?;
+        let topic = format!("$aws/things/{}/shadow/update", thing_name);
+        self.dispatch(
+            &format!("{}/accepted", topic),
+            accepted.to_string().as_bytes(),
+        );
+        if let Some(delta) = delta {
+            self.dispatch(&format!("{}/delta", topic), delta.to_string().as_bytes());
+        }
+        Ok(status)
+    }
+
+    unsafe fn delete_thing_shadow(
+        &self,
+        request: RequestHandle,
+        thing_name: &str,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        let removed = lock(&self.shadows).remove(thing_name);
+        match removed {
+            Some(shadow) => {
+                let accepted = json!({ "version": shadow.version, "timestamp": timestamp() });
+                let status = self.respond_json(request, &accepted)?;
+                let topic = format!("$aws/things/{}/shadow/delete/accepted", thing_name);
+                self.dispatch(&topic, accepted.to_string().as_bytes());
+                Ok(status)
+            }
+            None => self.shadow_not_found(request, thing_name),
+        }
+    }
+
+    unsafe fn get_secret_value(
+        &self,
+        request: RequestHandle,
+        secret_id: &str,
+        version_id: Option<&str>,
+        version_stage: Option<&str>,
+    ) -> GGResult<GGRequestStatus> {
+        if let Some(throttled) = self.throttled(request) {
+            return throttled;
+        }
+        let secret = lock(&self.secrets)
+            .iter()
+            .find(|(id, s)| *id == secret_id || s.arn == secret_id || s.name == secret_id)
+            .map(|(_, s)| s.clone())
+            .filter(|s| version_id.is_none_or(|v| v == s.version_id))
+            .filter(|s| version_stage.is_none_or(|v| s.version_stages.iter().any(|s| s == v)));
+        match secret {
+            Some(secret) => {
+                let body = serde_json::to_vec(&secret).map_err(GGError::from)?;
+                self.respond(request, GGRequestStatus::Success, body)
+            }
+            None => {
+                let message = "Secrets Manager can't find the specified secret.".to_owned();
+                self.fail(request, GGRequestStatus::Handled, 404, message)
+            }
+        }
+    }
+
+    fn log(&self, level: Level, message: &str) -> GGResult<()> {
+        lock(&self.logs).push(LogRecord {
+            level,
+            message: message.to_owned(),
+        });
+        Ok(())
+    }
+
+    /// Events are passed directly to handlers, so there is never an event to read
+    fn handler_read(&self, _: &mut [u8]) -> GGResult<usize> {
+        Ok(0)
+    }
+
+    fn handler_write_response(&self, response: &[u8]) -> GGResult<()> {
+        lock(&self.responses).push(HandlerResponse::Response(response.to_vec()));
+        Ok(())
+    }
+
+    fn handler_write_error(&self, message: &str) -> GGResult<()> {
+        lock(&self.responses).push(HandlerResponse::Error(message.to_owned()));
+        Ok(())
+    }
+}
+
+/// Locks the mutex, ignoring poisoning by a panicking handler
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Merges the patch into the section. Null values remove keys.
+fn merge(section: &mut Map<String, Value>, patch: &Map<String, Value>) {
+    for (key, value) in patch {
+        match value {
+            Value::Null => {
+                section.remove(key);
+            }
+            Value::Object(nested) => {
+                let entry = section
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if !entry.is_object() {
+                    *entry = Value::Object(Map::new());
+                }
+                if let Value::Object(entry) = entry {
+                    merge(entry, nested);
+                }
+            }
+            _ => {
+                section.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The desired values that differ from the reported values
+fn delta(desired: &Map<String, Value>, reported: &Map<String, Value>) -> Map<String, Value> {
+    let mut delta = Map::new();
+    for (key, value) in desired {
+        match (value, reported.get(key)) {
+            (Value::Object(desired), Some(Value::Object(reported))) => {
+                let nested = self::delta(desired, reported);
+                if !nested.is_empty() {
+                    delta.insert(key.clone(), Value::Object(nested));
+                }
+            }
+            (value, Some(reported)) if value == reported => {}
+            (value, _) => {
+                delta.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    delta
+}
+
+#[cfg(all(test, not(feature = "mock")))]
+mod test {
+    use super::*;
+    use crate::handler::Handler;
+    use crate::iotdata::IOTDataClient;
+    use crate::lambda::{InvokeOptions, LambdaClient};
+    use crate::secret::SecretClient;
+    use crate::shadow::ShadowClient;
+
+    struct Forwarder {
+        client: IOTDataClient,
+    }
+
+    impl Handler for Forwarder {
+        fn handle(&self, ctx: LambdaContext) {
+            let topic = format!("forwarded/{}", ctx.subject().unwrap());
+            self.client.publish(&topic, &ctx.message).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_publish_routes_to_subscriptions() {
+        let core = Arc::new(SimulatedCore::default());
+        let client = IOTDataClient::default().with_backend(core.clone());
+        core.subscribe(
+            "sensors/+",
+            Box::new(Forwarder {
+                client: client.clone(),
+            }),
+        )
+        .unwrap();
+        assert!(core
+            .subscribe(
+                "bad/#/filter",
+                Box::new(Forwarder {
+                    client: client.clone()
+                })
+            )
+            .is_err());
+
+        client.publish("sensors/kitchen", "21.5").unwrap();
+        client.publish("other/topic", "ignored").unwrap();
+
+        let published = core.published();
+        let topics: Vec<&str> = published.iter().map(|m| m.topic.as_str()).collect();
+        assert_eq!(
+            topics,
+            vec![
+                "sensors/kitchen",
+                "forwarded/sensors/kitchen",
+                "other/topic"
+            ]
+        );
+        assert_eq!(published[1].payload, b"21.5");
+    }
+
+    #[test]
+    fn test_shadows() {
+        let core = Arc::new(SimulatedCore::default());
+        let client = ShadowClient::default().with_backend(core.clone());
+        assert!(client.get_thing_shadow::<Value>("thing").unwrap().is_none());
+
+        client
+            .update_thing_shadow(
+                "thing",
+                &json!({"state": {"desired": {"on": true, "level": 5}}}),
+            )
+            .unwrap();
+        client
+            .update_thing_shadow(
+                "thing",
+                &json!({"state": {"reported": {"on": false, "level": 5}}}),
+            )
+            .unwrap();
+        let shadow: Value = client.get_thing_shadow("thing").unwrap().unwrap();
+        assert_eq!(shadow["version"], 2);
+        assert_eq!(shadow["state"]["delta"], json!({"on": true}));
+
+        let conflict = client.update_thing_shadow(
+            "thing",
+            &json!({"state": {"reported": {"on": true}}, "version": 1}),
+        );
+        assert!(conflict.is_err());
+        client
+            .update_thing_shadow(
+                "thing",
+                &json!({"state": {"reported": {"on": true}}, "version": 2}),
+            )
+            .unwrap();
+        assert!(core.shadow("thing").unwrap()["state"]
+            .get("delta")
+            .is_none());
+
+        let topics: Vec<String> = core.published().into_iter().map(|m| m.topic).collect();
+        assert_eq!(
+            topics,
+            vec![
+                "$aws/things/thing/shadow/update/accepted",
+                "$aws/things/thing/shadow/update/delta",
+                "$aws/things/thing/shadow/update/accepted",
+                "$aws/things/thing/shadow/update/delta",
+                "$aws/things/thing/shadow/update/accepted",
+            ]
+        );
+
+        client.delete_thing_shadow("thing").unwrap();
+        assert!(core.shadow("thing").is_none());
+    }
+
+    #[test]
+    fn test_secrets() {
+        let core = Arc::new(SimulatedCore::default());
+        core.put_secret(
+            "mysecret",
+            Secret {
+                arn: "arn:aws:secretsmanager:mysecret".to_owned(),
+                version_id: "v1".to_owned(),
+                version_stages: vec!["AWSCURRENT".to_owned()],
+                ..Secret::default()
+            }
+            .with_secret_string(Some("hunter2".to_owned())),
+        );
+        let client = SecretClient::default().with_backend(core);
+
+        let secret = client
+            .for_secret_id("arn:aws:secretsmanager:mysecret")
+            .with_secret_version_stage(Some("AWSCURRENT".to_owned()))
+            .request()
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.secret_string, Some("hunter2".to_owned()));
+        assert!(client
+            .for_secret_id("mysecret")
+            .with_secret_version(Some("v2".to_owned()))
+            .request()
+            .unwrap()
+            .is_none());
+        assert!(client.for_secret_id("other").request().unwrap().is_none());
+    }
+
+    struct Greeter;
+
+    impl ResponseHandler for Greeter {
+        type Error = String;
+
+        fn handle(&self, ctx: LambdaContext) -> Result<Vec<u8>, Self::Error> {
+            match ctx.custom::<Value>().unwrap() {
+                Some(custom) => Ok(format!("Hello {}", custom["name"]).into_bytes()),
+                None => Err("No name".to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invoke_and_capture() {
+        let core = Arc::new(SimulatedCore::default());
+        core.register_lambda("greeter_arn", Greeter);
+        let client = LambdaClient::default().with_backend(core.clone());
+
+        let options = InvokeOptions::new(
+            "greeter_arn".to_owned(),
+            json!({"name": "Bob"}),
+            "1".to_owned(),
+        );
+        let response = client.invoke_sync(options, None::<&[u8]>).unwrap();
+        assert_eq!(response, Some(br#"Hello "Bob""#.to_vec()));
+
+        let options = InvokeOptions::new("greeter_arn".to_owned(), json!(null), "1".to_owned());
+        assert!(client.invoke_sync(options, None::<&[u8]>).is_err());
+        let options = InvokeOptions::new("missing_arn".to_owned(), json!({}), "1".to_owned());
+        assert!(client.invoke_sync(options, None::<&[u8]>).is_err());
+
+        client.send_response(Ok(b"done")).unwrap();
+        client.send_response(Err("failed")).unwrap();
+        assert_eq!(
+            core.responses(),
+            vec![
+                HandlerResponse::Response(b"done".to_vec()),
+                HandlerResponse::Error("failed".to_owned())
+            ]
+        );
+
+        core.log(Level::Warn, "careful").unwrap();
+        assert_eq!(
+            core.logs(),
+            vec![LogRecord {
+                level: Level::Warn,
+                message: "careful".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_throttled_requests_retried() {
+        use crate::retry::RetryPolicy;
+        use std::time::Duration;
+
+        let core = Arc::new(SimulatedCore::default());
+        let policy = RetryPolicy::default().with_base_delay(Duration::from_millis(1));
+        let client = IOTDataClient::default()
+            .with_backend(core.clone())
+            .with_retry_policy(Some(policy.clone()));
+        core.throttle(2);
+        client.publish("sensors/temp", "21.5").unwrap();
+        assert_eq!(core.published().len(), 1);
+
+        core.throttle(3);
+        match client.publish("sensors/temp", "21.5") {
+            Err(GGError::RetryFailed(3, e)) => assert!(crate::retry::is_throttled(&e)),
+            _ => panic!("Expected RetryFailed"),
+        }
+        core.throttle(0);
+
+        let shadows = ShadowClient::default()
+            .with_backend(core.clone())
+            .with_retry_policy(Some(policy.clone()));
+        core.throttle(1);
+        assert!(shadows
+            .get_thing_shadow::<Value>("thing")
+            .unwrap()
+            .is_none());
+
+        let secrets = SecretClient::default().with_backend(core.clone());
+        core.throttle(1);
+        assert!(secrets.for_secret_id("mysecret").request().is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_clients() {
+        use crate::blocking::BlockingPool;
+        use crate::iotdata::AsyncIOTDataClient;
+        use crate::lambda::AsyncLambdaClient;
+        use crate::secret::AsyncSecretClient;
+        use crate::shadow::AsyncShadowClient;
+        use std::time::Duration;
+
+        let core = Arc::new(SimulatedCore::default());
+        let pool = BlockingPool::new(2);
+
+        let iotdata = AsyncIOTDataClient::default()
+            .with_backend(core.clone())
+            .with_pool(pool.clone());
+        iotdata
+            .publish("sensors/temp", b"21.5".to_vec())
+            .await
+            .unwrap();
+        iotdata.publish_json("sensors/humidity", 40).await.unwrap();
+        let topics: Vec<String> = core.published().into_iter().map(|m| m.topic).collect();
+        assert_eq!(topics, vec!["sensors/temp", "sensors/humidity"]);
+
+        let shadow = AsyncShadowClient::default()
+            .with_backend(core.clone())
+            .with_pool(pool.clone());
+        assert!(shadow
+            .get_thing_shadow::<Value>("thing")
+            .await
+            .unwrap()
+            .is_none());
+        shadow
+            .update_thing_shadow("thing", &json!({"state": {"desired": {"on": true}}}))
+            .await
+            .unwrap();
+        let document: Value = shadow.get_thing_shadow("thing").await.unwrap().unwrap();
+        assert_eq!(document["state"]["desired"], json!({"on": true}));
+        shadow.delete_thing_shadow("thing").await.unwrap();
+        assert!(core.shadow("thing").is_none());
+
+        core.put_secret(
+            "mysecret",
+            Secret::default().with_secret_string(Some("hunter2".to_owned())),
+        );
+        let secrets = AsyncSecretClient::default()
+            .with_backend(core.clone())
+            .with_pool(pool.clone());
+        let secret = secrets.get_secret("mysecret", None, None).await.unwrap();
+        assert_eq!(secret.unwrap().secret_string, Some("hunter2".to_owned()));
+        assert!(secrets
+            .get_secret("other", None, None)
+            .await
+            .unwrap()
+            .is_none());
+
+        core.register_lambda("greeter_arn", Greeter);
+        let lambda = AsyncLambdaClient::default()
+            .with_backend(core.clone())
+            .with_pool(pool)
+            .with_timeout(Some(Duration::from_secs(5)));
+        let options = InvokeOptions::new(
+            "greeter_arn".to_owned(),
+            json!({"name": "Bob"}),
+            "1".to_owned(),
+        );
+        let response = lambda.invoke_sync(options, None::<Vec<u8>>).await.unwrap();
+        assert_eq!(response, Some(br#"Hello "Bob""#.to_vec()));
+        lambda.send_response(Ok(b"done")).await.unwrap();
+        assert_eq!(
+            core.responses(),
+            vec![HandlerResponse::Response(b"done".to_vec())]
+        );
+    }
+}
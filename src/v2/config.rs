@@ -0,0 +1,181 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides access to the configuration of components
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aws_greengrass_core_rust::v2::IpcConnection;
+//! use aws_greengrass_core_rust::v2::config::ConfigClient;
+//!
+//! let client = ConfigClient::new(IpcConnection::connect().unwrap());
+//! let interval: Option<u64> = client.get_configuration(&["pollInterval"]).unwrap();
+//! for update in client.subscribe_to_configuration_update(&[]).unwrap() {
+//!     println!("Configuration changed: {:?}", update);
+//! }
+//! ```
+use crate::error::GGError;
+use crate::v2::{not_found_as_none, IpcConnection, Subscription};
+use crate::GGResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Notification that a component's configuration has changed
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigurationUpdate {
+    pub component_name: String,
+    /// The path of the configuration value that changed
+    pub key_path: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigurationUpdateEvents {
+    configuration_update_event: ConfigurationUpdate,
+}
+
+/// Reads, updates and watches component configuration.
+/// The configuration of this component is used unless a component name is specified.
+#[derive(Clone)]
+pub struct ConfigClient {
+    connection: IpcConnection,
+    /// The component whose configuration should be read
+    pub component_name: Option<String>,
+}
+
+impl ConfigClient {
+    pub fn new(connection: IpcConnection) -> Self {
+        ConfigClient {
+            connection,
+            component_name: None,
+        }
+    }
+
+    /// Read the configuration of another component
+    pub fn with_component_name(self, component_name: Option<String>) -> Self {
+        ConfigClient {
+            component_name,
+            ..self
+        }
+    }
+
+    fn request(&self, key_path: &[&str]) -> Value {
+        let mut request = json!({ "keyPath": key_path });
+        if let Some(component_name) = &self.component_name {
+            request["componentName"] = json!(component_name);
+        }
+        request
+    }
+
+    /// Get the configuration value at the key path. An empty key path returns the whole configuration.
+    /// None if there is no value at the key path.
+    pub fn get_configuration<T: DeserializeOwned>(&self, key_path: &[&str]) -> GGResult<Option<T>> {
+        let request = self.request(key_path);
+        match not_found_as_none(self.connection.call("GetConfiguration", &request))? {
+            Some(mut response) => serde_json::from_value(response["value"].take())
+                .map(Some)
+                .map_err(GGError::from),
+            None => Ok(None),
+        }
+    }
+
+    /// Merges the value into this component's configuration at the key path.
+    /// The value must serialize to a JSON object.
+    pub fn update_configuration<T: Serialize>(&self, key_path: &[&str], value: &T) -> GGResult<()> {
+        let value = serde_json::to_value(value).map_err(GGError::from)?;
+        if !value.is_object() {
+            return Err(GGError::InvalidParameter);
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        let request = json!({
+            "keyPath": key_path,
+            "timestamp": timestamp,
+            "valueToMerge": value,
+        });
+        self.connection
+            .call("UpdateConfiguration", &request)
+            .map(|_| ())
+    }
+
+    /// Subscribes to changes to the configuration at or below the key path
+    pub fn subscribe_to_configuration_update(
+        &self,
+        key_path: &[&str],
+    ) -> GGResult<Subscription<ConfigurationUpdate>> {
+        self.connection.subscribe(
+            "SubscribeToConfigurationUpdate",
+            &self.request(key_path),
+            decode_update,
+        )
+    }
+}
+
+fn decode_update(event: Value) -> GGResult<ConfigurationUpdate> {
+    serde_json::from_value::<ConfigurationUpdateEvents>(event)
+        .map(|events| events.configuration_update_event)
+        .map_err(GGError::from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::v2::test::*;
+
+    #[test]
+    fn test_configuration() {
+        let (connection, requests) = connect(Box::new(|request| match operation(request) {
+            "GetConfiguration" => vec![reply(
+                json!({"componentName": "com.example.Other", "value": {"pollInterval": 30}}),
+            )],
+            "SubscribeToConfigurationUpdate" => vec![
+                reply(json!({})),
+                reply(json!({"configurationUpdateEvent": {
+                    "componentName": "com.example.Mine", "keyPath": ["pollInterval"]
+                }})),
+            ],
+            _ => vec![reply(json!({}))],
+        }));
+        let client = ConfigClient::new(connection);
+
+        let config: Option<Value> = client
+            .clone()
+            .with_component_name(Some("com.example.Other".to_owned()))
+            .get_configuration(&[])
+            .unwrap();
+        assert_eq!(config, Some(json!({"pollInterval": 30})));
+        assert!(client
+            .update_configuration(&["nested"], &json!("not an object"))
+            .is_err());
+        client
+            .update_configuration(&["nested"], &json!({"enabled": true}))
+            .unwrap();
+        let update = client
+            .subscribe_to_configuration_update(&[])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.key_path, vec!["pollInterval"]);
+
+        let request = requests.recv().unwrap();
+        assert_eq!(
+            payload(&request),
+            json!({"keyPath": [], "componentName": "com.example.Other"})
+        );
+        let request = payload(&requests.recv().unwrap());
+        assert_eq!(request["valueToMerge"], json!({"enabled": true}));
+        assert_eq!(request["keyPath"], json!(["nested"]));
+    }
+}
@@ -0,0 +1,285 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Encoding and decoding of the AWS event stream binary message format used by Greengrass v2 IPC.
+//!
+//! Each message is laid out as:
+//! * total length (u32), headers length (u32) and a CRC32 of those 8 bytes
+//! * the headers, each as a name length (u8), name, type (u8) and value
+//! * the payload
+//! * a CRC32 of everything before it
+use std::io::{self, Read, Write};
+
+const PRELUDE_LEN: usize = 12;
+const CRC_LEN: usize = 4;
+/// The largest message accepted, as defined by the event stream specification
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// The value of a message header
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HeaderValue {
+    Bool(bool),
+    Byte(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Bytes(Vec<u8>),
+    String(String),
+    Timestamp(i64),
+    Uuid([u8; 16]),
+}
+
+/// A single event stream message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Message {
+    pub headers: Vec<(String, HeaderValue)>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn with_header(mut self, name: &str, value: HeaderValue) -> Self {
+        self.headers.push((name.to_owned(), value));
+        self
+    }
+
+    pub fn with_payload(self, payload: Vec<u8>) -> Self {
+        Message { payload, ..self }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&HeaderValue> {
+        self.headers.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    /// Returns the value of an Int32 header, or 0 if it is missing
+    pub fn int32(&self, name: &str) -> i32 {
+        match self.header(name) {
+            Some(HeaderValue::Int32(i)) => *i,
+            _ => 0,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.header(name) {
+            Some(HeaderValue::String(s)) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut headers = Vec::new();
+        for (name, value) in &self.headers {
+            headers.push(name.len() as u8);
+            headers.extend_from_slice(name.as_bytes());
+            encode_value(value, &mut headers);
+        }
+
+        let total_len = PRELUDE_LEN + headers.len() + self.payload.len() + CRC_LEN;
+        let mut bytes = Vec::with_capacity(total_len);
+        bytes.extend_from_slice(&(total_len as u32).to_be_bytes());
+        bytes.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        let prelude_crc = crc32(&bytes);
+        bytes.extend_from_slice(&prelude_crc.to_be_bytes());
+        bytes.extend_from_slice(&headers);
+        bytes.extend_from_slice(&self.payload);
+        let message_crc = crc32(&bytes);
+        bytes.extend_from_slice(&message_crc.to_be_bytes());
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.encode())?;
+        writer.flush()
+    }
+
+    /// Reads one complete message, validating both checksums
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Message> {
+        let mut prelude = [0u8; PRELUDE_LEN];
+        reader.read_exact(&mut prelude)?;
+        let total_len = read_u32(&prelude[0..4]) as usize;
+        let headers_len = read_u32(&prelude[4..8]) as usize;
+        if read_u32(&prelude[8..12]) != crc32(&prelude[..8]) {
+            return Err(invalid("prelude checksum mismatch"));
+        }
+        if total_len > MAX_MESSAGE_LEN || total_len < PRELUDE_LEN + headers_len + CRC_LEN {
+            return Err(invalid("invalid message length"));
+        }
+
+        let mut bytes = prelude.to_vec();
+        bytes.resize(total_len, 0);
+        reader.read_exact(&mut bytes[PRELUDE_LEN..])?;
+        let crc_start = total_len - CRC_LEN;
+        if read_u32(&bytes[crc_start..]) != crc32(&bytes[..crc_start]) {
+            return Err(invalid("message checksum mismatch"));
+        }
+
+        let headers_end = PRELUDE_LEN + headers_len;
+        Ok(Message {
+            headers: decode_headers(&bytes[PRELUDE_LEN..headers_end])?,
+            payload: bytes[headers_end..crc_start].to_vec(),
+        })
+    }
+}
+
+fn encode_value(value: &HeaderValue, out: &mut Vec<u8>) {
+    match value {
+        HeaderValue::Bool(true) => out.push(0),
+        HeaderValue::Bool(false) => out.push(1),
+        HeaderValue::Byte(b) => {
+            out.push(2);
+            out.extend_from_slice(&b.to_be_bytes());
+        }
+        HeaderValue::Int16(i) => {
+            out.push(3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        HeaderValue::Int32(i) => {
+            out.push(4);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        HeaderValue::Int64(i) => {
+            out.push(5);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        HeaderValue::Bytes(b) => {
+            out.push(6);
+            out.extend_from_slice(&(b.len() as u16).to_be_bytes());
+            out.extend_from_slice(b);
+        }
+        HeaderValue::String(s) => {
+            out.push(7);
+            out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+        HeaderValue::Timestamp(t) => {
+            out.push(8);
+            out.extend_from_slice(&t.to_be_bytes());
+        }
+        HeaderValue::Uuid(u) => {
+            out.push(9);
+            out.extend_from_slice(u);
+        }
+    }
+}
+
+fn decode_headers(mut raw: &[u8]) -> io::Result<Vec<(String, HeaderValue)>> {
+    let mut headers = Vec::new();
+    while !raw.is_empty() {
+        let name_len = raw[0] as usize;
+        let name = take(&mut raw, 1 + name_len)?[1..].to_vec();
+        let name = String::from_utf8(name).map_err(|_| invalid("invalid header name"))?;
+        let value_type = take(&mut raw, 1)?[0];
+        let value = match value_type {
+            0 => HeaderValue::Bool(true),
+            1 => HeaderValue::Bool(false),
+            2 => HeaderValue::Byte(take(&mut raw, 1)?[0] as i8),
+            3 => HeaderValue::Int16(i16::from_be_bytes(array(take(&mut raw, 2)?))),
+            4 => HeaderValue::Int32(i32::from_be_bytes(array(take(&mut raw, 4)?))),
+            5 => HeaderValue::Int64(i64::from_be_bytes(array(take(&mut raw, 8)?))),
+            6 | 7 => {
+                let len = u16::from_be_bytes(array(take(&mut raw, 2)?)) as usize;
+                let bytes = take(&mut raw, len)?.to_vec();
+                if value_type == 6 {
+                    HeaderValue::Bytes(bytes)
+                } else {
+                    HeaderValue::String(
+                        String::from_utf8(bytes).map_err(|_| invalid("invalid header value"))?,
+                    )
+                }
+            }
+            8 => HeaderValue::Timestamp(i64::from_be_bytes(array(take(&mut raw, 8)?))),
+            9 => HeaderValue::Uuid(array(take(&mut raw, 16)?)),
+            _ => return Err(invalid("unknown header type")),
+        };
+        headers.push((name, value));
+    }
+    Ok(headers)
+}
+
+/// Splits off the first `len` bytes
+fn take<'a>(raw: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if raw.len() < len {
+        return Err(invalid("truncated headers"));
+    }
+    let (head, tail) = raw.split_at(len);
+    *raw = tail;
+    Ok(head)
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(bytes);
+    array
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(array(bytes))
+}
+
+/// CRC32 (IEEE 802.3) as required for both message checksums
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = Message::default()
+            .with_header("true", HeaderValue::Bool(true))
+            .with_header("false", HeaderValue::Bool(false))
+            .with_header("byte", HeaderValue::Byte(-1))
+            .with_header("short", HeaderValue::Int16(-300))
+            .with_header(":stream-id", HeaderValue::Int32(7))
+            .with_header("long", HeaderValue::Int64(1 << 40))
+            .with_header("bytes", HeaderValue::Bytes(vec![1, 2, 3]))
+            .with_header(
+                "operation",
+                HeaderValue::String("aws.greengrass#Op".to_owned()),
+            )
+            .with_header("time", HeaderValue::Timestamp(1_600_000_000_000))
+            .with_header("uuid", HeaderValue::Uuid([7; 16]))
+            .with_payload(br#"{"foo":"bar"}"#.to_vec());
+
+        let encoded = message.encode();
+        let decoded = Message::read_from(&mut encoded.as_slice()).unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.int32(":stream-id"), 7);
+        assert_eq!(decoded.string("operation"), Some("aws.greengrass#Op"));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut encoded = Message::default().with_payload(b"hello".to_vec()).encode();
+        let len = encoded.len();
+        encoded[len - 5] ^= 0xFF;
+        assert!(Message::read_from(&mut encoded.as_slice()).is_err());
+
+        encoded[0] ^= 0xFF;
+        assert!(Message::read_from(&mut encoded.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,612 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides clients for Greengrass v2 components, which talk to the Greengrass nucleus over its
+//! event stream IPC protocol instead of the v1 C SDK.
+//! This module requires the `v2` feature and is only available on unix.
+//!
+//! A component connects with [`IpcConnection::connect`], which uses the following environment variables
+//! set by the nucleus for every component:
+//! * `AWS_GG_NUCLEUS_DOMAIN_SOCKET_FILEPATH_FOR_COMPONENT` - the path of the IPC socket
+//! * `SVCUID` - the token used to authorize the connection
+//!
+//! The clients share a connection and mirror the method signatures of their v1 counterparts, returning
+//! [`GGResult`] and using serde for payloads, so the same business code can target either version.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aws_greengrass_core_rust::v2::IpcConnection;
+//! use aws_greengrass_core_rust::v2::pubsub::PubSubClient;
+//! use aws_greengrass_core_rust::v2::shadow::ShadowClient;
+//! use serde_json::{json, Value};
+//!
+//! let connection = IpcConnection::connect().expect("Could not connect to the nucleus");
+//! let pubsub = PubSubClient::new(connection.clone());
+//! pubsub.publish_json("my/topic", json!({"msg": "hello"})).unwrap();
+//!
+//! let shadow = ShadowClient::new(connection).get_thing_shadow::<Value>("my_thing").unwrap();
+//! println!("Shadow: {:?}", shadow);
+//! ```
+pub mod config;
+mod eventstream;
+pub mod pubsub;
+pub mod secret;
+pub mod shadow;
+
+use self::eventstream::{HeaderValue, Message};
+use crate::error::GGError;
+use crate::request::{ErrorResponse, GGRequestResponse, GGRequestStatus};
+use crate::GGResult;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use log::{debug, error};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SOCKET_PATH_ENV: &str = "AWS_GG_NUCLEUS_DOMAIN_SOCKET_FILEPATH_FOR_COMPONENT";
+const AUTH_TOKEN_ENV: &str = "SVCUID";
+
+const PROTOCOL_VERSION: &str = "0.1.0";
+const CONTENT_TYPE_JSON: &str = "application/json";
+const SERVICE_PREFIX: &str = "aws.greengrass#";
+/// How long to wait for the nucleus to respond to a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const HEADER_MESSAGE_TYPE: &str = ":message-type";
+const HEADER_MESSAGE_FLAGS: &str = ":message-flags";
+const HEADER_STREAM_ID: &str = ":stream-id";
+const HEADER_VERSION: &str = ":version";
+const HEADER_CONTENT_TYPE: &str = ":content-type";
+const HEADER_OPERATION: &str = "operation";
+const HEADER_SERVICE_MODEL_TYPE: &str = "service-model-type";
+
+const MESSAGE_TYPE_APPLICATION: i32 = 0;
+const MESSAGE_TYPE_APPLICATION_ERROR: i32 = 1;
+const MESSAGE_TYPE_PING: i32 = 2;
+const MESSAGE_TYPE_PING_RESPONSE: i32 = 3;
+const MESSAGE_TYPE_CONNECT: i32 = 4;
+const MESSAGE_TYPE_CONNECT_ACK: i32 = 5;
+
+const FLAG_CONNECTION_ACCEPTED: i32 = 1;
+const FLAG_TERMINATE_STREAM: i32 = 2;
+
+/// A connection to the IPC socket of the Greengrass nucleus.
+///
+/// The connection is cheap to clone and is closed once every clone, client and subscription using it is dropped.
+#[derive(Clone)]
+pub struct IpcConnection {
+    inner: Arc<Inner>,
+}
+
+/// Closes the socket when the last handle is dropped, which stops the reader thread
+struct Inner {
+    shared: Arc<Shared>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Ok(writer) = self.shared.writer.lock() {
+            let _ = writer.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// State shared with the reader thread
+struct Shared {
+    writer: Mutex<UnixStream>,
+    /// Senders for the messages of each open stream, by stream id
+    streams: Mutex<HashMap<i32, Sender<Message>>>,
+    next_stream_id: AtomicI32,
+}
+
+impl IpcConnection {
+    /// Connects to the nucleus using the socket path and token from the environment of the component
+    pub fn connect() -> GGResult<Self> {
+        let socket_path = env::var(SOCKET_PATH_ENV).map_err(|_| {
+            GGError::Unknown(format!(
+                "{} is not set, is this a Greengrass v2 component?",
+                SOCKET_PATH_ENV
+            ))
+        })?;
+        let auth_token = env::var(AUTH_TOKEN_ENV)
+            .map_err(|_| GGError::Unknown(format!("{} is not set", AUTH_TOKEN_ENV)))?;
+        Self::connect_to(socket_path, &auth_token)
+    }
+
+    /// Connects to the nucleus listening on the specified socket
+    pub fn connect_to<P: AsRef<Path>>(socket_path: P, auth_token: &str) -> GGResult<Self> {
+        let mut stream = UnixStream::connect(socket_path).map_err(GGError::from)?;
+        Message::default()
+            .with_header(
+                HEADER_MESSAGE_TYPE,
+                HeaderValue::Int32(MESSAGE_TYPE_CONNECT),
+            )
+            .with_header(HEADER_MESSAGE_FLAGS, HeaderValue::Int32(0))
+            .with_header(HEADER_STREAM_ID, HeaderValue::Int32(0))
+            .with_header(
+                HEADER_VERSION,
+                HeaderValue::String(PROTOCOL_VERSION.to_owned()),
+            )
+            .with_payload(json!({ "authToken": auth_token }).to_string().into_bytes())
+            .write_to(&mut stream)
+            .map_err(GGError::from)?;
+
+        let ack = Message::read_from(&mut stream).map_err(GGError::from)?;
+        if ack.int32(HEADER_MESSAGE_TYPE) != MESSAGE_TYPE_CONNECT_ACK
+            || ack.int32(HEADER_MESSAGE_FLAGS) & FLAG_CONNECTION_ACCEPTED == 0
+        {
+            return Err(GGError::Unauthorized(
+                "The nucleus did not accept the IPC connection".to_owned(),
+            ));
+        }
+
+        let reader = stream.try_clone().map_err(GGError::from)?;
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(stream),
+            streams: Mutex::new(HashMap::new()),
+            next_stream_id: AtomicI32::new(1),
+        });
+        let reader_shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("gg-ipc-reader".to_owned())
+            .spawn(move || read_messages(reader, reader_shared))
+            .map_err(GGError::from)?;
+
+        Ok(IpcConnection {
+            inner: Arc::new(Inner { shared }),
+        })
+    }
+
+    /// Sends a request for the operation and waits for its response
+    pub(crate) fn call(&self, operation: &str, request: &Value) -> GGResult<Value> {
+        let (stream_id, receiver) = self.open_stream();
+        let result = self
+            .send(stream_id, operation, request, 0)
+            .and_then(|_| receive(&receiver, RESPONSE_TIMEOUT))
+            .and_then(|message| into_result(&message));
+        self.close_stream(stream_id);
+        result
+    }
+
+    /// Sends a request for a streaming operation. Once the nucleus has responded, events
+    /// on the stream are decoded and delivered to the returned subscription.
+    pub(crate) fn subscribe<T>(
+        &self,
+        operation: &str,
+        request: &Value,
+        decode: fn(Value) -> GGResult<T>,
+    ) -> GGResult<Subscription<T>> {
+        let (stream_id, receiver) = self.open_stream();
+        let result = self
+            .send(stream_id, operation, request, 0)
+            .and_then(|_| receive(&receiver, RESPONSE_TIMEOUT))
+            .and_then(|message| into_result(&message));
+        match result {
+            Ok(_) => Ok(Subscription {
+                connection: self.clone(),
+                stream_id,
+                receiver,
+                decode,
+                closed: false,
+            }),
+            Err(e) => {
+                self.close_stream(stream_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn open_stream(&self) -> (i32, Receiver<Message>) {
+        let shared = &self.inner.shared;
+        let stream_id = shared.next_stream_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = unbounded();
+        shared.streams.lock().unwrap().insert(stream_id, sender);
+        (stream_id, receiver)
+    }
+
+    fn close_stream(&self, stream_id: i32) {
+        self.inner.shared.streams.lock().unwrap().remove(&stream_id);
+    }
+
+    fn send(&self, stream_id: i32, operation: &str, request: &Value, flags: i32) -> GGResult<()> {
+        let message = Message::default()
+            .with_header(
+                HEADER_MESSAGE_TYPE,
+                HeaderValue::Int32(MESSAGE_TYPE_APPLICATION),
+            )
+            .with_header(HEADER_MESSAGE_FLAGS, HeaderValue::Int32(flags))
+            .with_header(HEADER_STREAM_ID, HeaderValue::Int32(stream_id))
+            .with_header(
+                HEADER_OPERATION,
+                HeaderValue::String(format!("{}{}", SERVICE_PREFIX, operation)),
+            )
+            .with_header(
+                HEADER_SERVICE_MODEL_TYPE,
+                HeaderValue::String(format!("{}{}Request", SERVICE_PREFIX, operation)),
+            )
+            .with_header(
+                HEADER_CONTENT_TYPE,
+                HeaderValue::String(CONTENT_TYPE_JSON.to_owned()),
+            )
+            .with_payload(serde_json::to_vec(request).map_err(GGError::from)?);
+        self.inner.shared.write(&message)
+    }
+}
+
+impl Shared {
+    fn write(&self, message: &Message) -> GGResult<()> {
+        let mut writer = self.writer.lock().unwrap();
+        message.write_to(&mut *writer).map_err(GGError::from)
+    }
+}
+
+/// Dispatches messages to their streams until the connection is closed
+fn read_messages(mut reader: UnixStream, shared: Arc<Shared>) {
+    loop {
+        let message = match Message::read_from(&mut reader) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("IPC connection closed: {}", e);
+                break;
+            }
+        };
+
+        if message.int32(HEADER_MESSAGE_TYPE) == MESSAGE_TYPE_PING {
+            let pong = Message::default()
+                .with_header(
+                    HEADER_MESSAGE_TYPE,
+                    HeaderValue::Int32(MESSAGE_TYPE_PING_RESPONSE),
+                )
+                .with_header(HEADER_MESSAGE_FLAGS, HeaderValue::Int32(0))
+                .with_header(HEADER_STREAM_ID, HeaderValue::Int32(0));
+            if let Err(e) = shared.write(&pong) {
+                error!("Error responding to IPC ping: {}", e);
+            }
+            continue;
+        }
+
+        let stream_id = message.int32(HEADER_STREAM_ID);
+        let mut streams = shared.streams.lock().unwrap();
+        let terminated = message.int32(HEADER_MESSAGE_FLAGS) & FLAG_TERMINATE_STREAM != 0;
+        match streams.get(&stream_id) {
+            Some(sender) => {
+                let _ = sender.send(message);
+            }
+            None => debug!("Dropping IPC message for closed stream {}", stream_id),
+        }
+        if terminated {
+            streams.remove(&stream_id);
+        }
+    }
+    // Disconnects everything still waiting on the connection
+    shared.streams.lock().unwrap().clear();
+}
+
+fn receive(receiver: &Receiver<Message>, timeout: Duration) -> GGResult<Message> {
+    receiver.recv_timeout(timeout).map_err(|e| match e {
+        RecvTimeoutError::Timeout => GGError::from(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Timed out waiting for the nucleus to respond",
+        )),
+        RecvTimeoutError::Disconnected => GGError::from(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "IPC connection closed",
+        )),
+    })
+}
+
+/// Converts a message received on a stream into its JSON payload or the error it represents
+fn into_result(message: &Message) -> GGResult<Value> {
+    match message.int32(HEADER_MESSAGE_TYPE) {
+        MESSAGE_TYPE_APPLICATION if message.payload.is_empty() => Ok(Value::Null),
+        MESSAGE_TYPE_APPLICATION => serde_json::from_slice(&message.payload).map_err(GGError::from),
+        MESSAGE_TYPE_APPLICATION_ERROR => Err(service_error(message)),
+        message_type => Err(GGError::Unknown(format!(
+            "IPC protocol error (message type {}): {}",
+            message_type,
+            String::from_utf8_lossy(&message.payload)
+        ))),
+    }
+}
+
+/// The body of an error returned by a Greengrass IPC service
+#[derive(Deserialize, Default)]
+struct ServiceError {
+    #[serde(rename = "_message", alias = "message", default)]
+    message: String,
+    #[serde(rename = "_errorCode", default)]
+    error_code: Option<String>,
+}
+
+/// Maps a service error onto the same errors the v1 SDK returns, so that
+/// not found errors can be handled as an Option.
+fn service_error(message: &Message) -> GGError {
+    let body: ServiceError = serde_json::from_slice(&message.payload).unwrap_or_default();
+    let error_code = body.error_code.unwrap_or_else(|| {
+        message
+            .string(HEADER_SERVICE_MODEL_TYPE)
+            .unwrap_or_default()
+            .trim_start_matches(SERVICE_PREFIX)
+            .to_owned()
+    });
+    let code = match error_code.as_str() {
+        "UnauthorizedError" => return GGError::Unauthorized(body.message),
+        "InvalidArgumentsError" => 400,
+        "ResourceNotFoundError" => 404,
+        "ConflictError" => 409,
+        _ => 500,
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    GGError::ErrorResponse(GGRequestResponse {
+        request_status: GGRequestStatus::Handled,
+        error_response: Some(ErrorResponse {
+            code,
+            message: format!("{}: {}", error_code, body.message),
+            timestamp,
+        }),
+    })
+}
+
+/// Converts a not found error response into None
+pub(crate) fn not_found_as_none<T>(result: GGResult<T>) -> GGResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(GGError::ErrorResponse(GGRequestResponse {
+            error_response: Some(ErrorResponse { code: 404, .. }),
+            ..
+        })) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A stream of events from a subscription operation.
+///
+/// Iterating blocks until the next event arrives and ends when the nucleus closes the stream.
+/// Dropping the subscription closes the stream.
+pub struct Subscription<T> {
+    connection: IpcConnection,
+    stream_id: i32,
+    receiver: Receiver<Message>,
+    decode: fn(Value) -> GGResult<T>,
+    closed: bool,
+}
+
+impl<T> Subscription<T> {
+    /// Waits up to the timeout for the next event.
+    /// None if no event arrived in time or the stream has closed.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<GGResult<T>> {
+        if self.closed {
+            return None;
+        }
+        match self.receiver.recv_timeout(timeout) {
+            Ok(message) => self.event(message),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    fn event(&mut self, message: Message) -> Option<GGResult<T>> {
+        if message.int32(HEADER_MESSAGE_FLAGS) & FLAG_TERMINATE_STREAM != 0 {
+            self.closed = true;
+            // The nucleus closes streams with an empty message unless there was an error
+            if message.int32(HEADER_MESSAGE_TYPE) == MESSAGE_TYPE_APPLICATION {
+                return None;
+            }
+        }
+        Some(into_result(&message).and_then(self.decode))
+    }
+}
+
+impl<T> Iterator for Subscription<T> {
+    type Item = GGResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.closed {
+            return None;
+        }
+        match self.receiver.recv() {
+            Ok(message) => self.event(message),
+            Err(_) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.connection.close_stream(self.stream_id);
+        if !self.closed {
+            let terminate = Message::default()
+                .with_header(
+                    HEADER_MESSAGE_TYPE,
+                    HeaderValue::Int32(MESSAGE_TYPE_APPLICATION),
+                )
+                .with_header(
+                    HEADER_MESSAGE_FLAGS,
+                    HeaderValue::Int32(FLAG_TERMINATE_STREAM),
+                )
+                .with_header(HEADER_STREAM_ID, HeaderValue::Int32(self.stream_id));
+            if let Err(e) = self.connection.inner.shared.write(&terminate) {
+                debug!("Could not close IPC stream {}: {}", self.stream_id, e);
+            }
+        }
+    }
+}
+
+/// Serializes binary payloads as the base64 strings expected by the IPC services
+pub(crate) mod blob {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(&encoded).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc::{channel, Receiver};
+
+    pub(crate) const TEST_TOKEN: &str = "test-svcuid";
+
+    /// Builds the messages the stand-in sends in reply to each request
+    pub(crate) type Responder = dyn Fn(&Message) -> Vec<Message> + Send;
+
+    /// Starts a stand-in for the nucleus IPC socket that records the requests it receives.
+    /// Connections are only accepted with [`TEST_TOKEN`].
+    pub(crate) fn stand_in(responder: Box<Responder>) -> (PathBuf, Receiver<Message>) {
+        let path = env::temp_dir().join(format!("gg-ipc-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).expect("Could not bind stand-in socket");
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let connect = match Message::read_from(&mut stream) {
+                    Ok(connect) => connect,
+                    Err(_) => continue,
+                };
+                let accepted = String::from_utf8_lossy(&connect.payload).contains(TEST_TOKEN);
+                let flags = if accepted {
+                    FLAG_CONNECTION_ACCEPTED
+                } else {
+                    0
+                };
+                let _ = Message::default()
+                    .with_header(
+                        HEADER_MESSAGE_TYPE,
+                        HeaderValue::Int32(MESSAGE_TYPE_CONNECT_ACK),
+                    )
+                    .with_header(HEADER_MESSAGE_FLAGS, HeaderValue::Int32(flags))
+                    .with_header(HEADER_STREAM_ID, HeaderValue::Int32(0))
+                    .write_to(&mut stream);
+                if !accepted {
+                    continue;
+                }
+
+                while let Ok(request) = Message::read_from(&mut stream) {
+                    let stream_id = request.int32(HEADER_STREAM_ID);
+                    for reply in responder(&request) {
+                        let _ = reply
+                            .with_header(HEADER_STREAM_ID, HeaderValue::Int32(stream_id))
+                            .write_to(&mut stream);
+                    }
+                    let _ = sender.send(request);
+                }
+            }
+        });
+        (path, receiver)
+    }
+
+    pub(crate) fn connect(responder: Box<Responder>) -> (IpcConnection, Receiver<Message>) {
+        let (path, requests) = stand_in(responder);
+        let connection = IpcConnection::connect_to(&path, TEST_TOKEN).unwrap();
+        (connection, requests)
+    }
+
+    /// A successful response, or an event on a stream
+    pub(crate) fn reply(payload: Value) -> Message {
+        Message::default()
+            .with_header(
+                HEADER_MESSAGE_TYPE,
+                HeaderValue::Int32(MESSAGE_TYPE_APPLICATION),
+            )
+            .with_header(HEADER_MESSAGE_FLAGS, HeaderValue::Int32(0))
+            .with_payload(payload.to_string().into_bytes())
+    }
+
+    pub(crate) fn error_reply(error_code: &str, message: &str) -> Message {
+        Message::default()
+            .with_header(
+                HEADER_MESSAGE_TYPE,
+                HeaderValue::Int32(MESSAGE_TYPE_APPLICATION_ERROR),
+            )
+            .with_header(
+                HEADER_MESSAGE_FLAGS,
+                HeaderValue::Int32(FLAG_TERMINATE_STREAM),
+            )
+            .with_payload(
+                json!({ "_message": message, "_errorCode": error_code })
+                    .to_string()
+                    .into_bytes(),
+            )
+    }
+
+    pub(crate) fn operation(request: &Message) -> &str {
+        request
+            .string(HEADER_OPERATION)
+            .unwrap_or_default()
+            .trim_start_matches(SERVICE_PREFIX)
+    }
+
+    pub(crate) fn payload(request: &Message) -> Value {
+        serde_json::from_slice(&request.payload).unwrap()
+    }
+
+    #[test]
+    fn test_connect_rejected() {
+        let (path, _) = stand_in(Box::new(|_| vec![]));
+        match IpcConnection::connect_to(&path, "wrong token") {
+            Err(GGError::Unauthorized(_)) => (),
+            _ => panic!("Expected the connection to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let (connection, requests) = connect(Box::new(|request| match operation(request) {
+            "Missing" => vec![error_reply("ResourceNotFoundError", "not here")],
+            "Denied" => vec![error_reply("UnauthorizedError", "no access")],
+            _ => vec![reply(json!({}))],
+        }));
+
+        assert_eq!(connection.call("Ok", &json!({})).unwrap(), json!({}));
+        let request = requests.recv().unwrap();
+        assert_eq!(
+            request.string(HEADER_SERVICE_MODEL_TYPE),
+            Some("aws.greengrass#OkRequest")
+        );
+        assert_eq!(request.int32(HEADER_STREAM_ID), 1);
+
+        assert!(not_found_as_none(connection.call("Missing", &json!({})))
+            .unwrap()
+            .is_none());
+        match connection.call("Denied", &json!({})) {
+            Err(GGError::Unauthorized(msg)) => assert_eq!(msg, "no access"),
+            _ => panic!("Expected an unauthorized error"),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides publishing and subscribing to local topics and to AWS IoT Core MQTT topics
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aws_greengrass_core_rust::v2::IpcConnection;
+//! use aws_greengrass_core_rust::v2::pubsub::{IotCoreClient, Qos};
+//!
+//! let connection = IpcConnection::connect().unwrap();
+//! let client = IotCoreClient::new(connection).with_qos(Qos::AtLeastOnce);
+//! for message in client.subscribe("commands/#").unwrap() {
+//!     match message {
+//!         Ok(message) => {
+//!             client.publish("responses", &message.payload).unwrap();
+//!         }
+//!         Err(e) => eprintln!("Error receiving message: {}", e),
+//!     }
+//! }
+//! ```
+use crate::error::GGError;
+use crate::v2::{blob, IpcConnection, Subscription};
+use crate::GGResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A message received from a subscription
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionMessage {
+    /// The topic the message was published to
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+impl SubscriptionMessage {
+    /// Deserializes the payload as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> GGResult<T> {
+        serde_json::from_slice(&self.payload).map_err(GGError::from)
+    }
+}
+
+/// Publishes and subscribes to topics on the local Greengrass pub/sub bus
+#[derive(Clone)]
+pub struct PubSubClient {
+    connection: IpcConnection,
+}
+
+impl PubSubClient {
+    pub fn new(connection: IpcConnection) -> Self {
+        PubSubClient { connection }
+    }
+
+    /// Publishes anything that implements AsRef<[u8]> as a binary message
+    pub fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        let request = json!({
+            "topic": topic,
+            "publishMessage": {
+                "binaryMessage": { "message": base64::encode(message.as_ref()) }
+            }
+        });
+        self.connection.call("PublishToTopic", &request).map(|_| ())
+    }
+
+    /// Publishes anything that is a serializable serde object as a JSON message
+    pub fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let message = serde_json::to_value(&message).map_err(GGError::from)?;
+        let request = json!({
+            "topic": topic,
+            "publishMessage": {
+                "jsonMessage": { "message": message }
+            }
+        });
+        self.connection.call("PublishToTopic", &request).map(|_| ())
+    }
+
+    /// Subscribes to a local topic. JSON messages are delivered with their serialized JSON as the payload.
+    pub fn subscribe(&self, topic: &str) -> GGResult<Subscription<SubscriptionMessage>> {
+        self.connection.subscribe(
+            "SubscribeToTopic",
+            &json!({ "topic": topic }),
+            decode_local_message,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LocalEvent {
+    json_message: Option<LocalJsonMessage>,
+    binary_message: Option<LocalBinaryMessage>,
+}
+
+#[derive(Deserialize)]
+struct LocalJsonMessage {
+    message: Value,
+    context: Option<MessageContext>,
+}
+
+#[derive(Deserialize)]
+struct LocalBinaryMessage {
+    #[serde(with = "blob")]
+    message: Vec<u8>,
+    context: Option<MessageContext>,
+}
+
+#[derive(Deserialize)]
+struct MessageContext {
+    topic: String,
+}
+
+fn decode_local_message(event: Value) -> GGResult<SubscriptionMessage> {
+    let event: LocalEvent = serde_json::from_value(event).map_err(GGError::from)?;
+    let (payload, context) = match (event.json_message, event.binary_message) {
+        (Some(json), _) => (
+            serde_json::to_vec(&json.message).map_err(GGError::from)?,
+            json.context,
+        ),
+        (None, Some(binary)) => (binary.message, binary.context),
+        (None, None) => (vec![], None),
+    };
+    Ok(SubscriptionMessage {
+        topic: context.map(|c| c.topic).unwrap_or_default(),
+        payload,
+    })
+}
+
+/// The MQTT quality of service used with AWS IoT Core
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Qos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+}
+
+impl Qos {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::AtMostOnce => "0",
+            Self::AtLeastOnce => "1",
+        }
+    }
+}
+
+/// Publishes and subscribes to AWS IoT Core MQTT topics through the nucleus
+#[derive(Clone)]
+pub struct IotCoreClient {
+    connection: IpcConnection,
+    /// The quality of service used when publishing and subscribing
+    pub qos: Qos,
+}
+
+impl IotCoreClient {
+    pub fn new(connection: IpcConnection) -> Self {
+        IotCoreClient {
+            connection,
+            qos: Qos::default(),
+        }
+    }
+
+    /// Define the quality of service used by this client
+    pub fn with_qos(self, qos: Qos) -> Self {
+        IotCoreClient { qos, ..self }
+    }
+
+    /// Publishes anything that implements AsRef<[u8]> to an IoT Core topic
+    pub fn publish<T: AsRef<[u8]>>(&self, topic: &str, message: T) -> GGResult<()> {
+        let request = json!({
+            "topicName": topic,
+            "qos": self.qos.as_str(),
+            "payload": base64::encode(message.as_ref()),
+        });
+        self.connection
+            .call("PublishToIoTCore", &request)
+            .map(|_| ())
+    }
+
+    /// Publish anything that is a serializable serde object
+    pub fn publish_json<T: Serialize>(&self, topic: &str, message: T) -> GGResult<()> {
+        let bytes = serde_json::to_vec(&message).map_err(GGError::from)?;
+        self.publish(topic, &bytes)
+    }
+
+    /// Subscribes to an IoT Core topic filter
+    pub fn subscribe(&self, topic_filter: &str) -> GGResult<Subscription<SubscriptionMessage>> {
+        let request = json!({ "topicName": topic_filter, "qos": self.qos.as_str() });
+        self.connection
+            .subscribe("SubscribeToIoTCore", &request, decode_iot_core_message)
+    }
+}
+
+#[derive(Deserialize)]
+struct IotCoreEvent {
+    message: IotCoreMessage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IotCoreMessage {
+    topic_name: String,
+    #[serde(with = "blob")]
+    payload: Vec<u8>,
+}
+
+fn decode_iot_core_message(event: Value) -> GGResult<SubscriptionMessage> {
+    let event: IotCoreEvent = serde_json::from_value(event).map_err(GGError::from)?;
+    Ok(SubscriptionMessage {
+        topic: event.message.topic_name,
+        payload: event.message.payload,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::v2::eventstream::Message;
+    use crate::v2::test::*;
+    use crate::v2::FLAG_TERMINATE_STREAM;
+    use crate::v2::HEADER_MESSAGE_FLAGS;
+    use std::time::Duration;
+
+    fn responder(request: &Message) -> Vec<Message> {
+        match operation(request) {
+            "SubscribeToTopic" => vec![
+                reply(json!({})),
+                reply(json!({
+                    "jsonMessage": { "message": {"foo": "bar"}, "context": { "topic": "local/topic" } }
+                })),
+                reply(json!({
+                    "binaryMessage": { "message": base64::encode(b"raw"), "context": { "topic": "local/topic" } }
+                })),
+            ],
+            "SubscribeToIoTCore" => vec![
+                reply(json!({})),
+                reply(json!({
+                    "message": { "topicName": "cloud/topic", "payload": base64::encode(b"hello") }
+                })),
+            ],
+            _ => vec![reply(json!({}))],
+        }
+    }
+
+    #[test]
+    fn test_publish() {
+        let (connection, requests) = connect(Box::new(responder));
+        let client = PubSubClient::new(connection.clone());
+        client.publish("local/topic", b"raw").unwrap();
+        client
+            .publish_json("local/topic", json!({"foo": "bar"}))
+            .unwrap();
+        IotCoreClient::new(connection)
+            .with_qos(Qos::AtLeastOnce)
+            .publish("cloud/topic", "hello")
+            .unwrap();
+
+        let request = requests.recv().unwrap();
+        assert_eq!(operation(&request), "PublishToTopic");
+        assert_eq!(
+            payload(&request),
+            json!({"topic": "local/topic", "publishMessage": {"binaryMessage": {"message": "cmF3"}}})
+        );
+        let request = requests.recv().unwrap();
+        assert_eq!(
+            payload(&request)["publishMessage"]["jsonMessage"]["message"],
+            json!({"foo": "bar"})
+        );
+        let request = requests.recv().unwrap();
+        assert_eq!(operation(&request), "PublishToIoTCore");
+        assert_eq!(
+            payload(&request),
+            json!({"topicName": "cloud/topic", "qos": "1", "payload": "aGVsbG8="})
+        );
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let (connection, requests) = connect(Box::new(responder));
+        let mut local = PubSubClient::new(connection.clone())
+            .subscribe("local/topic")
+            .unwrap();
+        let message = local.next().unwrap().unwrap();
+        assert_eq!(message.topic, "local/topic");
+        assert_eq!(message.json::<Value>().unwrap(), json!({"foo": "bar"}));
+        assert_eq!(local.next().unwrap().unwrap().payload, b"raw");
+        assert!(local.recv_timeout(Duration::from_millis(10)).is_none());
+
+        let mut cloud = IotCoreClient::new(connection).subscribe("cloud/#").unwrap();
+        let message = cloud.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(message.topic, "cloud/topic");
+        assert_eq!(message.payload, b"hello");
+
+        // Dropping a subscription closes its stream
+        drop(local);
+        let requests: Vec<Message> = requests.iter().take(3).collect();
+        assert_eq!(operation(&requests[0]), "SubscribeToTopic");
+        assert_eq!(operation(&requests[1]), "SubscribeToIoTCore");
+        assert_eq!(
+            requests[2].int32(HEADER_MESSAGE_FLAGS),
+            FLAG_TERMINATE_STREAM
+        );
+    }
+}
@@ -0,0 +1,176 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides the ability to acquire secrets deployed to the core by the secret manager component
+use crate::error::GGError;
+use crate::secret::Secret;
+use crate::v2::{not_found_as_none, IpcConnection};
+use crate::GGResult;
+use serde::{Deserialize, Serialize};
+
+/// Handles requests for secrets from the secret manager component
+///
+/// ```no_run
+/// use aws_greengrass_core_rust::v2::IpcConnection;
+/// use aws_greengrass_core_rust::v2::secret::SecretClient;
+///
+/// let secret_result = SecretClient::new(IpcConnection::connect().unwrap())
+///     .for_secret_id("mysecret")
+///     .with_secret_version_stage(Some("AWSCURRENT".to_owned()))
+///     .request();
+/// ```
+#[derive(Clone)]
+pub struct SecretClient {
+    connection: IpcConnection,
+}
+
+impl SecretClient {
+    pub fn new(connection: IpcConnection) -> Self {
+        SecretClient { connection }
+    }
+
+    /// Creates a new SecretRequestBuilder using the specified secret_id
+    ///
+    /// * `secret_id` - The full arn or simple name of the secret
+    pub fn for_secret_id(&self, secret_id: &str) -> SecretRequestBuilder {
+        SecretRequestBuilder {
+            connection: self.connection.clone(),
+            secret_id: secret_id.to_owned(),
+            secret_version: None,
+            secret_version_stage: None,
+        }
+    }
+}
+
+/// Used to construct a request for a secret
+#[derive(Clone)]
+pub struct SecretRequestBuilder {
+    connection: IpcConnection,
+    pub secret_id: String,
+    pub secret_version: Option<String>,
+    pub secret_version_stage: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetSecretValueRequest<'a> {
+    secret_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_stage: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetSecretValueResponse {
+    secret_id: String,
+    version_id: String,
+    #[serde(default)]
+    version_stage: Vec<String>,
+    secret_value: SecretValue,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretValue {
+    secret_string: Option<String>,
+    secret_binary: Option<String>,
+}
+
+impl SecretRequestBuilder {
+    /// Optional Secret version
+    pub fn with_secret_version(self, secret_version: Option<String>) -> Self {
+        SecretRequestBuilder {
+            secret_version,
+            ..self
+        }
+    }
+
+    /// Optional secret stage
+    pub fn with_secret_version_stage(self, secret_version_stage: Option<String>) -> Self {
+        SecretRequestBuilder {
+            secret_version_stage,
+            ..self
+        }
+    }
+
+    /// Executes the request and returns the secret.
+    /// The nucleus only returns the secret id, so it is used as both the arn and name of the secret.
+    pub fn request(&self) -> GGResult<Option<Secret>> {
+        let request = GetSecretValueRequest {
+            secret_id: &self.secret_id,
+            version_id: self.secret_version.as_deref(),
+            version_stage: self.secret_version_stage.as_deref(),
+        };
+        let request = serde_json::to_value(&request).map_err(GGError::from)?;
+        let response = match not_found_as_none(self.connection.call("GetSecretValue", &request))? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+        let response: GetSecretValueResponse =
+            serde_json::from_value(response).map_err(GGError::from)?;
+        let secret_binary = match response.secret_value.secret_binary {
+            Some(encoded) => Some(
+                base64::decode(&encoded)
+                    .map_err(|e| GGError::InvalidString(format!("Invalid secret binary: {}", e)))?,
+            ),
+            None => None,
+        };
+        Ok(Some(Secret {
+            arn: response.secret_id.clone(),
+            name: response.secret_id,
+            version_id: response.version_id,
+            secret_binary,
+            secret_string: response.secret_value.secret_string,
+            version_stages: response.version_stage,
+            created_date: 0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::v2::test::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request() {
+        let (connection, requests) = connect(Box::new(|request| {
+            match payload(request)["secretId"].as_str() {
+                Some("missing") => vec![error_reply("ResourceNotFoundError", "No secret")],
+                _ => vec![reply(json!({
+                    "secretId": "mysecret",
+                    "versionId": "v1",
+                    "versionStage": ["AWSCURRENT"],
+                    "secretValue": { "secretString": "hunter2" }
+                }))],
+            }
+        }));
+        let client = SecretClient::new(connection);
+
+        let secret = client
+            .for_secret_id("mysecret")
+            .with_secret_version_stage(Some("AWSCURRENT".to_owned()))
+            .request()
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.secret_string, Some("hunter2".to_owned()));
+        assert_eq!(secret.version_id, "v1");
+        assert_eq!(secret.version_stages, vec!["AWSCURRENT"]);
+        assert!(client.for_secret_id("missing").request().unwrap().is_none());
+
+        let request = requests.recv().unwrap();
+        assert_eq!(operation(&request), "GetSecretValue");
+        assert_eq!(
+            payload(&request),
+            json!({"secretId": "mysecret", "versionStage": "AWSCURRENT"})
+        );
+    }
+}
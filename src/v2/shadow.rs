@@ -0,0 +1,154 @@
+/*
+ * Copyright 2020-present, Nike, Inc.
+ * All rights reserved.
+ *
+ * This source code is licensed under the Apache-2.0 license found in
+ * the LICENSE file in the root of this source tree.
+ */
+
+//! Provides the ability to interact with a Thing's shadow documents through the nucleus shadow manager
+use crate::error::GGError;
+use crate::v2::{blob, not_found_as_none, IpcConnection};
+use crate::GGResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Provides the ability to get, update and delete shadow documents.
+/// The classic shadow is used unless a shadow name is specified.
+///
+/// ```no_run
+/// use aws_greengrass_core_rust::v2::IpcConnection;
+/// use aws_greengrass_core_rust::v2::shadow::ShadowClient;
+/// use serde_json::Value;
+///
+/// let client = ShadowClient::new(IpcConnection::connect().unwrap())
+///     .with_shadow_name(Some("config".to_owned()));
+/// if let Ok(maybe_json) = client.get_thing_shadow::<Value>("my_thing") {
+///     println!("Retrieved: {:?}", maybe_json);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ShadowClient {
+    connection: IpcConnection,
+    /// The named shadow to use instead of the classic shadow
+    pub shadow_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ShadowResponse {
+    #[serde(with = "blob")]
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateShadowRequest<'a> {
+    thing_name: &'a str,
+    shadow_name: &'a str,
+    #[serde(with = "blob")]
+    payload: Vec<u8>,
+}
+
+impl ShadowClient {
+    pub fn new(connection: IpcConnection) -> Self {
+        ShadowClient {
+            connection,
+            shadow_name: None,
+        }
+    }
+
+    /// Use a named shadow
+    pub fn with_shadow_name(self, shadow_name: Option<String>) -> Self {
+        ShadowClient {
+            shadow_name,
+            ..self
+        }
+    }
+
+    fn shadow_name(&self) -> &str {
+        self.shadow_name.as_deref().unwrap_or_default()
+    }
+
+    /// Get thing shadow for thing name. None if the shadow does not exist.
+    pub fn get_thing_shadow<T: DeserializeOwned>(&self, thing_name: &str) -> GGResult<Option<T>> {
+        let request = json!({ "thingName": thing_name, "shadowName": self.shadow_name() });
+        match not_found_as_none(self.connection.call("GetThingShadow", &request))? {
+            Some(response) => {
+                let response: ShadowResponse =
+                    serde_json::from_value(response).map_err(GGError::from)?;
+                let doc = serde_json::from_slice(&response.payload).map_err(GGError::from)?;
+                Ok(Some(doc))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Updates a shadow thing with the specified document.
+    pub fn update_thing_shadow<T: Serialize>(&self, thing_name: &str, doc: &T) -> GGResult<()> {
+        let request = UpdateShadowRequest {
+            thing_name,
+            shadow_name: self.shadow_name(),
+            payload: serde_json::to_vec(doc).map_err(GGError::from)?,
+        };
+        let request = serde_json::to_value(&request).map_err(GGError::from)?;
+        self.connection
+            .call("UpdateThingShadow", &request)
+            .map(|_| ())
+    }
+
+    /// Deletes thing shadow for thing name.
+    pub fn delete_thing_shadow(&self, thing_name: &str) -> GGResult<()> {
+        let request = json!({ "thingName": thing_name, "shadowName": self.shadow_name() });
+        self.connection
+            .call("DeleteThingShadow", &request)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::v2::test::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_shadow() {
+        let (connection, requests) = connect(Box::new(|request| {
+            let thing_name = payload(request)["thingName"].clone();
+            match (operation(request), thing_name.as_str()) {
+                ("GetThingShadow", Some("missing")) => {
+                    vec![error_reply("ResourceNotFoundError", "No shadow exists")]
+                }
+                ("GetThingShadow", _) => vec![reply(
+                    json!({ "payload": base64::encode(br#"{"state":{"reported":{"on":true}}}"#) }),
+                )],
+                _ => vec![reply(json!({ "payload": "" }))],
+            }
+        }));
+        let client = ShadowClient::new(connection).with_shadow_name(Some("lights".to_owned()));
+
+        let shadow = client.get_thing_shadow::<Value>("my_thing").unwrap();
+        assert_eq!(shadow, Some(json!({"state": {"reported": {"on": true}}})));
+        assert!(client
+            .get_thing_shadow::<Value>("missing")
+            .unwrap()
+            .is_none());
+        client
+            .update_thing_shadow("my_thing", &json!({"state": {"desired": {"on": false}}}))
+            .unwrap();
+        client.delete_thing_shadow("my_thing").unwrap();
+
+        let request = requests.recv().unwrap();
+        assert_eq!(
+            payload(&request),
+            json!({"thingName": "my_thing", "shadowName": "lights"})
+        );
+        requests.recv().unwrap();
+        let request = requests.recv().unwrap();
+        assert_eq!(operation(&request), "UpdateThingShadow");
+        let doc = base64::decode(payload(&request)["payload"].as_str().unwrap()).unwrap();
+        assert_eq!(doc, br#"{"state":{"desired":{"on":false}}}"#.to_vec());
+        assert_eq!(operation(&requests.recv().unwrap()), "DeleteThingShadow");
+    }
+}